@@ -4,6 +4,9 @@ export interface Note {
     id: string;
     title: string;
     body: string;
+    version: number;
+    created_at: string;
+    updated_at: string;
 }
 
 export interface NewNote {
@@ -38,12 +41,58 @@ export const updateNote = (note: Note): Promise<Note> => {
     }).then((response) => response.json());
 }
 
-export const listAllNotes = (): Promise<Note[]> => {
-    return fetch(`${API_URL}/notes`, {
+export interface Page<T> {
+    items: T[];
+    cursor: string | null;
+}
+
+export type NoteSort = 'created_at' | 'updated_at';
+
+export type SortOrder = 'asc' | 'desc';
+
+export const listNotes = (
+    cursor?: string,
+    limit?: number,
+    sort?: NoteSort,
+    order?: SortOrder
+): Promise<Page<Note>> => {
+    const params = new URLSearchParams();
+
+    if (cursor) {
+        params.set('cursor', cursor);
+    }
+
+    if (limit) {
+        params.set('limit', limit.toString());
+    }
+
+    if (sort) {
+        params.set('sort', sort);
+    }
+
+    if (order) {
+        params.set('order', order);
+    }
+
+    return fetch(`${API_URL}/notes?${params}`, {
         method: 'GET'
     }).then((response) => response.json());
 }
 
+export const listAllNotes = async (sort?: NoteSort, order?: SortOrder): Promise<Note[]> => {
+    const notes: Note[] = [];
+    let cursor: string | undefined;
+
+    do {
+        const page = await listNotes(cursor, undefined, sort, order);
+
+        notes.push(...page.items);
+        cursor = page.cursor ?? undefined;
+    } while (cursor);
+
+    return notes;
+}
+
 export const getNote = (id: string): Promise<Note> => {
     return fetch(`${API_URL}/notes/${id}`, {
         method: 'GET'
@@ -0,0 +1,594 @@
+use std::collections::HashMap;
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{
+    Attribute, Data, DeriveInput, Fields, GenericArgument, Ident, LitStr, PathArguments, Type,
+    Visibility,
+};
+
+use crate::template::KeyTemplate;
+
+struct EntityAttributes {
+    pk: KeyTemplate,
+    sk: Option<KeyTemplate>,
+    type_name: Option<LitStr>,
+}
+
+struct GsiAttributes {
+    name: LitStr,
+    pk: KeyTemplate,
+    sk: Option<KeyTemplate>,
+}
+
+/// Fields marked with `#[entity(...)]`
+#[derive(Default)]
+struct FieldAttributes {
+    version: Option<Ident>,
+    ttl: Option<Ident>,
+    created_at: Option<Ident>,
+    updated_at: Option<Ident>,
+}
+
+impl GsiAttributes {
+    fn pk_field(&self) -> Ident {
+        format_ident!("{}_pk", self.name.value(), span = self.name.span())
+    }
+
+    fn sk_field(&self) -> Ident {
+        format_ident!("{}_sk", self.name.value(), span = self.name.span())
+    }
+}
+
+pub fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
+    let field_types = named_field_types(&input)?;
+    let entity_attributes = parse_entity_attributes(&input)?;
+    let gsi_attributes = parse_gsi_attributes(&input.attrs)?;
+    let field_attributes = parse_field_attributes(&input)?;
+
+    let templates = std::iter::once(&entity_attributes.pk)
+        .chain(&entity_attributes.sk)
+        .chain(
+            gsi_attributes
+                .iter()
+                .flat_map(|gsi| std::iter::once(&gsi.pk).chain(&gsi.sk)),
+        );
+
+    for template in templates {
+        if let Some(field) = template
+            .fields()
+            .iter()
+            .find(|field| !field_types.contains_key(*field))
+        {
+            return Err(syn::Error::new(
+                template.literal().span(),
+                format!("`{}` has no field named `{}`", input.ident, field),
+            ));
+        }
+    }
+
+    let entity = &input.ident;
+    let vis = &input.vis;
+    let prefix = type_prefix(entity);
+    let primary_key = format_ident!("{}PrimaryKey", prefix);
+    let partition_key = format_ident!("{}PartitionKey", prefix);
+    let index_fields = format_ident!("{}IndexFields", prefix);
+
+    let pk = &entity_attributes.pk;
+    let primary_key_templates: Vec<(Ident, &KeyTemplate)> =
+        std::iter::once((format_ident!("pk"), pk))
+            .chain(
+                entity_attributes
+                    .sk
+                    .iter()
+                    .map(|sk| (format_ident!("sk"), sk)),
+            )
+            .collect();
+
+    let primary_key_struct = key_struct(
+        vis,
+        &primary_key,
+        &format!("Primary key of [`{}`].", entity),
+        &primary_key_templates,
+        "find_by",
+        None,
+        &field_types,
+    );
+
+    // A table without sort key has no partitions to query beyond the primary key itself
+    let partition_key_struct = match entity_attributes.sk {
+        Some(_) => key_struct(
+            vis,
+            &partition_key,
+            &format!(
+                "Partition of [`{}`] items, used to query all of them.",
+                entity
+            ),
+            &[(format_ident!("pk"), pk)],
+            "find_all_by",
+            None,
+            &field_types,
+        ),
+        None => quote!(),
+    };
+
+    let gsi_structs = gsi_attributes.iter().map(|gsi| {
+        key_struct(
+            vis,
+            &format_ident!("{}{}Index", prefix, upper_camel_case(&gsi.name.value())),
+            &format!(
+                "Partition of the `{}` index of [`{}`].",
+                gsi.name.value(),
+                entity
+            ),
+            &[(gsi.pk_field(), &gsi.pk)],
+            "find_all_by",
+            Some(&gsi.name),
+            &field_types,
+        )
+    });
+
+    let index_field_names: Vec<Ident> = gsi_attributes
+        .iter()
+        .flat_map(|gsi| {
+            std::iter::once(gsi.pk_field()).chain(gsi.sk.as_ref().map(|_| gsi.sk_field()))
+        })
+        .collect();
+    let index_field_values = gsi_attributes.iter().flat_map(|gsi| {
+        std::iter::once(gsi.pk.expand_from_self())
+            .chain(gsi.sk.as_ref().map(KeyTemplate::expand_from_self))
+    });
+    let index_fields_doc = format!("Secondary index attributes of [`{}`].", entity);
+
+    let primary_key_values = primary_key_templates
+        .iter()
+        .map(|(_, template)| template.expand_from_self());
+    let primary_key_names = primary_key_templates.iter().map(|(name, _)| name);
+
+    let type_name = entity_attributes
+        .type_name
+        .clone()
+        .unwrap_or_else(|| LitStr::new(&prefix, entity.span()));
+    let has_sort_key = entity_attributes.sk.is_some();
+    let index_schemas = gsi_attributes.iter().map(|gsi| {
+        let name = &gsi.name;
+        let has_sort_key = gsi.sk.is_some();
+
+        quote! {
+            ::orm::prelude::IndexSchema {
+                name: #name,
+                sort_key: #has_sort_key,
+            }
+        }
+    });
+
+    let (primary_key_constructor, key_arguments) = constructor(&primary_key_templates, "find_by");
+    let key_argument_types = key_arguments.iter().map(|argument| &field_types[*argument]);
+    let field_schemas = named_fields(&input)?.into_iter().filter_map(|(name, ty)| {
+        let field_type = field_type(ty)?;
+        let key = key_arguments.contains(&name);
+        let generated = key && is_uuid(ty);
+        let name = LitStr::new(&name.to_string(), name.span());
+
+        Some(quote! {
+            ::orm::prelude::FieldSchema {
+                name: #name,
+                field_type: #field_type,
+                key: #key,
+                generated: #generated,
+            }
+        })
+    });
+
+    let version_methods = field_attributes.version.map(|version| {
+        let attribute = LitStr::new(&version.to_string(), version.span());
+
+        quote! {
+            fn get_version_attribute() -> ::std::option::Option<&'static str> {
+                ::std::option::Option::Some(#attribute)
+            }
+
+            fn get_version(&self) -> u64 {
+                self.#version
+            }
+
+            fn set_version(&mut self, version: u64) {
+                self.#version = version;
+            }
+        }
+    });
+
+    let ttl_method = field_attributes.ttl.map(|ttl| {
+        let attribute = LitStr::new(&ttl.to_string(), ttl.span());
+
+        quote! {
+            fn get_ttl_attribute() -> ::std::option::Option<&'static str> {
+                ::std::option::Option::Some(#attribute)
+            }
+        }
+    });
+
+    let created_at_methods = field_attributes.created_at.map(|created_at| {
+        let attribute = LitStr::new(&created_at.to_string(), created_at.span());
+
+        quote! {
+            fn get_created_at_attribute() -> ::std::option::Option<&'static str> {
+                ::std::option::Option::Some(#attribute)
+            }
+
+            fn get_created_at(
+                &self,
+            ) -> ::std::option::Option<::chrono::DateTime<::chrono::Utc>> {
+                ::std::option::Option::Some(self.#created_at)
+            }
+
+            fn set_created_at(&mut self, created_at: ::chrono::DateTime<::chrono::Utc>) {
+                self.#created_at = created_at;
+            }
+        }
+    });
+
+    let updated_at_methods = field_attributes.updated_at.map(|updated_at| {
+        let attribute = LitStr::new(&updated_at.to_string(), updated_at.span());
+
+        quote! {
+            fn get_updated_at_attribute() -> ::std::option::Option<&'static str> {
+                ::std::option::Option::Some(#attribute)
+            }
+
+            fn set_updated_at(&mut self, updated_at: ::chrono::DateTime<::chrono::Utc>) {
+                self.#updated_at = updated_at;
+            }
+        }
+    });
+
+    Ok(quote! {
+        #primary_key_struct
+
+        #partition_key_struct
+
+        #(#gsi_structs)*
+
+        #[doc = #index_fields_doc]
+        #[derive(Debug, Clone, ::serde::Serialize)]
+        #vis struct #index_fields {
+            #(pub #index_field_names: ::std::string::String,)*
+        }
+
+        impl ::orm::prelude::Entity for #entity {
+            type PrimaryKey = #primary_key;
+            type IndexFields = #index_fields;
+
+            fn get_primary_key(&self) -> Self::PrimaryKey {
+                #primary_key {
+                    #(#primary_key_names: #primary_key_values,)*
+                }
+            }
+
+            fn get_index_fields(&self) -> Self::IndexFields {
+                #index_fields {
+                    #(#index_field_names: #index_field_values,)*
+                }
+            }
+
+            fn get_entity_type() -> ::std::option::Option<&'static str> {
+                ::std::option::Option::Some(#type_name)
+            }
+
+            fn get_key_schema() -> ::orm::prelude::KeySchema {
+                ::orm::prelude::KeySchema {
+                    sort_key: #has_sort_key,
+                    indexes: ::std::vec![#(#index_schemas),*],
+                }
+            }
+
+            fn get_fields() -> ::std::vec::Vec<::orm::prelude::FieldSchema> {
+                ::std::vec![#(#field_schemas),*]
+            }
+
+            fn primary_key_from_fields(
+                fields: ::orm::prelude::AttributeMap,
+            ) -> ::std::result::Result<Self::PrimaryKey, ::orm::prelude::DynamoRepositoryError> {
+                #[derive(::serde::Deserialize)]
+                struct KeyFields {
+                    #(#key_arguments: #key_argument_types,)*
+                }
+
+                #[allow(unused_variables)]
+                let fields: KeyFields = ::orm::prelude::from_attribute_map(fields)?;
+
+                ::std::result::Result::Ok(#primary_key::#primary_key_constructor(
+                    #(fields.#key_arguments),*
+                ))
+            }
+
+            #version_methods
+
+            #ttl_method
+
+            #created_at_methods
+
+            #updated_at_methods
+        }
+    })
+}
+
+/// Generates a key struct implementing `RepositoryIndex`, with a constructor taking the entity
+/// fields the key is formatted from.
+fn key_struct(
+    vis: &Visibility,
+    name: &Ident,
+    doc: &str,
+    templates: &[(Ident, &KeyTemplate)],
+    constructor_prefix: &str,
+    index_name: Option<&LitStr>,
+    field_types: &HashMap<Ident, Type>,
+) -> TokenStream {
+    let (constructor, arguments) = constructor(templates, constructor_prefix);
+    let argument_types = arguments.iter().map(|argument| &field_types[*argument]);
+    let key_names: Vec<&Ident> = templates.iter().map(|(name, _)| name).collect();
+    let key_values = templates
+        .iter()
+        .map(|(_, template)| template.expand_from_arguments());
+    let index_name = index_name.map(|index_name| {
+        quote! {
+            fn get_index_name(&self) -> ::std::option::Option<&'static str> {
+                ::std::option::Option::Some(#index_name)
+            }
+        }
+    });
+
+    quote! {
+        #[doc = #doc]
+        #[derive(Debug, Clone, ::serde::Serialize)]
+        #vis struct #name {
+            #(pub #key_names: ::std::string::String,)*
+        }
+
+        impl #name {
+            pub fn #constructor(#(#arguments: #argument_types),*) -> Self {
+                Self {
+                    #(#key_names: #key_values,)*
+                }
+            }
+        }
+
+        impl ::orm::prelude::RepositoryIndex for #name {
+            #index_name
+        }
+    }
+}
+
+/// Name of the constructor of a key struct, and the entity fields it takes in the order they first
+/// appear in the templates
+fn constructor<'a>(
+    templates: &[(Ident, &'a KeyTemplate)],
+    constructor_prefix: &str,
+) -> (Ident, Vec<&'a Ident>) {
+    let mut arguments: Vec<&Ident> = Vec::new();
+
+    for (_, template) in templates {
+        for field in template.fields() {
+            if !arguments.contains(&field) {
+                arguments.push(field);
+            }
+        }
+    }
+
+    let constructor = match arguments.is_empty() {
+        true => format_ident!("{}", constructor_prefix.trim_end_matches("_by")),
+        false => format_ident!(
+            "{}_{}",
+            constructor_prefix,
+            arguments
+                .iter()
+                .map(|argument| argument.to_string())
+                .collect::<Vec<_>>()
+                .join("_and_")
+        ),
+    };
+
+    (constructor, arguments)
+}
+
+/// `orm::prelude::FieldType` of a field, or `None` for types the GraphQL schema can't expose,
+/// such as nested structs and maps. Strings, UUIDs and dates are strings. Integers are ints when
+/// they fit the 32-bit GraphQL `Int` and big ints, which are decimal strings, otherwise.
+fn field_type(ty: &Type) -> Option<TokenStream> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    let argument = || match &segment.arguments {
+        PathArguments::AngleBracketed(arguments) => match arguments.args.first()? {
+            GenericArgument::Type(ty) => field_type(ty),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    Some(match segment.ident.to_string().as_str() {
+        "String" | "Uuid" | "DateTime" | "NaiveDate" | "NaiveDateTime" | "char" => {
+            quote!(::orm::prelude::FieldType::String)
+        }
+        "i8" | "i16" | "i32" | "u8" | "u16" => quote!(::orm::prelude::FieldType::Int),
+        "i64" | "isize" | "u32" | "u64" | "usize" => quote!(::orm::prelude::FieldType::BigInt),
+        "f32" | "f64" => quote!(::orm::prelude::FieldType::Float),
+        "bool" => quote!(::orm::prelude::FieldType::Boolean),
+        "Option" => {
+            let inner = argument()?;
+            quote!(::orm::prelude::FieldType::Nullable(::std::boxed::Box::new(#inner)))
+        }
+        "Vec" => {
+            let inner = argument()?;
+            quote!(::orm::prelude::FieldType::List(::std::boxed::Box::new(#inner)))
+        }
+        _ => return None,
+    })
+}
+
+fn is_uuid(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "Uuid"),
+        _ => false,
+    }
+}
+
+fn named_field_types(input: &DeriveInput) -> syn::Result<HashMap<Ident, Type>> {
+    Ok(named_fields(input)?
+        .into_iter()
+        .map(|(name, ty)| (name.clone(), ty.clone()))
+        .collect())
+}
+
+/// Named fields of the struct, in declaration order
+fn named_fields(input: &DeriveInput) -> syn::Result<Vec<(&Ident, &Type)>> {
+    match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => Ok(fields
+                .named
+                .iter()
+                .filter_map(|field| Some((field.ident.as_ref()?, &field.ty)))
+                .collect()),
+            _ => Err(syn::Error::new_spanned(
+                &input.ident,
+                "Entity can only be derived for structs with named fields",
+            )),
+        },
+        _ => Err(syn::Error::new_spanned(
+            &input.ident,
+            "Entity can only be derived for structs",
+        )),
+    }
+}
+
+fn parse_entity_attributes(input: &DeriveInput) -> syn::Result<EntityAttributes> {
+    let mut pk = None;
+    let mut sk = None;
+    let mut type_name = None;
+
+    for attribute in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("entity"))
+    {
+        attribute.parse_nested_meta(|meta| {
+            if meta.path.is_ident("pk") {
+                pk = Some(KeyTemplate::parse(meta.value()?.parse()?)?);
+            } else if meta.path.is_ident("sk") {
+                sk = Some(KeyTemplate::parse(meta.value()?.parse()?)?);
+            } else if meta.path.is_ident("type_name") {
+                type_name = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("unsupported entity attribute"));
+            }
+
+            Ok(())
+        })?;
+    }
+
+    let pk = pk.ok_or_else(|| {
+        syn::Error::new_spanned(&input.ident, "missing `#[entity(pk = \"...\")]` attribute")
+    })?;
+
+    Ok(EntityAttributes { pk, sk, type_name })
+}
+
+fn parse_field_attributes(input: &DeriveInput) -> syn::Result<FieldAttributes> {
+    let mut result = FieldAttributes::default();
+
+    let Data::Struct(data) = &input.data else {
+        return Ok(result);
+    };
+
+    for field in &data.fields {
+        for attribute in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("entity"))
+        {
+            attribute.parse_nested_meta(|meta| {
+                let target = if meta.path.is_ident("version") {
+                    &mut result.version
+                } else if meta.path.is_ident("ttl") {
+                    &mut result.ttl
+                } else if meta.path.is_ident("created_at") {
+                    &mut result.created_at
+                } else if meta.path.is_ident("updated_at") {
+                    &mut result.updated_at
+                } else {
+                    return Err(meta.error("unsupported entity field attribute"));
+                };
+
+                if target.is_some() {
+                    return Err(meta.error("only one field can be marked with this attribute"));
+                }
+
+                *target = field.ident.clone();
+
+                Ok(())
+            })?;
+        }
+    }
+
+    Ok(result)
+}
+
+fn parse_gsi_attributes(attributes: &[Attribute]) -> syn::Result<Vec<GsiAttributes>> {
+    let mut result = Vec::new();
+
+    for attribute in attributes.iter().filter(|attr| attr.path().is_ident("gsi")) {
+        let mut name: Option<LitStr> = None;
+        let mut pk = None;
+        let mut sk = None;
+
+        attribute.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                name = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("pk") {
+                pk = Some(KeyTemplate::parse(meta.value()?.parse()?)?);
+            } else if meta.path.is_ident("sk") {
+                sk = Some(KeyTemplate::parse(meta.value()?.parse()?)?);
+            } else {
+                return Err(meta.error("unsupported gsi attribute"));
+            }
+
+            Ok(())
+        })?;
+
+        let name = name.ok_or_else(|| syn::Error::new_spanned(attribute, "missing gsi `name`"))?;
+        let pk = pk.ok_or_else(|| syn::Error::new_spanned(attribute, "missing gsi `pk`"))?;
+
+        result.push(GsiAttributes { name, pk, sk });
+    }
+
+    Ok(result)
+}
+
+/// `by_owner` becomes `ByOwner`.
+fn upper_camel_case(name: &str) -> String {
+    name.split(['_', '-'])
+        .map(|part| {
+            let mut chars = part.chars();
+
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+/// `NoteEntity` becomes `Note`, so the generated structs are named `NotePrimaryKey` etc.
+fn type_prefix(entity: &Ident) -> String {
+    let name = entity.to_string();
+
+    match name.strip_suffix("Entity") {
+        Some(prefix) if !prefix.is_empty() => prefix.to_string(),
+        _ => name,
+    }
+}
@@ -0,0 +1,64 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput, Fields};
+
+pub fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
+    let Data::Enum(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "`EntityEnum` can only be derived for enums",
+        ));
+    };
+
+    let mut variants = Vec::new();
+
+    for variant in &data.variants {
+        match &variant.fields {
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                variants.push((&variant.ident, &fields.unnamed[0].ty));
+            }
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    variant,
+                    "`EntityEnum` variants must wrap a single entity, e.g. `Note(NoteEntity)`",
+                ))
+            }
+        }
+    }
+
+    let entity_enum = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    let names = variants.iter().map(|(name, _)| name);
+    let types = variants.iter().map(|(_, ty)| ty);
+
+    Ok(quote! {
+        impl #impl_generics ::orm::prelude::EntityEnum for #entity_enum #type_generics #where_clause {
+            fn from_item(
+                item: ::orm::prelude::AttributeMap,
+            ) -> ::std::result::Result<
+                ::std::option::Option<Self>,
+                ::orm::prelude::DynamoRepositoryError,
+            > {
+                let entity_type = ::orm::prelude::read_entity_type(&item)
+                    .ok_or(::orm::prelude::DynamoRepositoryError::MissingEntityType)?
+                    .to_string();
+
+                #(
+                    if <#types as ::orm::prelude::Entity>::get_entity_type()
+                        == ::std::option::Option::Some(entity_type.as_str())
+                    {
+                        if <#types as ::orm::prelude::Entity>::is_expired(&item) {
+                            return ::std::result::Result::Ok(::std::option::Option::None);
+                        }
+
+                        return ::std::result::Result::Ok(::std::option::Option::Some(
+                            Self::#names(<#types as ::orm::prelude::Entity>::from_attribute_values(item)?),
+                        ));
+                    }
+                )*
+
+                ::std::result::Result::Ok(::std::option::Option::None)
+            }
+        }
+    })
+}
@@ -0,0 +1,79 @@
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, DeriveInput};
+
+mod entity;
+mod entity_enum;
+mod template;
+mod view;
+
+/// Derives `orm::prelude::Entity` together with the key structs of the entity.
+///
+/// ```ignore
+/// #[derive(Serialize, Deserialize, Entity)]
+/// #[entity(pk = "NOTE", sk = "NOTE_ID#{id}")]
+/// #[gsi(name = "gsi1", pk = "NOTE", sk = "TITLE#{title}")]
+/// pub struct NoteEntity {
+///     pub id: Uuid,
+///     pub title: String,
+///     #[entity(version)]
+///     pub version: u64,
+/// }
+/// ```
+///
+/// Generates `NotePrimaryKey` (with a `find_by_id` constructor), `NotePartitionKey`
+/// (with a `find_all` constructor), `NoteIndexFields` holding the `gsi1_pk`/`gsi1_sk`
+/// attributes, `NoteGsi1Index` to query the `gsi1` index and the `Entity` impl tying them together.
+/// The field marked `#[entity(version)]` is used for optimistic locking, and the field marked
+/// `#[entity(ttl)]` holds the epoch seconds after which the item expires. `DateTime<Utc>` fields
+/// marked `#[entity(created_at)]` and `#[entity(updated_at)]` are stamped by the writes.
+#[proc_macro_derive(Entity, attributes(entity, gsi))]
+pub fn derive_entity(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    entity::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derives `orm::prelude::EntityEnum` for an enum of entities sharing an item collection.
+///
+/// ```ignore
+/// #[derive(EntityEnum)]
+/// pub enum NoteCollection {
+///     Note(NoteEntity),
+///     Comment(CommentEntity),
+/// }
+/// ```
+///
+/// Every variant wraps a single entity, and items are read into the variant whose entity type
+/// matches their `_type` attribute. An entity's type defaults to the prefix of its name, `Note`
+/// for `NoteEntity`, and can be set with `#[entity(type_name = "...")]`.
+#[proc_macro_derive(EntityEnum)]
+pub fn derive_entity_enum(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    entity_enum::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derives `orm::prelude::View` for a struct reading some of the attributes of an entity.
+///
+/// ```ignore
+/// #[derive(Deserialize, View)]
+/// #[view(entity = NoteEntity)]
+/// pub struct NoteTitle {
+///     pub id: Uuid,
+///     pub title: String,
+/// }
+/// ```
+///
+/// The view reads the attributes named after its fields.
+#[proc_macro_derive(View, attributes(view))]
+pub fn derive_view(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    view::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
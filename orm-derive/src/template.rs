@@ -0,0 +1,89 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Ident, LitStr};
+
+/// A key format such as `"NOTE_ID#{id}"`, where every `{field}` refers to a field of the entity.
+pub struct KeyTemplate {
+    literal: LitStr,
+    fields: Vec<Ident>,
+}
+
+impl KeyTemplate {
+    pub fn parse(literal: LitStr) -> syn::Result<Self> {
+        let value = literal.value();
+        let mut fields: Vec<Ident> = Vec::new();
+        let mut chars = value.chars().peekable();
+
+        while let Some(char) = chars.next() {
+            match char {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                }
+                '{' => {
+                    let mut name = String::new();
+
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(char) => name.push(char),
+                            None => {
+                                return Err(syn::Error::new(literal.span(), "unclosed `{` in key"))
+                            }
+                        }
+                    }
+
+                    let field = syn::parse_str::<Ident>(&name).map_err(|_| {
+                        syn::Error::new(
+                            literal.span(),
+                            format!("`{{{}}}` is not a valid field reference", name),
+                        )
+                    })?;
+
+                    if !fields.contains(&field) {
+                        fields.push(field);
+                    }
+                }
+                '}' => return Err(syn::Error::new(literal.span(), "unmatched `}` in key")),
+                _ => {}
+            }
+        }
+
+        Ok(Self { literal, fields })
+    }
+
+    pub fn literal(&self) -> &LitStr {
+        &self.literal
+    }
+
+    pub fn fields(&self) -> &[Ident] {
+        &self.fields
+    }
+
+    /// Expression formatting the key from the fields of `self`.
+    pub fn expand_from_self(&self) -> TokenStream {
+        self.expand(|field| quote!(self.#field))
+    }
+
+    /// Expression formatting the key from local variables named after the fields.
+    pub fn expand_from_arguments(&self) -> TokenStream {
+        self.expand(|field| quote!(#field))
+    }
+
+    fn expand(&self, value: impl Fn(&Ident) -> TokenStream) -> TokenStream {
+        let literal = &self.literal;
+
+        if self.fields.is_empty() {
+            return quote!(::std::string::String::from(#literal));
+        }
+
+        let arguments = self.fields.iter().map(|field| {
+            let value = value(field);
+            quote!(#field = #value)
+        });
+
+        quote!(::std::format!(#literal, #(#arguments),*))
+    }
+}
@@ -0,0 +1,66 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput, Fields, LitStr, Path};
+
+pub fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => fields
+                .named
+                .iter()
+                .filter_map(|field| field.ident.as_ref())
+                .map(|ident| LitStr::new(&ident.to_string(), ident.span()))
+                .collect::<Vec<_>>(),
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "View can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "View can only be derived for structs",
+            ))
+        }
+    };
+
+    let entity = parse_view_entity(&input)?;
+    let view = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::orm::prelude::View for #view #type_generics #where_clause {
+            type Entity = #entity;
+
+            fn get_projection() -> ::std::vec::Vec<&'static str> {
+                ::std::vec![#(#fields),*]
+            }
+        }
+    })
+}
+
+fn parse_view_entity(input: &DeriveInput) -> syn::Result<Path> {
+    let mut entity = None;
+
+    for attribute in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("view"))
+    {
+        attribute.parse_nested_meta(|meta| {
+            if meta.path.is_ident("entity") {
+                entity = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("unsupported view attribute"));
+            }
+
+            Ok(())
+        })?;
+    }
+
+    entity.ok_or_else(|| {
+        syn::Error::new_spanned(&input.ident, "missing `#[view(entity = ...)]` attribute")
+    })
+}
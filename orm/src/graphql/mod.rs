@@ -0,0 +1,1342 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use juniper::meta::{Field, MetaType};
+use juniper::parser::{ScalarToken, Token};
+use juniper::{
+    Arguments, DefaultScalarValue, EmptySubscription, ExecutionResult, Executor, FieldError,
+    FromInputValue, GraphQLType, GraphQLValue, GraphQLValueAsync, InputValue, ParseError,
+    ParseScalarResult, ParseScalarValue, Registry, RootNode, Value,
+};
+use serde::Serialize;
+use serde_json::Value as Json;
+
+use crate::prelude::{
+    CrudService, CursorCodec, DynamoRepository, DynamoRepositoryError, Entity, FieldSchema,
+    FieldType, Page, QueryData, RepositoryIndex,
+};
+use crate::repository::entity::{from_attribute_map, to_attribute_map};
+use crate::server::Problem;
+
+const DEFAULT_PAGE_SIZE: i32 = 50;
+const MAX_PAGE_SIZE: i32 = 100;
+
+/// GraphQL schema exposing the entities of the registered services, built by a
+/// [`CrudSchemaBuilder`]
+pub type CrudSchema = RootNode<'static, CrudQuery, CrudMutation, EmptySubscription<()>>;
+
+type Resolved<T> = BoxFuture<'static, Result<T, DynamoRepositoryError>>;
+type Resolver<Args, T> = Box<dyn Fn(Args) -> Resolved<T> + Send + Sync>;
+
+/// Builds a GraphQL schema from [`CrudService`]s. Every registered entity, e.g. `NoteEntity` with
+/// the entity type `Note`, gets:
+///
+/// - a `Note` object type with the fields listed by [`Entity::get_fields`]
+/// - a `note(<key fields>): Note` query, which is `null` when the note doesn't exist
+/// - a `notes(limit: Int = 50, cursor: String): NotePage!` query, paging through `list_index`,
+///   named by the list field passed to `register`
+/// - a `createNote(input: NoteInput!): Note!` mutation, taking every field but the version and
+///   timestamps, which the entity has to default. UUID key fields are optional and get a random
+///   UUID when left out.
+/// - an `updateNote(<key fields>, version: BigInt!, patch: NotePatch!): Note!` mutation, changing
+///   the fields set in the patch of the stored note. Versioned entities take the version the
+///   client read, so the update fails with a version conflict when the note changed since.
+/// - a `deleteNote(<key fields>): Note` mutation, returning the deleted note
+///
+/// GraphQL `Int`s are 32-bit, so 64-bit and unsigned 32-bit integer fields are `BigInt`s, which
+/// are decimal strings. Writes go through the service, so they run its hooks:
+///
+/// ```ignore
+/// let schema = CrudSchemaBuilder::new()
+///     .register(notes_service, "notes", NotePartitionKey::find_all(), CursorCodec::new(secret))
+///     .build()?;
+/// ```
+#[derive(Default)]
+pub struct CrudSchemaBuilder {
+    entities: Vec<EntityType>,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum CrudSchemaError {
+    #[error("More than one type or root field is named {0}")]
+    DuplicateName(String),
+}
+
+impl CrudSchemaBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register<E, R, S, Index>(
+        mut self,
+        service: S,
+        list_field: &str,
+        list_index: Index,
+        cursors: CursorCodec,
+    ) -> Self
+    where
+        E: Entity,
+        E::PrimaryKey: RepositoryIndex,
+        E::IndexFields: Serialize,
+        R: DynamoRepository<E>,
+        S: CrudService<E, R> + Send + Sync + 'static,
+        Index: RepositoryIndex + Sync + 'static,
+    {
+        self.entities
+            .push(EntityType::new(service, list_field, list_index, cursors));
+        self
+    }
+
+    /// Fails when two entities, or an entity and its list field, end up with the same name, as
+    /// fields would be resolved by whichever entity was registered first
+    pub fn build(self) -> Result<CrudSchema, CrudSchemaError> {
+        let mut names = HashSet::new();
+
+        for name in self.entities.iter().flat_map(EntityType::names) {
+            if !names.insert(name) {
+                return Err(CrudSchemaError::DuplicateName(name.to_string()));
+            }
+        }
+
+        let types = EntityTypes(Arc::new(self.entities));
+
+        Ok(RootNode::new_with_info(
+            CrudQuery,
+            CrudMutation,
+            EmptySubscription::new(),
+            types.clone(),
+            types,
+            (),
+        ))
+    }
+}
+
+/// The entity types of a [`CrudSchema`]
+#[derive(Clone)]
+pub struct EntityTypes(Arc<Vec<EntityType>>);
+
+impl EntityTypes {
+    fn find(&self, field_name: &str) -> Option<(&EntityType, RootField)> {
+        self.0.iter().find_map(|entity| {
+            let field = if field_name == entity.get_field {
+                RootField::Get
+            } else if field_name == entity.list_field {
+                RootField::List
+            } else if field_name == entity.create_field {
+                RootField::Create
+            } else if field_name == entity.update_field {
+                RootField::Update
+            } else if field_name == entity.delete_field {
+                RootField::Delete
+            } else {
+                return None;
+            };
+
+            Some((entity, field))
+        })
+    }
+}
+
+enum RootField {
+    Get,
+    List,
+    Create,
+    Update,
+    Delete,
+}
+
+/// An entity exposed by a [`CrudSchema`], with the names of its types and root fields and the
+/// resolvers reading and writing it through its service
+pub struct EntityType {
+    name: String,
+    page_name: String,
+    input_name: String,
+    patch_name: String,
+    get_field: String,
+    list_field: String,
+    create_field: String,
+    update_field: String,
+    delete_field: String,
+    fields: Vec<FieldSchema>,
+    /// The version field of versioned entities, which updates take as an argument
+    version: Option<FieldSchema>,
+    /// Attributes the writes stamp, which inputs leave out
+    managed: Vec<&'static str>,
+    resolvers: EntityResolvers,
+}
+
+/// Resolvers of an entity, taking and returning entities as the JSON of their attributes
+struct EntityResolvers {
+    get: Resolver<Json, Option<Json>>,
+    /// Takes the cursor and the page size
+    list: Resolver<(Option<String>, i32), Page<Json>>,
+    create: Resolver<Json, Json>,
+    /// Takes the key and the patch
+    update: Resolver<(Json, Json), Json>,
+    delete: Resolver<Json, Option<Json>>,
+}
+
+impl EntityType {
+    fn new<E, R, S, Index>(
+        service: S,
+        list_field: &str,
+        list_index: Index,
+        cursors: CursorCodec,
+    ) -> Self
+    where
+        E: Entity,
+        E::PrimaryKey: RepositoryIndex,
+        E::IndexFields: Serialize,
+        R: DynamoRepository<E>,
+        S: CrudService<E, R> + Send + Sync + 'static,
+        Index: RepositoryIndex + Sync + 'static,
+    {
+        let name = E::get_entity_type()
+            .map(String::from)
+            .unwrap_or_else(|| type_prefix(std::any::type_name::<E>()));
+        let field_name = lower_camel_case(&name);
+
+        Self {
+            page_name: format!("{}Page", name),
+            input_name: format!("{}Input", name),
+            patch_name: format!("{}Patch", name),
+            get_field: field_name.clone(),
+            list_field: list_field.to_string(),
+            create_field: format!("create{}", name),
+            update_field: format!("update{}", name),
+            delete_field: format!("delete{}", name),
+            version: E::get_fields()
+                .into_iter()
+                .find(|field| Some(field.name) == E::get_version_attribute()),
+            fields: E::get_fields(),
+            managed: [
+                E::get_version_attribute(),
+                E::get_created_at_attribute(),
+                E::get_updated_at_attribute(),
+                E::get_ttl_attribute(),
+            ]
+            .into_iter()
+            .flatten()
+            .collect(),
+            resolvers: EntityResolvers::new(Arc::new(service), list_index, Arc::new(cursors)),
+            name,
+        }
+    }
+
+    /// Names of the types and root fields of the entity
+    fn names(&self) -> [&str; 9] {
+        [
+            &self.name,
+            &self.page_name,
+            &self.input_name,
+            &self.patch_name,
+            &self.get_field,
+            &self.list_field,
+            &self.create_field,
+            &self.update_field,
+            &self.delete_field,
+        ]
+    }
+
+    fn key_fields(&self) -> impl Iterator<Item = &FieldSchema> {
+        self.fields.iter().filter(|field| field.key)
+    }
+
+    fn input_fields(&self) -> impl Iterator<Item = &FieldSchema> {
+        self.fields
+            .iter()
+            .filter(|field| !self.managed.contains(&field.name))
+    }
+
+    /// The fields an update can change, which excludes the key fields
+    fn patch_fields(&self) -> impl Iterator<Item = &FieldSchema> {
+        self.input_fields().filter(|field| !field.key)
+    }
+
+    /// Arguments taking the key fields, which the single entity fields are looked up by
+    fn key_arguments<'r>(
+        &self,
+        field: Field<'r, DefaultScalarValue>,
+        registry: &mut Registry<'r>,
+    ) -> Field<'r, DefaultScalarValue> {
+        self.key_fields().fold(field, |field, key| {
+            field.argument(registry.arg::<FieldValue>(key.name, &key.field_type))
+        })
+    }
+
+    fn read_key(&self, arguments: &Arguments) -> Json {
+        self.read_fields(Json::Object(
+            self.key_fields()
+                .map(|key| (key.name.to_string(), read_argument(arguments, key.name)))
+                .collect(),
+        ))
+    }
+
+    /// The fields of an input, with their values as the entity stores them
+    fn read_fields(&self, input: Json) -> Json {
+        let Json::Object(input) = input else {
+            return input;
+        };
+
+        Json::Object(
+            input
+                .into_iter()
+                .map(|(name, value)| {
+                    let value = match self.fields.iter().find(|field| field.name == name) {
+                        Some(field) => from_field_input(value, &field.field_type),
+                        None => value,
+                    };
+
+                    (name, value)
+                })
+                .collect(),
+        )
+    }
+
+    /// The create input, with a random UUID for each generated field it leaves out
+    fn read_input(&self, arguments: &Arguments) -> Json {
+        let mut input = read_argument(arguments, "input");
+
+        if let Json::Object(input) = &mut input {
+            for field in self.fields.iter().filter(|field| field.generated) {
+                let value = input.entry(field.name).or_insert(Json::Null);
+
+                if value.is_null() {
+                    *value = Json::from(uuid::Uuid::new_v4().to_string());
+                }
+            }
+        }
+
+        self.read_fields(input)
+    }
+
+    /// The update patch, with the version the client read so the write checks it
+    fn read_patch(&self, arguments: &Arguments) -> Json {
+        let mut patch = read_argument(arguments, "patch");
+
+        if let (Json::Object(patch), Some(version)) = (&mut patch, &self.version) {
+            patch.insert(
+                version.name.to_string(),
+                read_argument(arguments, version.name),
+            );
+        }
+
+        self.read_fields(patch)
+    }
+}
+
+impl EntityResolvers {
+    fn new<E, R, S, Index>(service: Arc<S>, list_index: Index, cursors: Arc<CursorCodec>) -> Self
+    where
+        E: Entity,
+        E::PrimaryKey: RepositoryIndex,
+        E::IndexFields: Serialize,
+        R: DynamoRepository<E>,
+        S: CrudService<E, R> + Send + Sync + 'static,
+        Index: RepositoryIndex + Sync + 'static,
+    {
+        let get = {
+            let service = service.clone();
+
+            move |key: Json| -> Resolved<Option<Json>> {
+                let service = service.clone();
+
+                Box::pin(async move {
+                    let key = E::primary_key_from_fields(to_attribute_map(&key)?)?;
+
+                    service.find(key).await?.as_ref().map(to_json).transpose()
+                })
+            }
+        };
+
+        let list = {
+            let service = service.clone();
+
+            move |(cursor, limit): (Option<String>, i32)| -> Resolved<Page<Json>> {
+                let service = service.clone();
+                let cursors = cursors.clone();
+                let query = QueryData::new(list_index.clone(), None).with_limit(limit);
+
+                Box::pin(async move {
+                    let page = service
+                        .query_page(&cursors, query, cursor.as_deref())
+                        .await?;
+
+                    Ok(Page {
+                        items: page.items.iter().map(to_json).collect::<Result<_, _>>()?,
+                        cursor: page.cursor,
+                    })
+                })
+            }
+        };
+
+        let create = {
+            let service = service.clone();
+
+            move |input: Json| -> Resolved<Json> {
+                let service = service.clone();
+
+                Box::pin(async move {
+                    let entity = E::from_attribute_values(to_attribute_map(&input)?)?;
+
+                    to_json(&service.create(entity).await?)
+                })
+            }
+        };
+
+        let update = {
+            let service = service.clone();
+
+            move |(key, patch): (Json, Json)| -> Resolved<Json> {
+                let service = service.clone();
+
+                Box::pin(async move {
+                    let key = E::primary_key_from_fields(to_attribute_map(&key)?)?;
+                    let stored = service
+                        .find(key)
+                        .await?
+                        .ok_or(DynamoRepositoryError::ItemNotFoundError)?;
+
+                    let mut item = to_json(&stored)?;
+
+                    if let (Json::Object(item), Json::Object(patch)) = (&mut item, patch) {
+                        item.extend(patch);
+                    }
+
+                    let entity = E::from_attribute_values(to_attribute_map(&item)?)?;
+
+                    to_json(&service.upsert(entity).await?)
+                })
+            }
+        };
+
+        let delete = move |key: Json| -> Resolved<Option<Json>> {
+            let service = service.clone();
+
+            Box::pin(async move {
+                let key = E::primary_key_from_fields(to_attribute_map(&key)?)?;
+
+                let Some(stored) = service.find(key).await? else {
+                    return Ok(None);
+                };
+
+                let deleted = to_json(&stored)?;
+                service.delete(stored).await?;
+
+                Ok(Some(deleted))
+            })
+        };
+
+        Self {
+            get: Box::new(get),
+            list: Box::new(list),
+            create: Box::new(create),
+            update: Box::new(update),
+            delete: Box::new(delete),
+        }
+    }
+}
+
+/// Query root of a [`CrudSchema`]
+pub struct CrudQuery;
+
+impl GraphQLType for CrudQuery {
+    fn name(_: &EntityTypes) -> Option<&str> {
+        Some("Query")
+    }
+
+    fn meta<'r>(info: &EntityTypes, registry: &mut Registry<'r>) -> MetaType<'r>
+    where
+        DefaultScalarValue: 'r,
+    {
+        let mut fields = Vec::new();
+
+        for entity in info.0.iter() {
+            let get = registry.field::<Option<EntityObject>>(&entity.get_field, entity);
+            fields.push(entity.key_arguments(get, registry));
+
+            let limit = registry.arg_with_default::<i32>("limit", &DEFAULT_PAGE_SIZE, &());
+            let cursor = registry.arg::<Option<String>>("cursor", &());
+            fields.push(
+                registry
+                    .field::<EntityPage>(&entity.list_field, entity)
+                    .argument(limit)
+                    .argument(cursor),
+            );
+        }
+
+        registry
+            .build_object_type::<Self>(info, &fields)
+            .into_meta()
+    }
+}
+
+impl GraphQLValue for CrudQuery {
+    type Context = ();
+    type TypeInfo = EntityTypes;
+
+    fn type_name<'i>(&self, info: &'i EntityTypes) -> Option<&'i str> {
+        Self::name(info)
+    }
+}
+
+impl GraphQLValueAsync for CrudQuery {
+    fn resolve_field_async<'a>(
+        &'a self,
+        info: &'a EntityTypes,
+        field_name: &'a str,
+        arguments: &'a Arguments,
+        executor: &'a Executor<Self::Context, DefaultScalarValue>,
+    ) -> BoxFuture<'a, ExecutionResult> {
+        Box::pin(async move {
+            match info.find(field_name) {
+                Some((entity, RootField::Get)) => {
+                    let item = (entity.resolvers.get)(entity.read_key(arguments))
+                        .await
+                        .map_err(field_error)?;
+
+                    executor
+                        .resolve_async(entity, &item.map(EntityObject))
+                        .await
+                }
+                Some((entity, RootField::List)) => {
+                    let limit = arguments
+                        .get::<i32>("limit")
+                        .unwrap_or(DEFAULT_PAGE_SIZE)
+                        .clamp(1, MAX_PAGE_SIZE);
+                    let page = (entity.resolvers.list)((arguments.get("cursor"), limit))
+                        .await
+                        .map_err(field_error)?;
+
+                    executor
+                        .resolve_async(entity, &EntityPage::from(page))
+                        .await
+                }
+                _ => Err(unknown_field(field_name)),
+            }
+        })
+    }
+}
+
+/// Mutation root of a [`CrudSchema`]
+pub struct CrudMutation;
+
+impl GraphQLType for CrudMutation {
+    fn name(_: &EntityTypes) -> Option<&str> {
+        Some("Mutation")
+    }
+
+    fn meta<'r>(info: &EntityTypes, registry: &mut Registry<'r>) -> MetaType<'r>
+    where
+        DefaultScalarValue: 'r,
+    {
+        let mut fields = Vec::new();
+
+        for entity in info.0.iter() {
+            let input = registry.arg::<EntityInput>("input", entity);
+            fields.push(
+                registry
+                    .field::<EntityObject>(&entity.create_field, entity)
+                    .argument(input),
+            );
+
+            let mut update = registry.field::<EntityObject>(&entity.update_field, entity);
+            update = entity.key_arguments(update, registry);
+
+            if let Some(version) = &entity.version {
+                let version = registry.arg::<FieldValue>(version.name, &version.field_type);
+                update = update.argument(version);
+            }
+
+            let patch = registry.arg::<EntityPatch>("patch", entity);
+            fields.push(update.argument(patch));
+
+            let delete = registry.field::<Option<EntityObject>>(&entity.delete_field, entity);
+            fields.push(entity.key_arguments(delete, registry));
+        }
+
+        registry
+            .build_object_type::<Self>(info, &fields)
+            .into_meta()
+    }
+}
+
+impl GraphQLValue for CrudMutation {
+    type Context = ();
+    type TypeInfo = EntityTypes;
+
+    fn type_name<'i>(&self, info: &'i EntityTypes) -> Option<&'i str> {
+        Self::name(info)
+    }
+}
+
+impl GraphQLValueAsync for CrudMutation {
+    fn resolve_field_async<'a>(
+        &'a self,
+        info: &'a EntityTypes,
+        field_name: &'a str,
+        arguments: &'a Arguments,
+        executor: &'a Executor<Self::Context, DefaultScalarValue>,
+    ) -> BoxFuture<'a, ExecutionResult> {
+        Box::pin(async move {
+            match info.find(field_name) {
+                Some((entity, RootField::Create)) => {
+                    let item = (entity.resolvers.create)(entity.read_input(arguments))
+                        .await
+                        .map_err(field_error)?;
+
+                    executor.resolve_async(entity, &EntityObject(item)).await
+                }
+                Some((entity, RootField::Update)) => {
+                    let key = entity.read_key(arguments);
+                    let item = (entity.resolvers.update)((key, entity.read_patch(arguments)))
+                        .await
+                        .map_err(field_error)?;
+
+                    executor.resolve_async(entity, &EntityObject(item)).await
+                }
+                Some((entity, RootField::Delete)) => {
+                    let item = (entity.resolvers.delete)(entity.read_key(arguments))
+                        .await
+                        .map_err(field_error)?;
+
+                    executor
+                        .resolve_async(entity, &item.map(EntityObject))
+                        .await
+                }
+                _ => Err(unknown_field(field_name)),
+            }
+        })
+    }
+}
+
+/// An entity, as the JSON of its attributes
+struct EntityObject(Json);
+
+impl GraphQLType for EntityObject {
+    fn name(info: &EntityType) -> Option<&str> {
+        Some(&info.name)
+    }
+
+    fn meta<'r>(info: &EntityType, registry: &mut Registry<'r>) -> MetaType<'r>
+    where
+        DefaultScalarValue: 'r,
+    {
+        let fields = info
+            .fields
+            .iter()
+            .map(|field| registry.field::<FieldValue>(field.name, &field.field_type))
+            .collect::<Vec<_>>();
+
+        registry
+            .build_object_type::<Self>(info, &fields)
+            .into_meta()
+    }
+}
+
+impl GraphQLValue for EntityObject {
+    type Context = ();
+    type TypeInfo = EntityType;
+
+    fn type_name<'i>(&self, info: &'i EntityType) -> Option<&'i str> {
+        Some(&info.name)
+    }
+
+    fn concrete_type_name(&self, _: &(), info: &EntityType) -> String {
+        info.name.clone()
+    }
+
+    fn resolve_field(
+        &self,
+        info: &EntityType,
+        field_name: &str,
+        _: &Arguments,
+        _: &Executor<(), DefaultScalarValue>,
+    ) -> ExecutionResult {
+        let field = info.fields.iter().find(|field| field.name == field_name);
+
+        Ok(match (self.0.get(field_name), field) {
+            (Some(value), Some(field)) => to_field_value(value, &field.field_type),
+            (Some(value), None) => to_value(value),
+            (None, _) => Value::null(),
+        })
+    }
+}
+
+impl GraphQLValueAsync for EntityObject {
+    fn resolve_field_async<'a>(
+        &'a self,
+        info: &'a EntityType,
+        field_name: &'a str,
+        arguments: &'a Arguments,
+        executor: &'a Executor<Self::Context, DefaultScalarValue>,
+    ) -> BoxFuture<'a, ExecutionResult> {
+        Box::pin(futures::future::ready(
+            self.resolve_field(info, field_name, arguments, executor),
+        ))
+    }
+}
+
+/// A page of entities, with the cursor of the next page
+struct EntityPage {
+    items: Vec<EntityObject>,
+    cursor: Option<String>,
+}
+
+impl From<Page<Json>> for EntityPage {
+    fn from(page: Page<Json>) -> Self {
+        Self {
+            items: page.items.into_iter().map(EntityObject).collect(),
+            cursor: page.cursor,
+        }
+    }
+}
+
+impl GraphQLType for EntityPage {
+    fn name(info: &EntityType) -> Option<&str> {
+        Some(&info.page_name)
+    }
+
+    fn meta<'r>(info: &EntityType, registry: &mut Registry<'r>) -> MetaType<'r>
+    where
+        DefaultScalarValue: 'r,
+    {
+        let fields = [
+            registry.field::<Vec<EntityObject>>("items", info),
+            registry.field::<Option<String>>("cursor", &()),
+        ];
+
+        registry
+            .build_object_type::<Self>(info, &fields)
+            .into_meta()
+    }
+}
+
+impl GraphQLValue for EntityPage {
+    type Context = ();
+    type TypeInfo = EntityType;
+
+    fn type_name<'i>(&self, info: &'i EntityType) -> Option<&'i str> {
+        Some(&info.page_name)
+    }
+
+    fn concrete_type_name(&self, _: &(), info: &EntityType) -> String {
+        info.page_name.clone()
+    }
+}
+
+impl GraphQLValueAsync for EntityPage {
+    fn resolve_field_async<'a>(
+        &'a self,
+        info: &'a EntityType,
+        field_name: &'a str,
+        _: &'a Arguments,
+        executor: &'a Executor<Self::Context, DefaultScalarValue>,
+    ) -> BoxFuture<'a, ExecutionResult> {
+        Box::pin(async move {
+            match field_name {
+                "items" => executor.resolve_async(info, &self.items).await,
+                "cursor" => executor.resolve_async(&(), &self.cursor).await,
+                _ => Err(unknown_field(field_name)),
+            }
+        })
+    }
+}
+
+/// Input creating an entity, with every field but the ones the writes stamp. Generated fields are
+/// optional.
+struct EntityInput;
+
+impl GraphQLType for EntityInput {
+    fn name(info: &EntityType) -> Option<&str> {
+        Some(&info.input_name)
+    }
+
+    fn meta<'r>(info: &EntityType, registry: &mut Registry<'r>) -> MetaType<'r>
+    where
+        DefaultScalarValue: 'r,
+    {
+        let fields = info
+            .input_fields()
+            .map(|field| match field.generated {
+                true => registry.arg::<FieldValue>(field.name, &nullable(&field.field_type)),
+                false => registry.arg::<FieldValue>(field.name, &field.field_type),
+            })
+            .collect::<Vec<_>>();
+
+        registry
+            .build_input_object_type::<Self>(info, &fields)
+            .into_meta()
+    }
+}
+
+impl GraphQLValue for EntityInput {
+    type Context = ();
+    type TypeInfo = EntityType;
+
+    fn type_name<'i>(&self, info: &'i EntityType) -> Option<&'i str> {
+        Some(&info.input_name)
+    }
+}
+
+impl FromInputValue for EntityInput {
+    fn from_input_value(_: &InputValue) -> Option<Self> {
+        Some(Self)
+    }
+}
+
+/// Input updating an entity, where every field but the key fields is optional
+struct EntityPatch;
+
+impl GraphQLType for EntityPatch {
+    fn name(info: &EntityType) -> Option<&str> {
+        Some(&info.patch_name)
+    }
+
+    fn meta<'r>(info: &EntityType, registry: &mut Registry<'r>) -> MetaType<'r>
+    where
+        DefaultScalarValue: 'r,
+    {
+        let fields = info
+            .patch_fields()
+            .map(|field| registry.arg::<FieldValue>(field.name, &nullable(&field.field_type)))
+            .collect::<Vec<_>>();
+
+        registry
+            .build_input_object_type::<Self>(info, &fields)
+            .into_meta()
+    }
+}
+
+impl GraphQLValue for EntityPatch {
+    type Context = ();
+    type TypeInfo = EntityType;
+
+    fn type_name<'i>(&self, info: &'i EntityType) -> Option<&'i str> {
+        Some(&info.patch_name)
+    }
+}
+
+impl FromInputValue for EntityPatch {
+    fn from_input_value(_: &InputValue) -> Option<Self> {
+        Some(Self)
+    }
+}
+
+/// Stands in for the type of an entity field while building the schema. Values are resolved by
+/// the entity, and arguments read with [`read_argument`].
+struct FieldValue;
+
+impl GraphQLType for FieldValue {
+    fn name(info: &FieldType) -> Option<&str> {
+        match info {
+            FieldType::String => Some("String"),
+            FieldType::Int => Some("Int"),
+            FieldType::BigInt => BigInt::name(&()),
+            FieldType::Float => Some("Float"),
+            FieldType::Boolean => Some("Boolean"),
+            FieldType::List(_) | FieldType::Nullable(_) => None,
+        }
+    }
+
+    fn meta<'r>(info: &FieldType, registry: &mut Registry<'r>) -> MetaType<'r>
+    where
+        DefaultScalarValue: 'r,
+    {
+        match info {
+            FieldType::String => <String as GraphQLType>::meta(&(), registry),
+            FieldType::Int => <i32 as GraphQLType>::meta(&(), registry),
+            FieldType::BigInt => BigInt::meta(&(), registry),
+            FieldType::Float => <f64 as GraphQLType>::meta(&(), registry),
+            FieldType::Boolean => <bool as GraphQLType>::meta(&(), registry),
+            FieldType::List(of_type) => registry.build_list_type::<Self>(of_type).into_meta(),
+            FieldType::Nullable(of_type) => {
+                registry.build_nullable_type::<Self>(of_type).into_meta()
+            }
+        }
+    }
+}
+
+impl GraphQLValue for FieldValue {
+    type Context = ();
+    type TypeInfo = FieldType;
+
+    fn type_name<'i>(&self, info: &'i FieldType) -> Option<&'i str> {
+        Self::name(info)
+    }
+}
+
+impl FromInputValue for FieldValue {
+    fn from_input_value(_: &InputValue) -> Option<Self> {
+        Some(Self)
+    }
+}
+
+/// Scalar of the integers a GraphQL `Int` can't hold, as decimal strings so clients don't round
+/// them to floats. Integer literals are accepted too.
+struct BigInt;
+
+impl GraphQLType for BigInt {
+    fn name(_: &()) -> Option<&str> {
+        Some("BigInt")
+    }
+
+    fn meta<'r>(_: &(), registry: &mut Registry<'r>) -> MetaType<'r>
+    where
+        DefaultScalarValue: 'r,
+    {
+        registry
+            .build_scalar_type::<Self>(&())
+            .description("An integer beyond 32 bits, as a decimal string")
+            .into_meta()
+    }
+}
+
+impl GraphQLValue for BigInt {
+    type Context = ();
+    type TypeInfo = ();
+
+    fn type_name<'i>(&self, info: &'i ()) -> Option<&'i str> {
+        Self::name(info)
+    }
+}
+
+impl FromInputValue for BigInt {
+    fn from_input_value(value: &InputValue) -> Option<Self> {
+        match value {
+            InputValue::Scalar(DefaultScalarValue::Int(_)) => Some(Self),
+            InputValue::Scalar(DefaultScalarValue::String(value)) => {
+                parse_big_int(value).map(|_| Self)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl ParseScalarValue for BigInt {
+    fn from_str(value: ScalarToken<'_>) -> ParseScalarResult<'_, DefaultScalarValue> {
+        match value {
+            ScalarToken::String(digits) | ScalarToken::Int(digits) => {
+                Ok(DefaultScalarValue::String(digits.to_string()))
+            }
+            ScalarToken::Float(_) => Err(ParseError::UnexpectedToken(Token::Scalar(value))),
+        }
+    }
+}
+
+/// A decimal integer as a JSON number, or `None` when it's not an integer of 64 bits
+fn parse_big_int(value: &str) -> Option<Json> {
+    match value.parse::<i64>() {
+        Ok(value) => Some(Json::from(value)),
+        Err(_) => value.parse::<u64>().ok().map(Json::from),
+    }
+}
+
+/// An argument as it was passed, whatever its type
+struct RawInput(InputValue);
+
+impl FromInputValue for RawInput {
+    fn from_input_value(value: &InputValue) -> Option<Self> {
+        Some(Self(value.clone()))
+    }
+}
+
+fn nullable(field_type: &FieldType) -> FieldType {
+    match field_type {
+        FieldType::Nullable(_) => field_type.clone(),
+        field_type => FieldType::Nullable(Box::new(field_type.clone())),
+    }
+}
+
+fn read_argument(arguments: &Arguments, name: &str) -> Json {
+    arguments
+        .get::<RawInput>(name)
+        .map(|RawInput(value)| to_json_input(&value))
+        .unwrap_or(Json::Null)
+}
+
+/// Attributes of `value` as JSON, the way the entity stores them
+fn to_json<T: Serialize>(value: &T) -> Result<Json, DynamoRepositoryError> {
+    from_attribute_map(to_attribute_map(value)?)
+}
+
+fn to_json_input(value: &InputValue) -> Json {
+    match value {
+        InputValue::Null | InputValue::Variable(_) => Json::Null,
+        InputValue::Scalar(DefaultScalarValue::Int(value)) => Json::from(*value),
+        InputValue::Scalar(DefaultScalarValue::Float(value)) => Json::from(*value),
+        InputValue::Scalar(DefaultScalarValue::String(value)) => Json::from(value.as_str()),
+        InputValue::Scalar(DefaultScalarValue::Boolean(value)) => Json::from(*value),
+        InputValue::Enum(value) => Json::from(value.as_str()),
+        InputValue::List(items) => items.iter().map(|item| to_json_input(&item.item)).collect(),
+        InputValue::Object(fields) => Json::Object(
+            fields
+                .iter()
+                .map(|(name, value)| (name.item.clone(), to_json_input(&value.item)))
+                .collect(),
+        ),
+    }
+}
+
+/// Value of an input field, with big ints as the numbers the entity deserializes
+fn from_field_input(json: Json, field_type: &FieldType) -> Json {
+    match (field_type, json) {
+        (FieldType::BigInt, Json::String(value)) => {
+            parse_big_int(&value).unwrap_or(Json::String(value))
+        }
+        (FieldType::List(of_type), Json::Array(items)) => items
+            .into_iter()
+            .map(|item| from_field_input(item, of_type))
+            .collect(),
+        (FieldType::Nullable(of_type), json) => from_field_input(json, of_type),
+        (_, json) => json,
+    }
+}
+
+/// Value of an entity field, with the numbers of `Float` fields as floats and of `BigInt` fields
+/// as decimal strings
+fn to_field_value(json: &Json, field_type: &FieldType) -> Value {
+    match (field_type, json) {
+        (FieldType::Float, Json::Number(number)) => {
+            Value::scalar(number.as_f64().unwrap_or_default())
+        }
+        (FieldType::BigInt, Json::Number(number)) => Value::scalar(number.to_string()),
+        (FieldType::List(of_type), Json::Array(items)) => Value::list(
+            items
+                .iter()
+                .map(|item| to_field_value(item, of_type))
+                .collect(),
+        ),
+        (FieldType::Nullable(of_type), json) => to_field_value(json, of_type),
+        (_, json) => to_value(json),
+    }
+}
+
+/// Numbers are ints when they fit, so 64-bit integers only lose precision beyond that
+fn to_value(json: &Json) -> Value {
+    match json {
+        Json::Null => Value::null(),
+        Json::Bool(value) => Value::scalar(*value),
+        Json::Number(number) => match number.as_i64().and_then(|n| i32::try_from(n).ok()) {
+            Some(number) => Value::scalar(number),
+            None => Value::scalar(number.as_f64().unwrap_or_default()),
+        },
+        Json::String(value) => Value::scalar(value.clone()),
+        Json::Array(items) => Value::list(items.iter().map(to_value).collect()),
+        Json::Object(fields) => Value::object(
+            fields
+                .iter()
+                .map(|(name, value)| (name.as_str(), to_value(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// The problem details of the error, as the extensions of a GraphQL error
+fn field_error(err: DynamoRepositoryError) -> FieldError {
+    let problem = Problem::from_error(&err);
+    let extensions = serde_json::to_value(&problem)
+        .map(|problem| to_value(&problem))
+        .unwrap_or_else(|_| Value::null());
+
+    FieldError::new(problem.detail, extensions)
+}
+
+fn unknown_field(field_name: &str) -> FieldError {
+    FieldError::new(format!("Unknown field {}", field_name), Value::null())
+}
+
+/// `Note` becomes `note`
+fn lower_camel_case(name: &str) -> String {
+    let mut chars = name.chars();
+
+    match chars.next() {
+        Some(first) => first.to_lowercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// `server::notes::NoteEntity` becomes `Note`
+fn type_prefix(type_name: &str) -> String {
+    let name = type_name.rsplit("::").next().unwrap_or(type_name);
+
+    match name.strip_suffix("Entity") {
+        Some(prefix) if !prefix.is_empty() => prefix.to_string(),
+        _ => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::executor::block_on;
+    use juniper::{DefaultScalarValue, Variables};
+    use serde::{Deserialize, Serialize};
+    use uuid::Uuid;
+
+    use crate::prelude::{
+        CrudSchema, CrudSchemaBuilder, CrudSchemaError, CrudService, CursorCodec, Entity,
+        InMemoryRepository,
+    };
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Entity)]
+    #[entity(pk = "BOOK", sk = "BOOK#{isbn}")]
+    struct BookEntity {
+        isbn: String,
+        title: String,
+        pages: u32,
+        tags: Vec<String>,
+        subtitle: Option<String>,
+        #[serde(default)]
+        copies_sold: Option<u64>,
+        #[entity(version)]
+        #[serde(default)]
+        version: u64,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Entity)]
+    #[entity(pk = "TICKET", sk = "TICKET#{id}")]
+    struct TicketEntity {
+        id: Uuid,
+        title: String,
+    }
+
+    struct BookService {
+        repository: InMemoryRepository<BookEntity>,
+    }
+
+    impl CrudService<BookEntity, InMemoryRepository<BookEntity>> for BookService {
+        fn get_repository(&self) -> &InMemoryRepository<BookEntity> {
+            &self.repository
+        }
+    }
+
+    struct TicketService {
+        repository: InMemoryRepository<TicketEntity>,
+    }
+
+    impl CrudService<TicketEntity, InMemoryRepository<TicketEntity>> for TicketService {
+        fn get_repository(&self) -> &InMemoryRepository<TicketEntity> {
+            &self.repository
+        }
+    }
+
+    fn books() -> BookService {
+        BookService {
+            repository: InMemoryRepository::new("books"),
+        }
+    }
+
+    fn schema() -> CrudSchema {
+        CrudSchemaBuilder::new()
+            .register(
+                books(),
+                "books",
+                BookPartitionKey::find_all(),
+                CursorCodec::new("test"),
+            )
+            .register(
+                TicketService {
+                    repository: InMemoryRepository::new("tickets"),
+                },
+                "tickets",
+                TicketPartitionKey::find_all(),
+                CursorCodec::new("test"),
+            )
+            .build()
+            .unwrap()
+    }
+
+    /// The data and the errors of the query
+    fn try_execute(schema: &CrudSchema, query: &str) -> (serde_json::Value, serde_json::Value) {
+        let (value, errors) = block_on(juniper::execute(
+            query,
+            None,
+            schema,
+            &Variables::<DefaultScalarValue>::new(),
+            &(),
+        ))
+        .unwrap();
+
+        (
+            serde_json::to_value(value).unwrap(),
+            serde_json::to_value(errors).unwrap(),
+        )
+    }
+
+    fn execute(schema: &CrudSchema, query: &str) -> serde_json::Value {
+        let (value, errors) = try_execute(schema, query);
+
+        assert_eq!(errors, serde_json::json!([]));
+        value
+    }
+
+    #[test]
+    fn it_creates_and_reads_entities() {
+        let schema = schema();
+
+        let created = execute(
+            &schema,
+            r#"mutation {
+                createBook(input: {isbn: "1", title: "Dune", pages: 412, tags: ["sf"]}) {
+                    isbn title version
+                }
+            }"#,
+        );
+        let found = execute(
+            &schema,
+            r#"{ book(isbn: "1") { title pages tags subtitle } }"#,
+        );
+        let page = execute(&schema, "{ books(limit: 10) { items { isbn } cursor } }");
+
+        assert_eq!(created["createBook"]["version"], "1");
+        assert_eq!(found["book"]["pages"], "412");
+        assert_eq!(found["book"]["tags"][0], "sf");
+        assert_eq!(found["book"]["subtitle"], serde_json::Value::Null);
+        assert_eq!(page["books"]["items"][0]["isbn"], "1");
+        assert_eq!(
+            execute(&schema, r#"{ book(isbn: "2") { title } }"#)["book"],
+            serde_json::Value::Null
+        );
+    }
+
+    #[test]
+    fn it_updates_and_deletes_entities() {
+        let schema = schema();
+
+        execute(
+            &schema,
+            r#"mutation { createBook(input: {isbn: "1", title: "Dune", pages: 412, tags: []}) { isbn } }"#,
+        );
+        let updated = execute(
+            &schema,
+            r#"mutation {
+                updateBook(isbn: "1", version: 1, patch: {subtitle: "Part one"}) {
+                    title subtitle version
+                }
+            }"#,
+        );
+        let deleted = execute(&schema, r#"mutation { deleteBook(isbn: "1") { title } }"#);
+        let found = execute(&schema, r#"{ book(isbn: "1") { title } }"#);
+
+        assert_eq!(updated["updateBook"]["title"], "Dune");
+        assert_eq!(updated["updateBook"]["subtitle"], "Part one");
+        assert_eq!(updated["updateBook"]["version"], "2");
+        assert_eq!(deleted["deleteBook"]["title"], "Dune");
+        assert_eq!(found["book"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn it_rejects_updates_of_stale_versions() {
+        let schema = schema();
+
+        execute(
+            &schema,
+            r#"mutation { createBook(input: {isbn: "1", title: "Dune", pages: 412, tags: []}) { isbn } }"#,
+        );
+        execute(
+            &schema,
+            r#"mutation { updateBook(isbn: "1", version: 1, patch: {pages: 500}) { isbn } }"#,
+        );
+        let (_, errors) = try_execute(
+            &schema,
+            r#"mutation { updateBook(isbn: "1", version: 1, patch: {pages: 600}) { isbn } }"#,
+        );
+        let found = execute(&schema, r#"{ book(isbn: "1") { pages version } }"#);
+
+        assert_eq!(errors[0]["extensions"]["status"], 409);
+        assert_eq!(found["book"]["pages"], "500");
+        assert_eq!(found["book"]["version"], "2");
+    }
+
+    #[test]
+    fn it_rejects_duplicate_names() {
+        let list_named_like_get = CrudSchemaBuilder::new()
+            .register(
+                books(),
+                "book",
+                BookPartitionKey::find_all(),
+                CursorCodec::new("test"),
+            )
+            .build();
+        let registered_twice = CrudSchemaBuilder::new()
+            .register(
+                books(),
+                "books",
+                BookPartitionKey::find_all(),
+                CursorCodec::new("test"),
+            )
+            .register(
+                books(),
+                "allBooks",
+                BookPartitionKey::find_all(),
+                CursorCodec::new("test"),
+            )
+            .build();
+
+        assert!(matches!(
+            list_named_like_get,
+            Err(CrudSchemaError::DuplicateName(name)) if name == "book"
+        ));
+        assert!(matches!(
+            registered_twice,
+            Err(CrudSchemaError::DuplicateName(name)) if name == "Book"
+        ));
+    }
+
+    #[test]
+    fn it_keeps_64_bit_integers_exact() {
+        let schema = schema();
+
+        execute(
+            &schema,
+            r#"mutation {
+                createBook(input: {isbn: "1", title: "Dune", pages: 412, tags: []}) { isbn }
+            }"#,
+        );
+        // 2^53 + 1, which a float rounds to 2^53
+        let updated = execute(
+            &schema,
+            r#"mutation {
+                updateBook(isbn: "1", version: "1", patch: {copies_sold: "9007199254740993"}) {
+                    copies_sold
+                }
+            }"#,
+        );
+        let found = execute(&schema, r#"{ book(isbn: "1") { copies_sold } }"#);
+
+        assert_eq!(updated["updateBook"]["copies_sold"], "9007199254740993");
+        assert_eq!(found["book"]["copies_sold"], "9007199254740993");
+    }
+
+    #[test]
+    fn it_generates_uuid_keys_left_out_of_the_input() {
+        let schema = schema();
+
+        let generated = execute(
+            &schema,
+            r#"mutation { createTicket(input: {title: "Flaky test"}) { id title } }"#,
+        );
+        let given = execute(
+            &schema,
+            r#"mutation {
+                createTicket(input: {id: "67e55044-10b1-426f-9247-bb680e5fe0c8", title: "Typo"}) {
+                    id
+                }
+            }"#,
+        );
+
+        let id = generated["createTicket"]["id"].as_str().unwrap();
+        let found = execute(
+            &schema,
+            &format!(r#"{{ ticket(id: "{}") {{ title }} }}"#, id),
+        );
+
+        assert!(Uuid::parse_str(id).is_ok());
+        assert_eq!(found["ticket"]["title"], "Flaky test");
+        assert_eq!(
+            given["createTicket"]["id"],
+            "67e55044-10b1-426f-9247-bb680e5fe0c8"
+        );
+    }
+}
@@ -1,9 +1,33 @@
+
+// Lets the code generated by `orm-derive` refer to `::orm` from within this crate as well
+extern crate self as orm;
+
+pub mod graphql;
 pub mod repository;
 pub mod server;
 pub mod service;
 
 pub mod prelude {
+    pub use crate::graphql::*;
     pub use crate::repository::repository::*;
+    pub use crate::repository::attributes::*;
+    pub use crate::repository::batch::{BatchWriteOperation, BATCH_GET_LIMIT, BATCH_WRITE_LIMIT};
+    pub use crate::repository::cache::*;
+    pub use crate::repository::cursor::*;
     pub use crate::repository::entity::*;
+    pub use crate::repository::expression::*;
+    pub use crate::repository::instrument::METRICS_TARGET;
+    pub use crate::repository::key_condition::*;
+    pub use crate::repository::memory::*;
+    pub use crate::repository::retry::*;
+    pub use crate::repository::scan::*;
+    pub use crate::repository::stream::*;
+    pub use crate::repository::table::*;
+    pub use crate::repository::transaction::*;
+    pub use crate::repository::ttl::{epoch_seconds, epoch_seconds_option};
+    pub use crate::repository::update::*;
+    pub use crate::repository::view::*;
+    pub use crate::service::hooks::*;
     pub use crate::service::*;
+    pub use orm_derive::{Entity, EntityEnum, View};
 }
@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::types::AttributeValue;
+
+/// Hands out `#name` and `:value` placeholders while an expression is being built, so attribute
+/// names never clash with DynamoDB reserved words and values never have to be inlined.
+#[derive(Debug, Clone, Default)]
+pub struct ExpressionAttributes {
+    names: HashMap<String, String>,
+    values: HashMap<String, AttributeValue>,
+}
+
+impl ExpressionAttributes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Placeholder for an attribute name. Document paths such as `address.city` or `tags[0]`
+    /// get a placeholder per segment.
+    pub fn name(&mut self, path: &str) -> String {
+        path.split('.')
+            .map(|segment| {
+                let (attribute, list_index) = match segment.find('[') {
+                    Some(position) => segment.split_at(position),
+                    None => (segment, ""),
+                };
+
+                format!("{}{}", self.name_placeholder(attribute), list_index)
+            })
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+
+    /// `ProjectionExpression` reading the given attributes, or document paths
+    pub fn projection<P: AsRef<str>>(&mut self, paths: &[P]) -> String {
+        paths
+            .iter()
+            .map(|path| self.name(path.as_ref()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Placeholder for a value. Every call gets a fresh placeholder.
+    pub fn value(&mut self, value: AttributeValue) -> String {
+        let placeholder = format!(":v{}", self.values.len());
+
+        self.values.insert(placeholder.clone(), value);
+
+        placeholder
+    }
+
+    /// `ExpressionAttributeNames`, or `None` when no names were used, as DynamoDB rejects empty
+    /// maps
+    pub fn names(&self) -> Option<HashMap<String, String>> {
+        Some(self.names.clone()).filter(|names| !names.is_empty())
+    }
+
+    /// `ExpressionAttributeValues`, or `None` when no values were used, as DynamoDB rejects
+    /// empty maps
+    pub fn values(&self) -> Option<HashMap<String, AttributeValue>> {
+        Some(self.values.clone()).filter(|values| !values.is_empty())
+    }
+
+    fn name_placeholder(&mut self, attribute: &str) -> String {
+        if let Some((placeholder, _)) = self.names.iter().find(|(_, name)| *name == attribute) {
+            return placeholder.clone();
+        }
+
+        let placeholder = format!("#n{}", self.names.len());
+
+        self.names
+            .insert(placeholder.clone(), attribute.to_string());
+
+        placeholder
+    }
+}
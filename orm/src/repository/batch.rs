@@ -0,0 +1,375 @@
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+
+use aws_sdk_dynamodb::operation::batch_get_item::BatchGetItemOutput;
+use aws_sdk_dynamodb::operation::batch_write_item::BatchWriteItemOutput;
+use aws_sdk_dynamodb::types::{
+    AttributeValue, DeleteRequest, KeysAndAttributes, PutRequest, ReturnConsumedCapacity,
+    WriteRequest,
+};
+use futures::{StreamExt, TryFutureExt, TryStreamExt};
+
+use crate::repository::instrument::Operation;
+use crate::repository::memory::canonical_key;
+use crate::repository::repository::DynamoRepositoryError;
+use crate::repository::retry::{sleep, RetryPolicy};
+
+/// Maximum number of keys of a single `BatchGetItem` call
+pub const BATCH_GET_LIMIT: usize = 100;
+/// Maximum number of items of a single `BatchWriteItem` call
+pub const BATCH_WRITE_LIMIT: usize = 25;
+/// Number of chunks sent to DynamoDB at the same time
+const BATCH_CONCURRENCY: usize = 8;
+/// Attempts per chunk before the unprocessed keys or items are given up on
+const MAX_BATCH_ATTEMPTS: u32 = 10;
+
+type Item = HashMap<String, AttributeValue>;
+
+/// A single operation of a batch write. Batch writes don't support condition expressions, so
+/// versioned entities get their version bumped without being checked.
+#[derive(Debug, Clone)]
+pub enum BatchWriteOperation<E> {
+    Put(E),
+    Delete(E),
+}
+
+/// Reads the items in chunks of [`BATCH_GET_LIMIT`], retrying unprocessed keys with backoff.
+/// Items that don't exist are left out, and the order of the result is unspecified. Duplicate
+/// keys are read once, as DynamoDB rejects batches holding the same key twice.
+pub(crate) async fn batch_get_items(
+    client: &aws_sdk_dynamodb::Client,
+    table_name: &str,
+    retry_policy: &RetryPolicy,
+    keys: Vec<Item>,
+) -> Result<Vec<Item>, DynamoRepositoryError> {
+    get_items(table_name, retry_policy, keys, |keys| {
+        client
+            .batch_get_item()
+            .return_consumed_capacity(ReturnConsumedCapacity::Total)
+            .request_items(table_name, keys)
+            .send()
+            .map_err(DynamoRepositoryError::from)
+    })
+    .await
+}
+
+/// Writes the requests in chunks of [`BATCH_WRITE_LIMIT`], retrying unprocessed items with
+/// backoff. Unprocessed items count as throttling for the rate limiter of `retry_policy`. The
+/// requests come with the primary key they write, and only the last request of every key is
+/// sent, as DynamoDB rejects batches holding the same key twice.
+pub(crate) async fn batch_write_items(
+    client: &aws_sdk_dynamodb::Client,
+    table_name: &str,
+    retry_policy: &RetryPolicy,
+    requests: Vec<(Item, WriteRequest)>,
+) -> Result<(), DynamoRepositoryError> {
+    write_items(table_name, retry_policy, requests, |requests| {
+        client
+            .batch_write_item()
+            .return_consumed_capacity(ReturnConsumedCapacity::Total)
+            .request_items(table_name, requests)
+            .send()
+            .map_err(DynamoRepositoryError::from)
+    })
+    .await
+}
+
+/// [`batch_get_items`], sending every `BatchGetItem` request through `send`
+async fn get_items<F, Fut>(
+    table_name: &str,
+    retry_policy: &RetryPolicy,
+    keys: Vec<Item>,
+    send: F,
+) -> Result<Vec<Item>, DynamoRepositoryError>
+where
+    F: Fn(KeysAndAttributes) -> Fut,
+    Fut: Future<Output = Result<BatchGetItemOutput, DynamoRepositoryError>>,
+{
+    let keys = dedup_by_key(keys.into_iter().map(|key| (key.clone(), key)).collect());
+    let chunks: Vec<Vec<Item>> = keys
+        .chunks(BATCH_GET_LIMIT)
+        .map(|chunk| chunk.to_vec())
+        .collect();
+
+    let results: Vec<Vec<Item>> = futures::stream::iter(chunks)
+        .map(|chunk| batch_get_chunk(table_name, retry_policy, chunk, &send))
+        .buffer_unordered(BATCH_CONCURRENCY)
+        .try_collect()
+        .await?;
+
+    Ok(results.into_iter().flatten().collect())
+}
+
+/// [`batch_write_items`], sending every `BatchWriteItem` request through `send`
+async fn write_items<F, Fut>(
+    table_name: &str,
+    retry_policy: &RetryPolicy,
+    requests: Vec<(Item, WriteRequest)>,
+    send: F,
+) -> Result<(), DynamoRepositoryError>
+where
+    F: Fn(Vec<WriteRequest>) -> Fut,
+    Fut: Future<Output = Result<BatchWriteItemOutput, DynamoRepositoryError>>,
+{
+    let requests = dedup_by_key(requests);
+    let chunks: Vec<Vec<WriteRequest>> = requests
+        .chunks(BATCH_WRITE_LIMIT)
+        .map(|chunk| chunk.to_vec())
+        .collect();
+
+    futures::stream::iter(chunks)
+        .map(|chunk| batch_write_chunk(table_name, retry_policy, chunk, &send))
+        .buffer_unordered(BATCH_CONCURRENCY)
+        .try_collect::<Vec<()>>()
+        .await?;
+
+    Ok(())
+}
+
+/// Keeps the last entry of every key, in the order of the entries
+fn dedup_by_key<T>(entries: Vec<(Item, T)>) -> Vec<T> {
+    let mut seen = HashSet::new();
+    let mut deduped: Vec<T> = entries
+        .into_iter()
+        .rev()
+        .filter(|(key, _)| seen.insert(canonical_key(key)))
+        .map(|(_, entry)| entry)
+        .collect();
+
+    deduped.reverse();
+    deduped
+}
+
+pub(crate) fn put_request(item: Item) -> Result<WriteRequest, DynamoRepositoryError> {
+    Ok(WriteRequest::builder()
+        .put_request(PutRequest::builder().set_item(Some(item)).build()?)
+        .build())
+}
+
+pub(crate) fn delete_request(key: Item) -> Result<WriteRequest, DynamoRepositoryError> {
+    Ok(WriteRequest::builder()
+        .delete_request(DeleteRequest::builder().set_key(Some(key)).build()?)
+        .build())
+}
+
+async fn batch_get_chunk<F, Fut>(
+    table_name: &str,
+    retry_policy: &RetryPolicy,
+    keys: Vec<Item>,
+    send: &F,
+) -> Result<Vec<Item>, DynamoRepositoryError>
+where
+    F: Fn(KeysAndAttributes) -> Fut,
+    Fut: Future<Output = Result<BatchGetItemOutput, DynamoRepositoryError>>,
+{
+    let mut items = Vec::new();
+    let mut pending = KeysAndAttributes::builder().set_keys(Some(keys)).build()?;
+    let mut attempt = 0;
+
+    loop {
+        let output = Operation::new("BatchGetItem", table_name)
+            .run(retry_policy, || send(pending.clone()))
+            .await?;
+
+        items.extend(
+            output
+                .responses
+                .and_then(|mut responses| responses.remove(table_name))
+                .unwrap_or_default(),
+        );
+
+        match output
+            .unprocessed_keys
+            .and_then(|mut unprocessed| unprocessed.remove(table_name))
+            .filter(|unprocessed| !unprocessed.keys.is_empty())
+        {
+            Some(unprocessed) => {
+                retry_policy.on_throttle();
+                attempt += 1;
+
+                if attempt >= MAX_BATCH_ATTEMPTS {
+                    return Err(DynamoRepositoryError::UnprocessedItemsError(
+                        unprocessed.keys.len(),
+                    ));
+                }
+
+                sleep(retry_policy.backoff(attempt)).await;
+                pending = unprocessed;
+            }
+            None => return Ok(items),
+        }
+    }
+}
+
+async fn batch_write_chunk<F, Fut>(
+    table_name: &str,
+    retry_policy: &RetryPolicy,
+    requests: Vec<WriteRequest>,
+    send: &F,
+) -> Result<(), DynamoRepositoryError>
+where
+    F: Fn(Vec<WriteRequest>) -> Fut,
+    Fut: Future<Output = Result<BatchWriteItemOutput, DynamoRepositoryError>>,
+{
+    let mut pending = requests;
+    let mut attempt = 0;
+
+    loop {
+        let output = Operation::new("BatchWriteItem", table_name)
+            .with_items(pending.len())
+            .run(retry_policy, || send(pending.clone()))
+            .await?;
+
+        match output
+            .unprocessed_items
+            .and_then(|mut unprocessed| unprocessed.remove(table_name))
+            .filter(|unprocessed| !unprocessed.is_empty())
+        {
+            Some(unprocessed) => {
+                retry_policy.on_throttle();
+                attempt += 1;
+
+                if attempt >= MAX_BATCH_ATTEMPTS {
+                    return Err(DynamoRepositoryError::UnprocessedItemsError(
+                        unprocessed.len(),
+                    ));
+                }
+
+                sleep(retry_policy.backoff(attempt)).await;
+                pending = unprocessed;
+            }
+            None => return Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use aws_sdk_dynamodb::operation::batch_get_item::BatchGetItemOutput;
+    use aws_sdk_dynamodb::operation::batch_write_item::BatchWriteItemOutput;
+    use aws_sdk_dynamodb::types::{AttributeValue, KeysAndAttributes};
+    use futures::executor::block_on;
+
+    use crate::prelude::{DynamoRepositoryError, RetryPolicy};
+    use crate::repository::batch::{
+        get_items, put_request, write_items, Item, BATCH_GET_LIMIT, MAX_BATCH_ATTEMPTS,
+    };
+
+    fn key(id: usize) -> Item {
+        HashMap::from([("pk".to_string(), AttributeValue::S(format!("ITEM#{}", id)))])
+    }
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy::default().with_backoff(Duration::ZERO, Duration::ZERO)
+    }
+
+    #[test]
+    fn it_reads_unique_keys_in_chunks() {
+        let sizes = Mutex::new(Vec::new());
+        let keys = (0..210).chain(0..40).map(key).collect();
+
+        let items = block_on(get_items(
+            "items",
+            &policy(),
+            keys,
+            |keys: KeysAndAttributes| {
+                sizes.lock().unwrap().push(keys.keys.len());
+
+                async move {
+                    Ok(BatchGetItemOutput::builder()
+                        .responses("items", keys.keys)
+                        .build())
+                }
+            },
+        ))
+        .unwrap();
+
+        let mut sizes = sizes.into_inner().unwrap();
+        sizes.sort();
+
+        assert_eq!(items.len(), 210);
+        assert_eq!(sizes, vec![10, BATCH_GET_LIMIT, BATCH_GET_LIMIT]);
+    }
+
+    #[test]
+    fn it_retries_unprocessed_keys() {
+        let calls = Mutex::new(0);
+        let keys = (0..10).map(key).collect();
+
+        let items = block_on(get_items(
+            "items",
+            &policy(),
+            keys,
+            |keys: KeysAndAttributes| {
+                *calls.lock().unwrap() += 1;
+
+                // Processes the first half of the keys it gets
+                let (processed, unprocessed) = keys.keys.split_at(keys.keys.len().div_ceil(2));
+                let mut output =
+                    BatchGetItemOutput::builder().responses("items", processed.to_vec());
+
+                if !unprocessed.is_empty() {
+                    output = output.unprocessed_keys(
+                        "items",
+                        KeysAndAttributes::builder()
+                            .set_keys(Some(unprocessed.to_vec()))
+                            .build()
+                            .unwrap(),
+                    );
+                }
+
+                async move { Ok(output.build()) }
+            },
+        ))
+        .unwrap();
+
+        assert_eq!(items.len(), 10);
+        assert_eq!(calls.into_inner().unwrap(), 4);
+    }
+
+    #[test]
+    fn it_writes_the_last_request_of_every_key() {
+        let sent = Mutex::new(Vec::new());
+        let requests = (0..50)
+            .chain(0..10)
+            .map(|id| (key(id), put_request(key(id)).unwrap()))
+            .collect();
+
+        block_on(write_items("items", &policy(), requests, |requests| {
+            sent.lock().unwrap().push(requests.len());
+
+            async { Ok(BatchWriteItemOutput::builder().build()) }
+        }))
+        .unwrap();
+
+        assert_eq!(sent.into_inner().unwrap(), vec![25, 25]);
+    }
+
+    #[test]
+    fn it_gives_up_on_unprocessed_items() {
+        let calls = Mutex::new(0);
+        let requests = (0..3)
+            .map(|id| (key(id), put_request(key(id)).unwrap()))
+            .collect();
+
+        let result = block_on(write_items("items", &policy(), requests, |requests| {
+            *calls.lock().unwrap() += 1;
+
+            async move {
+                Ok(BatchWriteItemOutput::builder()
+                    .unprocessed_items("items", requests)
+                    .build())
+            }
+        }));
+
+        assert!(matches!(
+            result,
+            Err(DynamoRepositoryError::UnprocessedItemsError(3))
+        ));
+        assert_eq!(calls.into_inner().unwrap(), MAX_BATCH_ATTEMPTS);
+    }
+}
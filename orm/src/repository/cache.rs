@@ -0,0 +1,715 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use aws_sdk_dynamodb::operation::delete_item::DeleteItemOutput;
+use serde::Serialize;
+
+use crate::repository::batch::BatchWriteOperation;
+use crate::repository::entity::{AttributeMap, Entity, EntityEnum};
+use crate::repository::expression::Expression;
+use crate::repository::memory::canonical_key;
+use crate::repository::repository::{
+    DynamoRepository, DynamoRepositoryError, QueryData, QueryResult, RepositoryIndex,
+};
+use crate::repository::retry::RetryPolicy;
+use crate::repository::scan::ScanData;
+use crate::repository::table::TableDefinition;
+use crate::repository::transaction::TransactionTarget;
+use crate::repository::update::Update;
+use crate::repository::view::View;
+
+/// Number of generation counters the cache keys of a [`CachedRepository`] are spread over
+const GENERATION_SLOTS: usize = 1024;
+
+/// Store of the items a [`CachedRepository`] read, keyed by table and primary key. Implement it
+/// to cache in an external store, failing lookups should just miss.
+#[async_trait::async_trait]
+pub trait ItemCache: Send + Sync {
+    async fn get(&self, key: &str) -> Option<AttributeMap>;
+    async fn put(&self, key: &str, item: AttributeMap);
+    async fn remove(&self, key: &str);
+}
+
+/// In-process cache keeping the most recently used items, each for at most the time to live
+pub struct LruCache {
+    capacity: usize,
+    ttl: Option<Duration>,
+    state: Mutex<LruState>,
+}
+
+#[derive(Default)]
+struct LruState {
+    entries: HashMap<String, LruEntry>,
+    /// Keys by the tick they were last used at, the least recently used first
+    recency: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+struct LruEntry {
+    item: AttributeMap,
+    cached_at: Instant,
+    used_at: u64,
+}
+
+impl LruCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            ttl: None,
+            state: Mutex::new(LruState::default()),
+        }
+    }
+
+    /// Drops items this long after they were cached, so writes of other processes show up
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().entries.is_empty()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, LruState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl LruState {
+    fn touch(&mut self, key: &str) {
+        self.tick += 1;
+
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.recency.remove(&entry.used_at);
+            entry.used_at = self.tick;
+            self.recency.insert(self.tick, key.to_string());
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.used_at);
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl ItemCache for LruCache {
+    async fn get(&self, key: &str) -> Option<AttributeMap> {
+        let mut state = self.lock();
+        let cached_at = state.entries.get(key)?.cached_at;
+
+        if self.ttl.is_some_and(|ttl| cached_at.elapsed() >= ttl) {
+            state.remove(key);
+            return None;
+        }
+
+        state.touch(key);
+        state.entries.get(key).map(|entry| entry.item.clone())
+    }
+
+    async fn put(&self, key: &str, item: AttributeMap) {
+        let mut state = self.lock();
+
+        state.remove(key);
+        state.entries.insert(
+            key.to_string(),
+            LruEntry {
+                item,
+                cached_at: Instant::now(),
+                used_at: 0,
+            },
+        );
+        state.touch(key);
+
+        while state.entries.len() > self.capacity {
+            match state.recency.pop_first() {
+                Some((_, evicted)) => {
+                    state.entries.remove(&evicted);
+                }
+                None => break,
+            }
+        }
+    }
+
+    async fn remove(&self, key: &str) {
+        self.lock().remove(key);
+    }
+}
+
+/// Hits and misses of the lookups of a [`CachedRepository`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Repository serving `find`, `get` and `batch_get` from a cache before reading through to the
+/// wrapped repository. Writes through the repository invalidate the items they touch, writes of
+/// transactions or other processes only show up once the cached items expire.
+///
+/// ```ignore
+/// let repository = CachedRepository::new(
+///     DynamoNotesRepository::new(client),
+///     LruCache::new(1_000).with_ttl(Duration::from_secs(60)),
+/// );
+/// ```
+pub struct CachedRepository<E, R> {
+    repository: R,
+    cache: Arc<dyn ItemCache>,
+    /// Bumped when the items of the cache keys hashed to them are invalidated, so reads that
+    /// started before don't cache what they read. Keys sharing a counter only skip caching.
+    generations: Arc<Vec<AtomicU64>>,
+    hits: Arc<AtomicU64>,
+    misses: Arc<AtomicU64>,
+    entity: PhantomData<fn() -> E>,
+}
+
+impl<E, R: Clone> Clone for CachedRepository<E, R> {
+    fn clone(&self) -> Self {
+        Self {
+            repository: self.repository.clone(),
+            cache: self.cache.clone(),
+            generations: self.generations.clone(),
+            hits: self.hits.clone(),
+            misses: self.misses.clone(),
+            entity: PhantomData,
+        }
+    }
+}
+
+impl<E, R> CachedRepository<E, R>
+where
+    E: Entity,
+    E::PrimaryKey: Serialize,
+    E::IndexFields: Serialize,
+    R: DynamoRepository<E>,
+{
+    pub fn new(repository: R, cache: impl ItemCache + 'static) -> Self {
+        Self {
+            repository,
+            cache: Arc::new(cache),
+            generations: Arc::new((0..GENERATION_SLOTS).map(|_| AtomicU64::new(0)).collect()),
+            hits: Arc::new(AtomicU64::new(0)),
+            misses: Arc::new(AtomicU64::new(0)),
+            entity: PhantomData,
+        }
+    }
+
+    pub fn get_repository(&self) -> &R {
+        &self.repository
+    }
+
+    pub fn get_stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Drops the cached item at `index`, e.g. after writing it in a transaction
+    pub async fn invalidate<Index: RepositoryIndex>(
+        &self,
+        index: Index,
+    ) -> Result<(), DynamoRepositoryError> {
+        self.evict(&self.cache_key(&index.to_key()?)).await;
+
+        Ok(())
+    }
+
+    fn cache_key(&self, key: &AttributeMap) -> String {
+        format!("{}\u{1e}{}", self.repository.get_table_name(), canonical_key(key))
+    }
+
+    /// The cached entity, or `None` when it isn't cached or expired since
+    async fn cached(&self, cache_key: &str) -> Result<Option<E>, DynamoRepositoryError> {
+        let entity = match self.cache.get(cache_key).await {
+            Some(item) if !E::is_expired(&item) => Some(E::from_attribute_values(item)?),
+            _ => None,
+        };
+
+        match entity {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+
+        Ok(entity)
+    }
+
+    fn generation(&self, cache_key: &str) -> &AtomicU64 {
+        let mut hasher = DefaultHasher::new();
+        cache_key.hash(&mut hasher);
+
+        &self.generations[hasher.finish() as usize % self.generations.len()]
+    }
+
+    /// Caches `entity`, read when the generation of `cache_key` was `generation`. The entity is
+    /// dropped again when the key was invalidated since, as it may have been read before a
+    /// write, and the invalidation may have landed before the put.
+    async fn cache(
+        &self,
+        cache_key: &str,
+        entity: &E,
+        generation: u64,
+    ) -> Result<(), DynamoRepositoryError> {
+        self.cache
+            .put(cache_key, entity.serialize_with_indexes()?)
+            .await;
+
+        if self.generation(cache_key).load(Ordering::SeqCst) != generation {
+            self.cache.remove(cache_key).await;
+        }
+
+        Ok(())
+    }
+
+    /// Drops the cached item at `cache_key`, bumping its generation first so reads in flight
+    /// don't cache it again
+    async fn evict(&self, cache_key: &str) {
+        self.generation(cache_key).fetch_add(1, Ordering::SeqCst);
+        self.cache.remove(cache_key).await;
+    }
+
+    /// Runs `write`, then evicts the cached items at `cache_keys` whether it succeeded or not.
+    /// Evicting them only once the write returned keeps a read racing it from caching the item
+    /// as it was before.
+    async fn invalidating<T>(
+        &self,
+        cache_keys: Vec<String>,
+        write: impl Future<Output = Result<T, DynamoRepositoryError>>,
+    ) -> Result<T, DynamoRepositoryError> {
+        let result = write.await;
+
+        for cache_key in &cache_keys {
+            self.evict(cache_key).await;
+        }
+
+        result
+    }
+
+    fn entity_cache_key(&self, entity: &E) -> Result<String, DynamoRepositoryError> {
+        Ok(self.cache_key(&entity.serialize_primary_key()?))
+    }
+}
+
+#[async_trait::async_trait]
+impl<E, R> DynamoRepository<E> for CachedRepository<E, R>
+where
+    E: Entity,
+    E::PrimaryKey: Serialize,
+    E::IndexFields: Serialize,
+    R: DynamoRepository<E>,
+{
+    fn get_table_name(&self) -> &'static str {
+        self.repository.get_table_name()
+    }
+
+    fn get_client(&self) -> &'_ aws_sdk_dynamodb::Client {
+        self.repository.get_client()
+    }
+
+    fn get_table_definition(&self) -> TableDefinition {
+        self.repository.get_table_definition()
+    }
+
+    fn get_retry_policy(&self) -> RetryPolicy {
+        self.repository.get_retry_policy()
+    }
+
+    fn get_transaction_target(&self) -> TransactionTarget {
+        self.repository.get_transaction_target()
+    }
+
+    async fn ensure_table(&self) -> Result<(), DynamoRepositoryError> {
+        self.repository.ensure_table().await
+    }
+
+    async fn create(&self, item: E) -> Result<E, DynamoRepositoryError> {
+        let cache_key = self.entity_cache_key(&item)?;
+
+        self.invalidating(vec![cache_key], self.repository.create(item))
+            .await
+    }
+
+    async fn upsert(&self, item: E) -> Result<E, DynamoRepositoryError> {
+        let cache_key = self.entity_cache_key(&item)?;
+
+        self.invalidating(vec![cache_key], self.repository.upsert(item))
+            .await
+    }
+
+    async fn upsert_with_condition(
+        &self,
+        item: E,
+        condition: Expression,
+    ) -> Result<E, DynamoRepositoryError> {
+        let cache_key = self.entity_cache_key(&item)?;
+
+        self.invalidating(
+            vec![cache_key],
+            self.repository.upsert_with_condition(item, condition),
+        )
+        .await
+    }
+
+    async fn delete(&self, item: E) -> Result<DeleteItemOutput, DynamoRepositoryError> {
+        let cache_key = self.entity_cache_key(&item)?;
+
+        self.invalidating(vec![cache_key], self.repository.delete(item))
+            .await
+    }
+
+    async fn delete_with_condition(
+        &self,
+        item: E,
+        condition: Expression,
+    ) -> Result<DeleteItemOutput, DynamoRepositoryError> {
+        let cache_key = self.entity_cache_key(&item)?;
+
+        self.invalidating(
+            vec![cache_key],
+            self.repository.delete_with_condition(item, condition),
+        )
+        .await
+    }
+
+    async fn update<Index: RepositoryIndex>(
+        &self,
+        index: Index,
+        update: Update,
+    ) -> Result<E, DynamoRepositoryError> {
+        let cache_key = self.cache_key(&index.to_key()?);
+
+        self.invalidating(vec![cache_key], self.repository.update(index, update))
+            .await
+    }
+
+    async fn update_with_condition<Index: RepositoryIndex>(
+        &self,
+        index: Index,
+        update: Update,
+        condition: Expression,
+    ) -> Result<E, DynamoRepositoryError> {
+        let cache_key = self.cache_key(&index.to_key()?);
+
+        self.invalidating(
+            vec![cache_key],
+            self.repository
+                .update_with_condition(index, update, condition),
+        )
+        .await
+    }
+
+    async fn find<Index: RepositoryIndex>(
+        &self,
+        index: Index,
+    ) -> Result<Option<E>, DynamoRepositoryError> {
+        let cache_key = self.cache_key(&index.to_key()?);
+
+        if let Some(entity) = self.cached(&cache_key).await? {
+            return Ok(Some(entity));
+        }
+
+        let generation = self.generation(&cache_key).load(Ordering::SeqCst);
+        let entity = self.repository.find(index).await?;
+
+        if let Some(entity) = &entity {
+            self.cache(&cache_key, entity, generation).await?;
+        }
+
+        Ok(entity)
+    }
+
+    async fn find_view<V: View<Entity = E>, Index: RepositoryIndex>(
+        &self,
+        index: Index,
+    ) -> Result<Option<V>, DynamoRepositoryError> {
+        self.repository.find_view(index).await
+    }
+
+    /// Only reads the items that aren't cached from the wrapped repository
+    async fn batch_get<Index: RepositoryIndex>(
+        &self,
+        indexes: Vec<Index>,
+    ) -> Result<Vec<E>, DynamoRepositoryError> {
+        let mut entities = Vec::with_capacity(indexes.len());
+        let mut uncached = Vec::new();
+        let mut generations = HashMap::new();
+
+        for index in indexes {
+            let cache_key = self.cache_key(&index.to_key()?);
+
+            match self.cached(&cache_key).await? {
+                Some(entity) => entities.push(entity),
+                None => {
+                    let generation = self.generation(&cache_key).load(Ordering::SeqCst);
+                    generations.insert(cache_key, generation);
+                    uncached.push(index);
+                }
+            }
+        }
+
+        if !uncached.is_empty() {
+            for entity in self.repository.batch_get(uncached).await? {
+                let cache_key = self.entity_cache_key(&entity)?;
+
+                if let Some(&generation) = generations.get(&cache_key) {
+                    self.cache(&cache_key, &entity, generation).await?;
+                }
+
+                entities.push(entity);
+            }
+        }
+
+        Ok(entities)
+    }
+
+    async fn batch_write(
+        &self,
+        operations: Vec<BatchWriteOperation<E>>,
+    ) -> Result<(), DynamoRepositoryError> {
+        let cache_keys = operations
+            .iter()
+            .map(|operation| match operation {
+                BatchWriteOperation::Put(item) | BatchWriteOperation::Delete(item) => {
+                    self.entity_cache_key(item)
+                }
+            })
+            .collect::<Result<_, _>>()?;
+
+        self.invalidating(cache_keys, self.repository.batch_write(operations))
+            .await
+    }
+
+    async fn query<Index: RepositoryIndex>(
+        &self,
+        query_data: QueryData<Index>,
+    ) -> Result<QueryResult<E>, DynamoRepositoryError> {
+        self.repository.query(query_data).await
+    }
+
+    async fn query_view<V: View<Entity = E>, Index: RepositoryIndex>(
+        &self,
+        query_data: QueryData<Index>,
+    ) -> Result<QueryResult<V>, DynamoRepositoryError> {
+        self.repository.query_view(query_data).await
+    }
+
+    async fn query_collection<T: EntityEnum, Index: RepositoryIndex>(
+        &self,
+        query_data: QueryData<Index>,
+    ) -> Result<QueryResult<T>, DynamoRepositoryError> {
+        self.repository.query_collection(query_data).await
+    }
+
+    async fn scan(&self, scan_data: ScanData) -> Result<QueryResult<E>, DynamoRepositoryError> {
+        self.repository.scan(scan_data).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use futures::channel::oneshot;
+    use futures::executor::block_on;
+    use futures::future::join;
+    use serde::{Deserialize, Serialize};
+
+    use crate::prelude::{
+        CacheStats, CachedRepository, DynamoRepository, DynamoRepositoryError, Entity,
+        InMemoryRepository, ItemCache, LruCache, RepositoryIndex, Update,
+    };
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Entity)]
+    #[entity(pk = "USER#{id}", sk = "USER")]
+    struct UserEntity {
+        id: u32,
+        name: String,
+    }
+
+    fn user(id: u32, name: &str) -> UserEntity {
+        UserEntity {
+            id,
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn it_reads_through_the_cache() {
+        let memory = InMemoryRepository::new("users");
+        let repository = CachedRepository::new(memory.clone(), LruCache::new(10));
+
+        block_on(repository.create(user(1, "john"))).unwrap();
+        block_on(repository.get(UserPrimaryKey::find_by_id(1))).unwrap();
+
+        // Served from the cache, even though the item is gone underneath it
+        memory.clear();
+        let cached = block_on(repository.get(UserPrimaryKey::find_by_id(1))).unwrap();
+
+        assert_eq!(cached, user(1, "john"));
+        assert_eq!(repository.get_stats(), CacheStats { hits: 1, misses: 1 });
+    }
+
+    #[test]
+    fn it_invalidates_on_writes() {
+        let repository = CachedRepository::new(InMemoryRepository::new("users"), LruCache::new(10));
+
+        block_on(repository.create(user(1, "john"))).unwrap();
+        block_on(repository.get(UserPrimaryKey::find_by_id(1))).unwrap();
+        block_on(repository.update(
+            UserPrimaryKey::find_by_id(1),
+            Update::new().set("name", "jane"),
+        ))
+        .unwrap();
+
+        let updated = block_on(repository.get(UserPrimaryKey::find_by_id(1))).unwrap();
+        let batch = block_on(repository.batch_get(vec![
+            UserPrimaryKey::find_by_id(1),
+            UserPrimaryKey::find_by_id(2),
+        ]))
+        .unwrap();
+
+        assert_eq!(updated.name, "jane");
+        assert_eq!(batch, vec![user(1, "jane")]);
+        assert_eq!(repository.get_stats(), CacheStats { hits: 1, misses: 3 });
+    }
+
+    /// Holds the next `find` after reading and the next upsert before writing until their
+    /// receiver fires, so other reads and writes can run while they're in flight
+    struct Paused {
+        inner: InMemoryRepository<UserEntity>,
+        read: Mutex<Option<oneshot::Receiver<()>>>,
+        write: Mutex<Option<oneshot::Receiver<()>>>,
+    }
+
+    async fn resume(pause: &Mutex<Option<oneshot::Receiver<()>>>) {
+        let resume = pause.lock().unwrap().take();
+
+        if let Some(resume) = resume {
+            resume.await.unwrap();
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl DynamoRepository<UserEntity> for Paused {
+        fn get_table_name(&self) -> &'static str {
+            self.inner.get_table_name()
+        }
+
+        fn get_client(&self) -> &'_ aws_sdk_dynamodb::Client {
+            self.inner.get_client()
+        }
+
+        async fn find<Index: RepositoryIndex>(
+            &self,
+            index: Index,
+        ) -> Result<Option<UserEntity>, DynamoRepositoryError> {
+            let found = self.inner.find(index).await;
+            resume(&self.read).await;
+
+            found
+        }
+
+        async fn upsert(&self, item: UserEntity) -> Result<UserEntity, DynamoRepositoryError> {
+            resume(&self.write).await;
+
+            self.inner.upsert(item).await
+        }
+    }
+
+    #[test]
+    fn it_does_not_cache_items_read_while_they_are_written() {
+        let (sender, receiver) = oneshot::channel();
+        let memory = InMemoryRepository::new("users");
+        let repository = CachedRepository::new(
+            Paused {
+                inner: memory.clone(),
+                read: Mutex::new(None),
+                write: Mutex::new(Some(receiver)),
+            },
+            LruCache::new(10),
+        );
+
+        block_on(memory.create(user(1, "john"))).unwrap();
+
+        // The write is polled first and pauses, so the read sees the item from before it
+        let (written, read) = block_on(join(repository.upsert(user(1, "jane")), async {
+            let read = repository.get(UserPrimaryKey::find_by_id(1)).await;
+            sender.send(()).unwrap();
+            read
+        }));
+
+        assert_eq!(written.unwrap(), user(1, "jane"));
+        assert_eq!(read.unwrap(), user(1, "john"));
+
+        let cached = block_on(repository.get(UserPrimaryKey::find_by_id(1))).unwrap();
+        assert_eq!(cached, user(1, "jane"));
+    }
+
+    #[test]
+    fn it_does_not_cache_items_read_before_they_were_written() {
+        let (sender, receiver) = oneshot::channel();
+        let memory = InMemoryRepository::new("users");
+        let repository = CachedRepository::new(
+            Paused {
+                inner: memory.clone(),
+                read: Mutex::new(Some(receiver)),
+                write: Mutex::new(None),
+            },
+            LruCache::new(10),
+        );
+
+        block_on(memory.create(user(1, "john"))).unwrap();
+
+        // The read is polled first and pauses after reading, so the write and its invalidation
+        // land before the read caches the item
+        let (read, written) =
+            block_on(join(repository.get(UserPrimaryKey::find_by_id(1)), async {
+                let written = repository.upsert(user(1, "jane")).await;
+                sender.send(()).unwrap();
+                written
+            }));
+
+        assert_eq!(read.unwrap(), user(1, "john"));
+        assert_eq!(written.unwrap(), user(1, "jane"));
+
+        let cached = block_on(repository.get(UserPrimaryKey::find_by_id(1))).unwrap();
+        assert_eq!(cached, user(1, "jane"));
+    }
+
+    #[test]
+    fn it_evicts_least_recently_used_and_expired_items() {
+        let cache = LruCache::new(2);
+
+        block_on(async {
+            cache.put("a", Default::default()).await;
+            cache.put("b", Default::default()).await;
+            cache.get("a").await;
+            cache.put("c", Default::default()).await;
+        });
+
+        assert!(block_on(cache.get("a")).is_some());
+        assert!(block_on(cache.get("b")).is_none());
+        assert!(block_on(cache.get("c")).is_some());
+
+        let cache = LruCache::new(2).with_ttl(Duration::ZERO);
+        block_on(cache.put("a", Default::default()));
+
+        assert!(block_on(cache.get("a")).is_none());
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,314 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use aws_sdk_dynamodb::primitives::Blob;
+use aws_sdk_dynamodb::types::AttributeValue;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::repository::repository::{LastEvaluatedKey, QueryResult, RepositoryIndex};
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum CursorError {
+    #[error("Cursor is malformed")]
+    Malformed,
+    #[error("Cursor signature is invalid")]
+    InvalidSignature,
+    #[error("Cursor was issued for a different index")]
+    WrongIndex,
+    #[error("Cursor has expired")]
+    Expired,
+    #[error("Key attribute {0} can't be encoded in a cursor")]
+    UnsupportedKey(String),
+    #[error("Index of the cursor can't be serialized")]
+    UnsupportedIndex,
+}
+
+/// Key attributes can only be strings, numbers or binaries
+#[derive(Debug, Serialize, Deserialize)]
+enum KeyValue {
+    S(String),
+    N(String),
+    B(Vec<u8>),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CursorPayload {
+    #[serde(rename = "i")]
+    index: String,
+    #[serde(rename = "k")]
+    key: BTreeMap<String, KeyValue>,
+    #[serde(rename = "e", default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<u64>,
+}
+
+/// Turns a `LastEvaluatedKey` into an opaque, URL-safe cursor and back.
+///
+/// Cursors are signed with HMAC-SHA256 and bound to the index they were issued for, so clients
+/// can neither forge a key nor replay a cursor against another partition or index:
+///
+/// ```
+/// use std::time::Duration;
+/// use orm::prelude::CursorCodec;
+///
+/// let codec = CursorCodec::new("secret").with_ttl(Duration::from_secs(3600));
+/// ```
+#[derive(Clone)]
+pub struct CursorCodec {
+    secret: Vec<u8>,
+    ttl: Option<Duration>,
+}
+
+/// A single page of items, with the cursor of the next page if there is one
+#[derive(Debug, Clone, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            cursor: self.cursor,
+        }
+    }
+}
+
+impl CursorCodec {
+    pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+        Self {
+            secret: secret.into(),
+            ttl: None,
+        }
+    }
+
+    /// Cursors are rejected once `ttl` has passed, with a resolution of a second
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn encode<Index: RepositoryIndex>(
+        &self,
+        index: &Index,
+        key: &LastEvaluatedKey,
+    ) -> Result<String, CursorError> {
+        let payload = CursorPayload {
+            index: fingerprint(index)?,
+            key: key_values(key)?,
+            expires_at: self.ttl.map(|ttl| now() + ttl.as_secs()),
+        };
+        let payload = URL_SAFE_NO_PAD
+            .encode(serde_json::to_vec(&payload).map_err(|_| CursorError::Malformed)?);
+        let signature = URL_SAFE_NO_PAD.encode(self.mac(&payload).finalize().into_bytes());
+
+        Ok(format!("{}.{}", payload, signature))
+    }
+
+    pub fn decode<Index: RepositoryIndex>(
+        &self,
+        index: &Index,
+        cursor: &str,
+    ) -> Result<LastEvaluatedKey, CursorError> {
+        let (payload, signature) = cursor.split_once('.').ok_or(CursorError::Malformed)?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| CursorError::Malformed)?;
+
+        self.mac(payload)
+            .verify_slice(&signature)
+            .map_err(|_| CursorError::InvalidSignature)?;
+
+        let payload: CursorPayload = URL_SAFE_NO_PAD
+            .decode(payload)
+            .ok()
+            .and_then(|payload| serde_json::from_slice(&payload).ok())
+            .ok_or(CursorError::Malformed)?;
+
+        if payload.index != fingerprint(index)? {
+            return Err(CursorError::WrongIndex);
+        }
+
+        if payload
+            .expires_at
+            .is_some_and(|expires_at| expires_at <= now())
+        {
+            return Err(CursorError::Expired);
+        }
+
+        Ok(payload
+            .key
+            .into_iter()
+            .map(|(name, value)| {
+                let value = match value {
+                    KeyValue::S(value) => AttributeValue::S(value),
+                    KeyValue::N(value) => AttributeValue::N(value),
+                    KeyValue::B(value) => AttributeValue::B(Blob::new(value)),
+                };
+
+                (name, value)
+            })
+            .collect())
+    }
+
+    /// Wraps a query result in a page, encoding its `LastEvaluatedKey` as the next cursor
+    pub fn page<T, Index: RepositoryIndex>(
+        &self,
+        index: &Index,
+        result: QueryResult<T>,
+    ) -> Result<Page<T>, CursorError> {
+        let cursor = result
+            .last_evaluated_key
+            .map(|key| self.encode(index, &key))
+            .transpose()?;
+
+        Ok(Page {
+            items: result.items,
+            cursor,
+        })
+    }
+
+    fn mac(&self, payload: &str) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts keys of any size");
+        mac.update(payload.as_bytes());
+        mac
+    }
+}
+
+/// Identifies the index and the key values it queries, so a cursor of one partition can't be
+/// used on another
+fn fingerprint<Index: RepositoryIndex>(index: &Index) -> Result<String, CursorError> {
+    let mut hasher = Sha256::new();
+
+    hasher.update(index.get_index_name().unwrap_or_default());
+
+    // The key values are hashed in the JSON form cursors carry them in, which doesn't change
+    // between releases like the `Debug` output of the SDK types may
+    let key = index
+        .to_key()
+        .ok()
+        .and_then(|key| key_values(&key).ok())
+        .ok_or(CursorError::UnsupportedIndex)?;
+    let key = serde_json::to_vec(&key).map_err(|_| CursorError::UnsupportedIndex)?;
+
+    hasher.update(b"\0");
+    hasher.update(key);
+
+    Ok(URL_SAFE_NO_PAD.encode(&hasher.finalize()[..12]))
+}
+
+fn key_values(key: &LastEvaluatedKey) -> Result<BTreeMap<String, KeyValue>, CursorError> {
+    key.iter()
+        .map(|(name, value)| {
+            let value = match value {
+                AttributeValue::S(value) => KeyValue::S(value.clone()),
+                AttributeValue::N(value) => KeyValue::N(value.clone()),
+                AttributeValue::B(value) => KeyValue::B(value.clone().into_inner()),
+                _ => return Err(CursorError::UnsupportedKey(name.clone())),
+            };
+
+            Ok((name.clone(), value))
+        })
+        .collect()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use aws_sdk_dynamodb::types::AttributeValue;
+    use serde::Serialize;
+
+    use super::fingerprint;
+    use crate::prelude::{CursorCodec, CursorError, RepositoryIndex};
+
+    #[derive(Clone, Serialize)]
+    struct PartitionIndex {
+        pk: String,
+    }
+
+    impl RepositoryIndex for PartitionIndex {}
+
+    fn index(pk: &str) -> PartitionIndex {
+        PartitionIndex { pk: pk.to_string() }
+    }
+
+    fn key() -> HashMap<String, AttributeValue> {
+        HashMap::from([
+            ("pk".to_string(), AttributeValue::S("NOTE".to_string())),
+            ("sk".to_string(), AttributeValue::S("NOTE_ID#1".to_string())),
+        ])
+    }
+
+    #[test]
+    fn it_round_trips_keys() {
+        let codec = CursorCodec::new("secret");
+        let cursor = codec.encode(&index("NOTE"), &key()).unwrap();
+
+        assert!(cursor
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c)));
+        assert_eq!(codec.decode(&index("NOTE"), &cursor), Ok(key()));
+    }
+
+    #[test]
+    fn it_rejects_tampered_cursors() {
+        let cursor = CursorCodec::new("secret")
+            .encode(&index("NOTE"), &key())
+            .unwrap();
+
+        assert_eq!(
+            CursorCodec::new("other").decode(&index("NOTE"), &cursor),
+            Err(CursorError::InvalidSignature)
+        );
+        assert_eq!(
+            CursorCodec::new("secret").decode(&index("NOTE"), &format!("e30{}", cursor)),
+            Err(CursorError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn it_rejects_cursors_of_other_indexes() {
+        let codec = CursorCodec::new("secret");
+        let cursor = codec.encode(&index("NOTE"), &key()).unwrap();
+
+        assert_eq!(
+            codec.decode(&index("TAG"), &cursor),
+            Err(CursorError::WrongIndex)
+        );
+    }
+
+    #[test]
+    fn it_rejects_expired_cursors() {
+        let codec = CursorCodec::new("secret").with_ttl(Duration::ZERO);
+        let cursor = codec.encode(&index("NOTE"), &key()).unwrap();
+
+        assert_eq!(
+            codec.decode(&index("NOTE"), &cursor),
+            Err(CursorError::Expired)
+        );
+    }
+
+    #[test]
+    fn it_fingerprints_indexes_the_same_way_across_releases() {
+        // Cursors handed out before an upgrade must still be accepted after it
+        assert_eq!(
+            fingerprint(&index("NOTE")),
+            Ok("H-032yjJzyK2YGaf".to_string())
+        );
+        assert_ne!(fingerprint(&index("NOTE")), fingerprint(&index("TAG")));
+    }
+}
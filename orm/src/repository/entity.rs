@@ -1,10 +1,54 @@
 use std::collections::HashMap;
 
 use aws_sdk_dynamodb::types::AttributeValue;
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_dynamo::{from_item, to_item, Item};
 
-pub trait Entity: Serialize + for<'a> Deserialize<'a> + Send + 'static {
+use crate::repository::repository::DynamoRepositoryError;
+use crate::repository::table::KeySchema;
+use crate::repository::ttl::is_expired;
+
+/// Attribute holding the entity type of every item, which lets several entity types share a table
+pub const ENTITY_TYPE_ATTRIBUTE: &str = "_type";
+
+/// Attributes of an item as DynamoDB returns them
+pub type AttributeMap = HashMap<String, AttributeValue>;
+
+/// Entity type written to the item, or `None` for items written without one
+pub fn read_entity_type(item: &AttributeMap) -> Option<&str> {
+    match item.get(ENTITY_TYPE_ATTRIBUTE) {
+        Some(AttributeValue::S(entity_type)) => Some(entity_type),
+        _ => None,
+    }
+}
+
+/// GraphQL type of an entity field
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldType {
+    String,
+    Int,
+    /// Integers beyond the 32 bits of an `Int`, as decimal strings
+    BigInt,
+    Float,
+    Boolean,
+    List(Box<FieldType>),
+    Nullable(Box<FieldType>),
+}
+
+/// A field of an entity, as exposed by the GraphQL schema generated from it
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldSchema {
+    pub name: &'static str,
+    pub field_type: FieldType,
+    /// Whether the primary key is formatted from the field
+    pub key: bool,
+    /// Whether creating the entity generates the field when it's left out, as with UUID keys
+    pub generated: bool,
+}
+
+pub trait Entity: Serialize + for<'a> Deserialize<'a> + Send + Sync + 'static {
     type PrimaryKey;
     type IndexFields;
 
@@ -12,6 +56,86 @@ pub trait Entity: Serialize + for<'a> Deserialize<'a> + Send + 'static {
 
     fn get_index_fields(&self) -> Self::IndexFields;
 
+    /// Key attributes of the table and its global secondary indexes, used to provision the
+    /// table. Defaults to a `pk` and `sk` primary key without indexes.
+    fn get_key_schema() -> KeySchema {
+        KeySchema::default()
+    }
+
+    /// Fields the GraphQL schema of the entity exposes. The derive lists every field of a type it
+    /// can expose, leaving out the others.
+    fn get_fields() -> Vec<FieldSchema> {
+        Vec::new()
+    }
+
+    /// Primary key formatted from the key fields in `fields`, which may hold other attributes
+    /// too. Lets the GraphQL schema look entities up by their fields.
+    fn primary_key_from_fields(
+        _fields: AttributeMap,
+    ) -> Result<Self::PrimaryKey, DynamoRepositoryError> {
+        Err(DynamoRepositoryError::DeserializationError(
+            serde::de::Error::custom("the entity doesn't know its key fields"),
+        ))
+    }
+
+    /// Type written to the `_type` attribute of every item, or `None` to write none. Reads skip
+    /// the items of other entity types sharing the table, see [`Entity::is_entity_type`].
+    fn get_entity_type() -> Option<&'static str> {
+        None
+    }
+
+    /// Whether the item belongs to this entity type. Items without a type, and every item of an
+    /// entity without a type, are assumed to belong to it.
+    fn is_entity_type(item: &AttributeMap) -> bool {
+        match (Self::get_entity_type(), read_entity_type(item)) {
+            (Some(expected), Some(found)) => expected == found,
+            _ => true,
+        }
+    }
+
+    /// Attribute holding the epoch seconds after which DynamoDB deletes the item, or `None` when
+    /// items don't expire. Provisioning enables time to live on it, and reads leave out the
+    /// items that expired but weren't deleted yet.
+    fn get_ttl_attribute() -> Option<&'static str> {
+        None
+    }
+
+    /// Whether the item expired, see [`Entity::get_ttl_attribute`]
+    fn is_expired(item: &AttributeMap) -> bool {
+        Self::get_ttl_attribute().is_some_and(|ttl_attribute| is_expired(item, ttl_attribute))
+    }
+
+    /// Attribute holding the version used for optimistic locking, or `None` when the entity isn't
+    /// versioned. Versioned entities get their version incremented on every write, and a write
+    /// fails with `DynamoRepositoryError::VersionConflict` when the stored version differs.
+    fn get_version_attribute() -> Option<&'static str> {
+        None
+    }
+
+    fn get_version(&self) -> u64 {
+        0
+    }
+
+    fn set_version(&mut self, _version: u64) {}
+
+    /// Attribute holding the time the item was created, stamped by `create`
+    fn get_created_at_attribute() -> Option<&'static str> {
+        None
+    }
+
+    /// Attribute holding the time the item was last written, stamped by every write
+    fn get_updated_at_attribute() -> Option<&'static str> {
+        None
+    }
+
+    fn get_created_at(&self) -> Option<DateTime<Utc>> {
+        None
+    }
+
+    fn set_created_at(&mut self, _created_at: DateTime<Utc>) {}
+
+    fn set_updated_at(&mut self, _updated_at: DateTime<Utc>) {}
+
     fn from_attribute_values(
         values: HashMap<String, AttributeValue>,
     ) -> Result<Self, serde_dynamo::Error> {
@@ -21,58 +145,99 @@ pub trait Entity: Serialize + for<'a> Deserialize<'a> + Send + 'static {
         Ok(entity)
     }
 
-    fn serialize_primary_key(&self) -> HashMap<String, AttributeValue>
+    fn serialize_primary_key(&self) -> Result<AttributeMap, DynamoRepositoryError>
     where
         Self::PrimaryKey: Serialize,
     {
-        to_item::<Self::PrimaryKey, Item>(self.get_primary_key())
-            .expect("Failed to serialize primary key")
-            .into()
+        to_attribute_map(&self.get_primary_key())
     }
 
-    fn serialize_index_fields(&self) -> HashMap<String, AttributeValue>
+    fn serialize_index_fields(&self) -> Result<AttributeMap, DynamoRepositoryError>
     where
         Self::IndexFields: Serialize,
     {
-        to_item::<Self::IndexFields, Item>(self.get_index_fields())
-            .expect("Failed to serialize index fields")
-            .into()
+        to_attribute_map(&self.get_index_fields())
     }
 
-    fn serialize(&self) -> HashMap<String, AttributeValue>
+    fn serialize(&self) -> Result<AttributeMap, DynamoRepositoryError>
     where
         Self::PrimaryKey: Serialize,
         Self::IndexFields: Serialize,
     {
-        let serialized: Item = to_item(self).expect("Failed to serialize entity");
-
-        serialized.into()
+        to_attribute_map(self)
     }
 
-    fn serialize_with_indexes(&self) -> HashMap<String, AttributeValue>
+    fn serialize_with_indexes(&self) -> Result<AttributeMap, DynamoRepositoryError>
     where
         Self::PrimaryKey: Serialize,
         Self::IndexFields: Serialize,
     {
-        let serialized: Item = to_item(self).expect("Failed to serialize entity");
-
-        let mut casted_entity: HashMap<String, AttributeValue> = serialized.into();
-        let casted_primary_key: HashMap<String, AttributeValue> =
-            to_item::<Self::PrimaryKey, Item>(self.get_primary_key())
-                .expect("Failed to serialize primary key")
-                .into();
-        let casted_index_fields: HashMap<String, AttributeValue> =
-            to_item::<Self::IndexFields, Item>(self.get_index_fields())
-                .expect("Failed to serialize index fields")
-                .into();
+        let mut casted_entity = to_attribute_map(self)?;
+        let casted_primary_key = self.serialize_primary_key()?;
+        let casted_index_fields = self.serialize_index_fields()?;
 
         casted_entity.extend(casted_index_fields);
         casted_entity.extend(casted_primary_key);
 
-        casted_entity
+        if let Some(entity_type) = Self::get_entity_type() {
+            casted_entity.insert(
+                ENTITY_TYPE_ATTRIBUTE.to_string(),
+                AttributeValue::S(entity_type.to_string()),
+            );
+        }
+
+        Ok(casted_entity)
     }
 }
 
+/// Serializes `value` into the attributes of an item, failing on values DynamoDB can't store,
+/// such as a map with non-string keys
+pub(crate) fn to_attribute_map<T: Serialize + ?Sized>(
+    value: &T,
+) -> Result<AttributeMap, DynamoRepositoryError> {
+    let item: Item = to_item(value).map_err(DynamoRepositoryError::SerializationError)?;
+
+    Ok(item.into())
+}
+
+/// Deserializes the attributes of an item into any type, like an entity reads its items
+pub fn from_attribute_map<T: DeserializeOwned>(
+    values: AttributeMap,
+) -> Result<T, DynamoRepositoryError> {
+    let item: Item = values.into();
+
+    Ok(from_item(item)?)
+}
+
+/// An enum of the entity types stored in one item collection, read with
+/// [`DynamoRepository::query_collection`]. Usually derived:
+///
+/// ```
+/// use orm::prelude::{Entity, EntityEnum};
+/// # use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize, Entity)]
+/// #[entity(pk = "NOTE#{note_id}", sk = "NOTE")]
+/// struct NoteEntity { note_id: u32, title: String }
+///
+/// #[derive(Serialize, Deserialize, Entity)]
+/// #[entity(pk = "NOTE#{note_id}", sk = "COMMENT#{id}")]
+/// struct CommentEntity { note_id: u32, id: u32, body: String }
+///
+/// #[derive(EntityEnum)]
+/// enum NoteCollection {
+///     Note(NoteEntity),
+///     Comment(CommentEntity),
+/// }
+/// ```
+///
+/// [`DynamoRepository::query_collection`]: crate::prelude::DynamoRepository::query_collection
+pub trait EntityEnum: Sized + Send + 'static {
+    /// Deserializes the item into the variant of its `_type`, or returns `None` for entity types
+    /// the enum doesn't know
+    fn from_item(item: AttributeMap) -> Result<Option<Self>, DynamoRepositoryError>;
+}
+
 // Unit test for serialization
 // #[cfg(test)]
 // mod test {
@@ -129,3 +294,132 @@ pub trait Entity: Serialize + for<'a> Deserialize<'a> + Send + 'static {
 //         let _dynamo_item: HashMap<String, AttributeValue> = item.into();
 //     }
 // }
+
+#[cfg(test)]
+mod derive_test {
+    use aws_sdk_dynamodb::types::AttributeValue;
+    use serde::{Deserialize, Serialize};
+
+    use crate::prelude::{DynamoRepositoryError, Entity, RepositoryIndex};
+
+    #[derive(Serialize, Deserialize, Entity)]
+    #[entity(pk = "USER#{tenant}", sk = "USER_ID#{id}")]
+    #[gsi(name = "gsi1", pk = "EMAIL#{email}")]
+    struct UserEntity {
+        tenant: String,
+        id: i32,
+        email: String,
+        #[entity(version)]
+        version: u64,
+    }
+
+    #[derive(Serialize, Deserialize, Entity)]
+    #[entity(pk = "BROKEN#{id}", sk = "BROKEN")]
+    struct BrokenEntity {
+        id: i32,
+        #[serde(serialize_with = "fail")]
+        payload: String,
+    }
+
+    fn fail<S: serde::Serializer>(_: &String, _: S) -> Result<S::Ok, S::Error> {
+        Err(serde::ser::Error::custom("payload can't be serialized"))
+    }
+
+    fn user() -> UserEntity {
+        UserEntity {
+            tenant: "acme".to_string(),
+            id: 1,
+            email: "john@acme.com".to_string(),
+            version: 3,
+        }
+    }
+
+    #[test]
+    fn it_generates_primary_key() {
+        let key = user().serialize_primary_key().unwrap();
+
+        assert_eq!(key["pk"], AttributeValue::S("USER#acme".to_string()));
+        assert_eq!(key["sk"], AttributeValue::S("USER_ID#1".to_string()));
+        assert_eq!(
+            UserPrimaryKey::find_by_tenant_and_id("acme".to_string(), 1)
+                .to_key()
+                .unwrap(),
+            key
+        );
+    }
+
+    #[test]
+    fn it_generates_partition_key() {
+        let key = UserPartitionKey::find_all_by_tenant("acme".to_string())
+            .to_key()
+            .unwrap();
+
+        assert_eq!(key.len(), 1);
+        assert_eq!(key["pk"], AttributeValue::S("USER#acme".to_string()));
+    }
+
+    #[test]
+    fn it_writes_index_fields() {
+        let item = user().serialize_with_indexes().unwrap();
+
+        assert_eq!(
+            item["gsi1_pk"],
+            AttributeValue::S("EMAIL#john@acme.com".to_string())
+        );
+        assert_eq!(
+            item["email"],
+            AttributeValue::S("john@acme.com".to_string())
+        );
+    }
+
+    #[test]
+    fn it_generates_gsi_index() {
+        let index = UserGsi1Index::find_all_by_email("john@acme.com".to_string());
+
+        assert_eq!(index.get_index_name(), Some("gsi1"));
+        assert_eq!(
+            index.to_key().unwrap()["gsi1_pk"],
+            AttributeValue::S("EMAIL#john@acme.com".to_string())
+        );
+        assert_eq!(
+            UserPartitionKey::find_all_by_tenant("acme".to_string()).get_index_name(),
+            None
+        );
+    }
+
+    #[test]
+    fn it_writes_entity_type() {
+        let item = user().serialize_with_indexes().unwrap();
+        let mut other = item.clone();
+        other.insert("_type".to_string(), AttributeValue::S("Note".to_string()));
+
+        assert_eq!(UserEntity::get_entity_type(), Some("User"));
+        assert_eq!(item["_type"], AttributeValue::S("User".to_string()));
+        assert!(UserEntity::is_entity_type(&item));
+        assert!(!UserEntity::is_entity_type(&other));
+    }
+
+    #[test]
+    fn it_exposes_version_field() {
+        let mut user = user();
+
+        user.set_version(user.get_version() + 1);
+
+        assert_eq!(UserEntity::get_version_attribute(), Some("version"));
+        assert_eq!(user.version, 4);
+    }
+
+    #[test]
+    fn it_returns_serialization_errors() {
+        let entity = BrokenEntity {
+            id: 1,
+            payload: "payload".to_string(),
+        };
+
+        assert!(entity.serialize_primary_key().is_ok());
+        assert!(matches!(
+            entity.serialize_with_indexes(),
+            Err(DynamoRepositoryError::SerializationError(_))
+        ));
+    }
+}
@@ -0,0 +1,370 @@
+use aws_sdk_dynamodb::types::AttributeValue;
+use serde::Serialize;
+use serde_dynamo::to_attribute_value;
+
+use crate::repository::attributes::ExpressionAttributes;
+use crate::repository::repository::DynamoRepositoryError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparator {
+    Equals,
+    NotEquals,
+    LessThan,
+    LessThanOrEquals,
+    GreaterThan,
+    GreaterThanOrEquals,
+}
+
+impl Comparator {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Comparator::Equals => "=",
+            Comparator::NotEquals => "<>",
+            Comparator::LessThan => "<",
+            Comparator::LessThanOrEquals => "<=",
+            Comparator::GreaterThan => ">",
+            Comparator::GreaterThanOrEquals => ">=",
+        }
+    }
+}
+
+/// Left hand side of a comparison: an attribute or the `size` of one
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Attribute(String),
+    Size(String),
+}
+
+impl Operand {
+    fn build(&self, attributes: &mut ExpressionAttributes) -> String {
+        match self {
+            Operand::Attribute(path) => attributes.name(path),
+            Operand::Size(path) => format!("size({})", attributes.name(path)),
+        }
+    }
+}
+
+/// A filter or condition expression. Build one starting from [`attribute`] and combine them with
+/// [`Expression::and`], [`Expression::or`] and `!`:
+///
+/// ```
+/// use orm::prelude::attribute;
+///
+/// let expression = attribute("status").eq("OPEN")
+///     .and(attribute("tags").contains("rust").or(!attribute("archived").exists()));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Compare(Operand, Comparator, AttributeValue),
+    Between(Operand, AttributeValue, AttributeValue),
+    In(Operand, Vec<AttributeValue>),
+    BeginsWith(String, AttributeValue),
+    Contains(String, AttributeValue),
+    AttributeExists(String),
+    AttributeNotExists(String),
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    /// A value that couldn't be serialized, [`Expression::build`] fails with its error
+    Invalid(serde_dynamo::Error),
+}
+
+impl Expression {
+    pub fn and(self, other: Expression) -> Self {
+        Expression::And(Box::new(self), Box::new(other))
+    }
+
+    pub fn or(self, other: Expression) -> Self {
+        Expression::Or(Box::new(self), Box::new(other))
+    }
+
+    /// Renders the expression, registering its names and values as placeholders. Fails when one of
+    /// its values couldn't be serialized.
+    pub fn build(
+        &self,
+        attributes: &mut ExpressionAttributes,
+    ) -> Result<String, DynamoRepositoryError> {
+        self.validate()?;
+
+        Ok(self.render(attributes))
+    }
+
+    /// Fails with the error of the first value that couldn't be serialized
+    pub(crate) fn validate(&self) -> Result<(), DynamoRepositoryError> {
+        match self {
+            Expression::Invalid(err) => Err(DynamoRepositoryError::SerializationError(err.clone())),
+            Expression::And(left, right) | Expression::Or(left, right) => {
+                left.validate()?;
+                right.validate()
+            }
+            Expression::Not(expression) => expression.validate(),
+            _ => Ok(()),
+        }
+    }
+
+    fn render(&self, attributes: &mut ExpressionAttributes) -> String {
+        match self {
+            Expression::Compare(operand, comparator, value) => format!(
+                "{} {} {}",
+                operand.build(attributes),
+                comparator.as_str(),
+                attributes.value(value.clone())
+            ),
+            Expression::Between(operand, low, high) => format!(
+                "{} BETWEEN {} AND {}",
+                operand.build(attributes),
+                attributes.value(low.clone()),
+                attributes.value(high.clone())
+            ),
+            Expression::In(operand, values) => format!(
+                "{} IN ({})",
+                operand.build(attributes),
+                values
+                    .iter()
+                    .map(|value| attributes.value(value.clone()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Expression::BeginsWith(path, prefix) => format!(
+                "begins_with({}, {})",
+                attributes.name(path),
+                attributes.value(prefix.clone())
+            ),
+            Expression::Contains(path, value) => format!(
+                "contains({}, {})",
+                attributes.name(path),
+                attributes.value(value.clone())
+            ),
+            Expression::AttributeExists(path) => {
+                format!("attribute_exists({})", attributes.name(path))
+            }
+            Expression::AttributeNotExists(path) => {
+                format!("attribute_not_exists({})", attributes.name(path))
+            }
+            Expression::And(left, right) => format!(
+                "{} AND {}",
+                left.build_nested(attributes),
+                right.build_nested(attributes)
+            ),
+            Expression::Or(left, right) => format!(
+                "{} OR {}",
+                left.build_nested(attributes),
+                right.build_nested(attributes)
+            ),
+            Expression::Not(expression) => format!("NOT {}", expression.build_nested(attributes)),
+            // Rejected by `validate` before rendering
+            Expression::Invalid(_) => String::new(),
+        }
+    }
+
+    fn build_nested(&self, attributes: &mut ExpressionAttributes) -> String {
+        match self {
+            Expression::And(..) | Expression::Or(..) | Expression::Not(..) => {
+                format!("({})", self.render(attributes))
+            }
+            _ => self.render(attributes),
+        }
+    }
+}
+
+impl std::ops::Not for Expression {
+    type Output = Expression;
+
+    fn not(self) -> Self::Output {
+        Expression::Not(Box::new(self))
+    }
+}
+
+/// Starts an expression on the attribute at `path`, e.g. `title` or `address.city`
+pub fn attribute(path: impl Into<String>) -> Attribute {
+    Attribute(path.into())
+}
+
+pub struct Attribute(String);
+
+impl Attribute {
+    pub fn eq(self, value: impl Serialize) -> Expression {
+        compare(Operand::Attribute(self.0), Comparator::Equals, value)
+    }
+
+    pub fn ne(self, value: impl Serialize) -> Expression {
+        compare(Operand::Attribute(self.0), Comparator::NotEquals, value)
+    }
+
+    pub fn lt(self, value: impl Serialize) -> Expression {
+        compare(Operand::Attribute(self.0), Comparator::LessThan, value)
+    }
+
+    pub fn le(self, value: impl Serialize) -> Expression {
+        compare(
+            Operand::Attribute(self.0),
+            Comparator::LessThanOrEquals,
+            value,
+        )
+    }
+
+    pub fn gt(self, value: impl Serialize) -> Expression {
+        compare(Operand::Attribute(self.0), Comparator::GreaterThan, value)
+    }
+
+    pub fn ge(self, value: impl Serialize) -> Expression {
+        compare(
+            Operand::Attribute(self.0),
+            Comparator::GreaterThanOrEquals,
+            value,
+        )
+    }
+
+    pub fn between(self, low: impl Serialize, high: impl Serialize) -> Expression {
+        valid(to_value(low).and_then(|low| {
+            Ok(Expression::Between(
+                Operand::Attribute(self.0),
+                low,
+                to_value(high)?,
+            ))
+        }))
+    }
+
+    pub fn is_in<V: Serialize>(self, values: impl IntoIterator<Item = V>) -> Expression {
+        valid(
+            values
+                .into_iter()
+                .map(to_value)
+                .collect::<Result<_, _>>()
+                .map(|values| Expression::In(Operand::Attribute(self.0), values)),
+        )
+    }
+
+    pub fn begins_with(self, prefix: impl Serialize) -> Expression {
+        valid(to_value(prefix).map(|prefix| Expression::BeginsWith(self.0, prefix)))
+    }
+
+    /// Substring of a string attribute, or element of a set or list attribute
+    pub fn contains(self, value: impl Serialize) -> Expression {
+        valid(to_value(value).map(|value| Expression::Contains(self.0, value)))
+    }
+
+    pub fn exists(self) -> Expression {
+        Expression::AttributeExists(self.0)
+    }
+
+    pub fn not_exists(self) -> Expression {
+        Expression::AttributeNotExists(self.0)
+    }
+
+    /// Compares the length of a string, or the number of elements of a set, list or map
+    pub fn size(self) -> Size {
+        Size(self.0)
+    }
+}
+
+pub struct Size(String);
+
+impl Size {
+    pub fn eq(self, value: usize) -> Expression {
+        compare(Operand::Size(self.0), Comparator::Equals, value)
+    }
+
+    pub fn ne(self, value: usize) -> Expression {
+        compare(Operand::Size(self.0), Comparator::NotEquals, value)
+    }
+
+    pub fn lt(self, value: usize) -> Expression {
+        compare(Operand::Size(self.0), Comparator::LessThan, value)
+    }
+
+    pub fn le(self, value: usize) -> Expression {
+        compare(Operand::Size(self.0), Comparator::LessThanOrEquals, value)
+    }
+
+    pub fn gt(self, value: usize) -> Expression {
+        compare(Operand::Size(self.0), Comparator::GreaterThan, value)
+    }
+
+    pub fn ge(self, value: usize) -> Expression {
+        compare(
+            Operand::Size(self.0),
+            Comparator::GreaterThanOrEquals,
+            value,
+        )
+    }
+}
+
+fn compare(operand: Operand, comparator: Comparator, value: impl Serialize) -> Expression {
+    valid(to_value(value).map(|value| Expression::Compare(operand, comparator, value)))
+}
+
+fn to_value(value: impl Serialize) -> Result<AttributeValue, serde_dynamo::Error> {
+    to_attribute_value(value)
+}
+
+/// Keeps the error of a value that couldn't be serialized for `build` to return
+fn valid(expression: Result<Expression, serde_dynamo::Error>) -> Expression {
+    expression.unwrap_or_else(Expression::Invalid)
+}
+
+#[cfg(test)]
+mod test {
+    use aws_sdk_dynamodb::types::AttributeValue;
+    use serde::ser::Error;
+    use serde::{Serialize, Serializer};
+
+    use crate::prelude::{attribute, DynamoRepositoryError, ExpressionAttributes};
+
+    /// Fails to serialize, like a map whose keys aren't strings
+    struct Unserializable;
+
+    impl Serialize for Unserializable {
+        fn serialize<S: Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+            Err(S::Error::custom("not serializable"))
+        }
+    }
+
+    #[test]
+    fn it_builds_nested_expressions() {
+        let mut attributes = ExpressionAttributes::new();
+
+        let expression = attribute("status").eq("OPEN").and(
+            attribute("tags")
+                .size()
+                .gt(2)
+                .or(!attribute("name").exists()),
+        );
+
+        assert_eq!(
+            expression.build(&mut attributes).unwrap(),
+            "#n0 = :v0 AND (size(#n1) > :v1 OR (NOT attribute_exists(#n2)))"
+        );
+        assert_eq!(attributes.names().unwrap()["#n2"], "name");
+        assert_eq!(
+            attributes.values().unwrap()[":v1"],
+            AttributeValue::N("2".to_string())
+        );
+    }
+
+    #[test]
+    fn it_reuses_name_placeholders() {
+        let mut attributes = ExpressionAttributes::new();
+
+        let expression = attribute("address.city")
+            .is_in(["Amsterdam", "Utrecht"])
+            .and(attribute("address.zip").begins_with("35"));
+
+        assert_eq!(
+            expression.build(&mut attributes).unwrap(),
+            "#n0.#n1 IN (:v0, :v1) AND begins_with(#n0.#n2, :v2)"
+        );
+    }
+
+    #[test]
+    fn it_fails_to_build_values_that_dont_serialize() {
+        let expression = attribute("status")
+            .eq("OPEN")
+            .and(!attribute("tags").contains(Unserializable));
+
+        assert!(matches!(
+            expression.build(&mut ExpressionAttributes::new()),
+            Err(DynamoRepositoryError::SerializationError(_))
+        ));
+    }
+}
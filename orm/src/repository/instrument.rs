@@ -0,0 +1,260 @@
+use std::future::Future;
+use std::time::Instant;
+
+use aws_sdk_dynamodb::operation::batch_get_item::BatchGetItemOutput;
+use aws_sdk_dynamodb::operation::batch_write_item::BatchWriteItemOutput;
+use aws_sdk_dynamodb::operation::delete_item::DeleteItemOutput;
+use aws_sdk_dynamodb::operation::get_item::GetItemOutput;
+use aws_sdk_dynamodb::operation::put_item::PutItemOutput;
+use aws_sdk_dynamodb::operation::query::QueryOutput;
+use aws_sdk_dynamodb::operation::scan::ScanOutput;
+use aws_sdk_dynamodb::operation::transact_write_items::TransactWriteItemsOutput;
+use aws_sdk_dynamodb::operation::update_item::UpdateItemOutput;
+use aws_sdk_dynamodb::types::ConsumedCapacity;
+use tracing::field::Empty;
+use tracing::{Instrument, Span};
+
+use crate::repository::repository::DynamoRepositoryError;
+use crate::repository::retry::RetryPolicy;
+
+/// Target of the events reporting the consumed capacity and latency of every call
+pub const METRICS_TARGET: &str = "orm::metrics";
+
+/// A single call to DynamoDB, traced in a `dynamodb` span tagged with the table, operation,
+/// index and number of items. Once the call finished, an event on [`METRICS_TARGET`] reports its
+/// consumed capacity units and latency, with the `monotonic_counter.` and `histogram.` field
+/// prefixes metrics layers such as `tracing-opentelemetry` turn into metrics.
+pub(crate) struct Operation {
+    name: &'static str,
+    table: String,
+    span: Span,
+}
+
+impl Operation {
+    pub(crate) fn new(name: &'static str, table: impl Into<String>) -> Self {
+        let table = table.into();
+        let span = tracing::info_span!(
+            "dynamodb",
+            table = %table,
+            operation = name,
+            index = Empty,
+            items = Empty,
+            read_units = Empty,
+            write_units = Empty,
+        );
+
+        Self { name, table, span }
+    }
+
+    pub(crate) fn with_index(self, index: Option<&str>) -> Self {
+        if let Some(index) = index {
+            self.span.record("index", index);
+        }
+
+        self
+    }
+
+    /// Number of items sent, read operations tag the number of items they returned instead
+    pub(crate) fn with_items(self, items: usize) -> Self {
+        self.span.record("items", items);
+        self
+    }
+
+    /// Sends the request through `retry_policy`, within the span of the operation
+    pub(crate) async fn run<T, F, Fut>(
+        self,
+        retry_policy: &RetryPolicy,
+        send: F,
+    ) -> Result<T, DynamoRepositoryError>
+    where
+        T: OperationOutput,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, DynamoRepositoryError>>,
+    {
+        let started = Instant::now();
+        let result = retry_policy.run(send).instrument(self.span.clone()).await;
+        let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+        let _entered = self.span.enter();
+
+        match &result {
+            Ok(output) => self.report(output, latency_ms),
+            Err(err) => tracing::warn!(
+                target: METRICS_TARGET,
+                table = %self.table,
+                operation = self.name,
+                histogram.dynamodb_latency_ms = latency_ms,
+                error = %err,
+                "{} on {} failed after {:.1}ms",
+                self.name,
+                self.table,
+                latency_ms,
+            ),
+        }
+
+        result
+    }
+
+    fn report<T: OperationOutput>(&self, output: &T, latency_ms: f64) {
+        if let Some(items) = output.item_count() {
+            self.span.record("items", items);
+        }
+
+        let (read_units, write_units) =
+            output
+                .consumed_capacity()
+                .iter()
+                .fold((0.0, 0.0), |(read, write), capacity| {
+                    let (capacity_read, capacity_write) = capacity_units(capacity, T::IS_WRITE);
+                    (read + capacity_read, write + capacity_write)
+                });
+
+        self.span.record("read_units", read_units);
+        self.span.record("write_units", write_units);
+
+        tracing::info!(
+            target: METRICS_TARGET,
+            table = %self.table,
+            operation = self.name,
+            monotonic_counter.dynamodb_read_capacity_units = read_units,
+            monotonic_counter.dynamodb_write_capacity_units = write_units,
+            histogram.dynamodb_latency_ms = latency_ms,
+            "{} on {} consumed {} RCU and {} WCU in {:.1}ms",
+            self.name,
+            self.table,
+            read_units,
+            write_units,
+            latency_ms,
+        );
+    }
+}
+
+/// Read and write units of `capacity`. DynamoDB may only report the total units, which count
+/// towards the kind of the operation.
+fn capacity_units(capacity: &ConsumedCapacity, is_write: bool) -> (f64, f64) {
+    let total = capacity.capacity_units.unwrap_or_default();
+
+    match (capacity.read_capacity_units, capacity.write_capacity_units) {
+        (None, None) if is_write => (0.0, total),
+        (None, None) => (total, 0.0),
+        (read, write) => (read.unwrap_or_default(), write.unwrap_or_default()),
+    }
+}
+
+/// Output of a DynamoDB call an [`Operation`] reports on
+pub(crate) trait OperationOutput {
+    const IS_WRITE: bool;
+
+    fn consumed_capacity(&self) -> &[ConsumedCapacity];
+
+    /// Number of items returned, for reads
+    fn item_count(&self) -> Option<usize> {
+        None
+    }
+}
+
+macro_rules! single_capacity {
+    ($output:ty, $is_write:expr) => {
+        impl OperationOutput for $output {
+            const IS_WRITE: bool = $is_write;
+
+            fn consumed_capacity(&self) -> &[ConsumedCapacity] {
+                self.consumed_capacity.as_slice()
+            }
+        }
+    };
+}
+
+single_capacity!(PutItemOutput, true);
+single_capacity!(UpdateItemOutput, true);
+single_capacity!(DeleteItemOutput, true);
+
+impl OperationOutput for GetItemOutput {
+    const IS_WRITE: bool = false;
+
+    fn consumed_capacity(&self) -> &[ConsumedCapacity] {
+        self.consumed_capacity.as_slice()
+    }
+
+    fn item_count(&self) -> Option<usize> {
+        Some(self.item.is_some() as usize)
+    }
+}
+
+impl OperationOutput for QueryOutput {
+    const IS_WRITE: bool = false;
+
+    fn consumed_capacity(&self) -> &[ConsumedCapacity] {
+        self.consumed_capacity.as_slice()
+    }
+
+    fn item_count(&self) -> Option<usize> {
+        Some(self.count as usize)
+    }
+}
+
+impl OperationOutput for ScanOutput {
+    const IS_WRITE: bool = false;
+
+    fn consumed_capacity(&self) -> &[ConsumedCapacity] {
+        self.consumed_capacity.as_slice()
+    }
+
+    fn item_count(&self) -> Option<usize> {
+        Some(self.count as usize)
+    }
+}
+
+impl OperationOutput for BatchGetItemOutput {
+    const IS_WRITE: bool = false;
+
+    fn consumed_capacity(&self) -> &[ConsumedCapacity] {
+        self.consumed_capacity.as_deref().unwrap_or_default()
+    }
+
+    fn item_count(&self) -> Option<usize> {
+        Some(
+            self.responses
+                .iter()
+                .flatten()
+                .map(|(_, items)| items.len())
+                .sum(),
+        )
+    }
+}
+
+impl OperationOutput for BatchWriteItemOutput {
+    const IS_WRITE: bool = true;
+
+    fn consumed_capacity(&self) -> &[ConsumedCapacity] {
+        self.consumed_capacity.as_deref().unwrap_or_default()
+    }
+}
+
+impl OperationOutput for TransactWriteItemsOutput {
+    const IS_WRITE: bool = true;
+
+    fn consumed_capacity(&self) -> &[ConsumedCapacity] {
+        self.consumed_capacity.as_deref().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use aws_sdk_dynamodb::types::ConsumedCapacity;
+
+    use crate::repository::instrument::capacity_units;
+
+    #[test]
+    fn it_splits_capacity_units() {
+        let total = ConsumedCapacity::builder().capacity_units(2.5).build();
+        let split = ConsumedCapacity::builder()
+            .capacity_units(3.0)
+            .read_capacity_units(1.0)
+            .write_capacity_units(2.0)
+            .build();
+
+        assert_eq!(capacity_units(&total, false), (2.5, 0.0));
+        assert_eq!(capacity_units(&total, true), (0.0, 2.5));
+        assert_eq!(capacity_units(&split, true), (1.0, 2.0));
+    }
+}
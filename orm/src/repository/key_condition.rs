@@ -0,0 +1,252 @@
+use aws_sdk_dynamodb::types::AttributeValue;
+use serde::Serialize;
+use serde_dynamo::to_attribute_value;
+
+use crate::repository::attributes::ExpressionAttributes;
+use crate::repository::repository::{DynamoRepositoryError, RepositoryIndex};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyOperator {
+    Equals(AttributeValue),
+    LessThan(AttributeValue),
+    LessThanOrEquals(AttributeValue),
+    GreaterThan(AttributeValue),
+    GreaterThanOrEquals(AttributeValue),
+    Between(AttributeValue, AttributeValue),
+    BeginsWith(AttributeValue),
+}
+
+/// A single condition of a `KeyConditionExpression`. The partition key only supports `eq`, the
+/// sort key supports every operator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyCondition {
+    attribute: String,
+    /// Holds the error of a value that couldn't be serialized, `build` fails with it
+    operator: Result<KeyOperator, serde_dynamo::Error>,
+}
+
+impl KeyCondition {
+    pub fn new(attribute: impl Into<String>, operator: KeyOperator) -> Self {
+        Self::try_new(attribute, Ok(operator))
+    }
+
+    pub fn eq(attribute: impl Into<String>, value: impl Serialize) -> Self {
+        Self::try_new(attribute, to_value(value).map(KeyOperator::Equals))
+    }
+
+    pub fn lt(attribute: impl Into<String>, value: impl Serialize) -> Self {
+        Self::try_new(attribute, to_value(value).map(KeyOperator::LessThan))
+    }
+
+    pub fn le(attribute: impl Into<String>, value: impl Serialize) -> Self {
+        Self::try_new(
+            attribute,
+            to_value(value).map(KeyOperator::LessThanOrEquals),
+        )
+    }
+
+    pub fn gt(attribute: impl Into<String>, value: impl Serialize) -> Self {
+        Self::try_new(attribute, to_value(value).map(KeyOperator::GreaterThan))
+    }
+
+    pub fn ge(attribute: impl Into<String>, value: impl Serialize) -> Self {
+        Self::try_new(
+            attribute,
+            to_value(value).map(KeyOperator::GreaterThanOrEquals),
+        )
+    }
+
+    pub fn between(
+        attribute: impl Into<String>,
+        low: impl Serialize,
+        high: impl Serialize,
+    ) -> Self {
+        let operator = to_value(low).and_then(|low| Ok(KeyOperator::Between(low, to_value(high)?)));
+
+        Self::try_new(attribute, operator)
+    }
+
+    pub fn begins_with(attribute: impl Into<String>, prefix: impl Serialize) -> Self {
+        Self::try_new(attribute, to_value(prefix).map(KeyOperator::BeginsWith))
+    }
+
+    fn try_new(
+        attribute: impl Into<String>,
+        operator: Result<KeyOperator, serde_dynamo::Error>,
+    ) -> Self {
+        Self {
+            attribute: attribute.into(),
+            operator,
+        }
+    }
+
+    /// Equality conditions on every field of the index, sorted by attribute name
+    pub fn from_index<Index: RepositoryIndex>(
+        index: &Index,
+    ) -> Result<Vec<Self>, DynamoRepositoryError> {
+        let mut conditions: Vec<Self> = index
+            .to_key()?
+            .into_iter()
+            .map(|(key, value)| Self::new(key, KeyOperator::Equals(value)))
+            .collect();
+
+        conditions.sort_by(|a, b| a.attribute.cmp(&b.attribute));
+
+        Ok(conditions)
+    }
+
+    pub fn get_attribute(&self) -> &str {
+        &self.attribute
+    }
+
+    /// Fails when the value of the condition couldn't be serialized
+    pub fn get_operator(&self) -> Result<&KeyOperator, DynamoRepositoryError> {
+        self.operator
+            .as_ref()
+            .map_err(|err| DynamoRepositoryError::SerializationError(err.clone()))
+    }
+
+    /// Renders the condition, registering its name and values as placeholders. Fails when its
+    /// value couldn't be serialized.
+    pub fn build(
+        &self,
+        attributes: &mut ExpressionAttributes,
+    ) -> Result<String, DynamoRepositoryError> {
+        let operator = self.get_operator()?;
+        let name = attributes.name(&self.attribute);
+
+        Ok(match operator {
+            KeyOperator::Equals(value) => format!("{} = {}", name, attributes.value(value.clone())),
+            KeyOperator::LessThan(value) => {
+                format!("{} < {}", name, attributes.value(value.clone()))
+            }
+            KeyOperator::LessThanOrEquals(value) => {
+                format!("{} <= {}", name, attributes.value(value.clone()))
+            }
+            KeyOperator::GreaterThan(value) => {
+                format!("{} > {}", name, attributes.value(value.clone()))
+            }
+            KeyOperator::GreaterThanOrEquals(value) => {
+                format!("{} >= {}", name, attributes.value(value.clone()))
+            }
+            KeyOperator::Between(low, high) => format!(
+                "{} BETWEEN {} AND {}",
+                name,
+                attributes.value(low.clone()),
+                attributes.value(high.clone())
+            ),
+            KeyOperator::BeginsWith(prefix) => {
+                format!(
+                    "begins_with({}, {})",
+                    name,
+                    attributes.value(prefix.clone())
+                )
+            }
+        })
+    }
+}
+
+fn to_value(value: impl Serialize) -> Result<AttributeValue, serde_dynamo::Error> {
+    to_attribute_value(value)
+}
+
+#[cfg(test)]
+mod test {
+    use aws_sdk_dynamodb::types::AttributeValue;
+    use serde::ser::Error;
+    use serde::{Serialize, Serializer};
+
+    use crate::prelude::{
+        attribute, DynamoRepositoryError, KeyCondition, QueryData, RepositoryIndex,
+    };
+
+    struct Unserializable;
+
+    impl Serialize for Unserializable {
+        fn serialize<S: Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+            Err(S::Error::custom("not serializable"))
+        }
+    }
+
+    #[derive(Debug, Clone, Serialize)]
+    struct StatusIndex {
+        status: String,
+    }
+
+    impl RepositoryIndex for StatusIndex {}
+
+    #[test]
+    fn it_builds_range_conditions_with_placeholders() {
+        let query_data = QueryData::new(
+            StatusIndex {
+                status: "OPEN".to_string(),
+            },
+            None,
+        )
+        .with_key_condition(KeyCondition::between("name", "a", "m"));
+
+        let expression_data = query_data.get_expression_data().unwrap();
+        let names = expression_data.expression_attribute_names.unwrap();
+        let values = expression_data.expression_attribute_values.unwrap();
+
+        assert_eq!(
+            expression_data.key_condition_expression,
+            "#n0 = :v0 AND #n1 BETWEEN :v1 AND :v2"
+        );
+        assert_eq!(names["#n0"], "status");
+        assert_eq!(names["#n1"], "name");
+        assert_eq!(values[":v0"], AttributeValue::S("OPEN".to_string()));
+        assert_eq!(values[":v2"], AttributeValue::S("m".to_string()));
+    }
+
+    #[test]
+    fn it_replaces_index_equality_on_the_same_attribute() {
+        let query_data = QueryData::new(
+            StatusIndex {
+                status: "OPEN".to_string(),
+            },
+            None,
+        )
+        .with_key_condition(KeyCondition::begins_with("status", "OP"));
+
+        assert_eq!(
+            query_data
+                .get_expression_data()
+                .unwrap()
+                .key_condition_expression,
+            "begins_with(#n0, :v0)"
+        );
+    }
+
+    #[test]
+    fn it_shares_placeholders_with_the_filter() {
+        let query_data = QueryData::new(
+            StatusIndex {
+                status: "OPEN".to_string(),
+            },
+            None,
+        )
+        .with_filter(attribute("status").ne("CLOSED"));
+
+        let expression_data = query_data.get_expression_data().unwrap();
+
+        assert_eq!(expression_data.key_condition_expression, "#n0 = :v0");
+        assert_eq!(expression_data.filter_expression.unwrap(), "#n0 <> :v1");
+    }
+
+    #[test]
+    fn it_fails_to_build_values_that_dont_serialize() {
+        let query_data = QueryData::new(
+            StatusIndex {
+                status: "OPEN".to_string(),
+            },
+            None,
+        )
+        .with_key_condition(KeyCondition::between("name", "a", Unserializable));
+
+        assert!(matches!(
+            query_data.get_expression_data(),
+            Err(DynamoRepositoryError::SerializationError(_))
+        ));
+    }
+}
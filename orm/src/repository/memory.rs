@@ -0,0 +1,1396 @@
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use aws_sdk_dynamodb::config::{BehaviorVersion, Region};
+use aws_sdk_dynamodb::operation::delete_item::DeleteItemOutput;
+use aws_sdk_dynamodb::types::AttributeValue;
+use serde::Serialize;
+
+use crate::repository::batch::BatchWriteOperation;
+use crate::repository::entity::{Entity, EntityEnum};
+use crate::repository::expression::{Comparator, Expression, Operand};
+use crate::repository::key_condition::{KeyCondition, KeyOperator};
+use crate::repository::repository::{
+    read_entity, DynamoRepository, DynamoRepositoryError, LastEvaluatedKey, QueryData, QueryResult,
+    RepositoryIndex,
+};
+use crate::repository::scan::ScanData;
+use crate::repository::transaction::{
+    TransactionFailure, TransactionOperation, TransactionTarget, TransactionWrite,
+};
+use crate::repository::ttl::create_condition;
+use crate::repository::update::{Update, UpdateAction};
+use crate::repository::view::{read_view, view_projection, View};
+use crate::repository::write::{
+    condition_failed, prepare_batch_put, prepare_delete, prepare_put, prepare_update,
+    update_condition_failed, PutMode,
+};
+
+type Item = HashMap<String, AttributeValue>;
+
+#[derive(Debug, Clone)]
+struct StoredItem {
+    key: Item,
+    item: Item,
+}
+
+/// A `DynamoRepository` keeping its items in memory, for tests and offline development:
+///
+/// ```
+/// use orm::prelude::InMemoryRepository;
+/// # #[derive(serde::Serialize, serde::Deserialize, orm::prelude::Entity)]
+/// # #[entity(pk = "NOTE", sk = "NOTE_ID#{id}")]
+/// # struct NoteEntity { id: u32 }
+///
+/// let repository: InMemoryRepository<NoteEntity> = InMemoryRepository::new("notes");
+/// ```
+///
+/// It behaves like a table whose primary key is `pk` and `sk`, and whose global secondary
+/// indexes use the `<name>_pk` and `<name>_sk` attributes the `Entity` derive writes. Key
+/// conditions, filters, conditions, updates, sort order and `Limit` paging are evaluated the way
+/// DynamoDB does, and failed conditions surface as the same errors.
+///
+/// Clones share their items. Transactions are applied to the items of every in-memory
+/// repository they write to, all at once or not at all.
+pub struct InMemoryRepository<E> {
+    table_name: &'static str,
+    client: aws_sdk_dynamodb::Client,
+    items: Arc<Mutex<BTreeMap<String, StoredItem>>>,
+    entity: PhantomData<fn() -> E>,
+}
+
+impl<E> Clone for InMemoryRepository<E> {
+    fn clone(&self) -> Self {
+        Self {
+            table_name: self.table_name,
+            client: self.client.clone(),
+            items: self.items.clone(),
+            entity: PhantomData,
+        }
+    }
+}
+
+impl<E> InMemoryRepository<E> {
+    pub fn new(table_name: &'static str) -> Self {
+        // Only there to satisfy `get_client`, it has no credentials and never sends anything
+        let config = aws_sdk_dynamodb::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("in-memory"))
+            .build();
+
+        Self {
+            table_name,
+            client: aws_sdk_dynamodb::Client::from_conf(config),
+            items: Arc::new(Mutex::new(BTreeMap::new())),
+            entity: PhantomData,
+        }
+    }
+
+    /// A repository of another entity type sharing the items of this one, like entity types
+    /// sharing a table
+    pub fn for_entity<F>(&self) -> InMemoryRepository<F> {
+        InMemoryRepository {
+            table_name: self.table_name,
+            client: self.client.clone(),
+            items: self.items.clone(),
+            entity: PhantomData,
+        }
+    }
+
+    /// Number of items in the table
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<String, StoredItem>> {
+        self.items
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<E> InMemoryRepository<E>
+where
+    E: Entity,
+    E::PrimaryKey: Serialize,
+    E::IndexFields: Serialize,
+{
+    fn put_item(
+        &self,
+        mut item: E,
+        condition: Option<Expression>,
+        mode: PutMode,
+    ) -> Result<E, DynamoRepositoryError> {
+        let (condition, version_check) = prepare_put(&mut item, condition, mode);
+        condition.as_ref().map(Expression::validate).transpose()?;
+        let key = item.serialize_primary_key()?;
+        let serialized = item.serialize_with_indexes()?;
+        let mut items = self.lock();
+        let stored_item = items.get(&canonical_key(&key)).map(|stored| &stored.item);
+
+        if !condition.is_none_or(|condition| matches(&condition, stored_item)) {
+            return Err(mode.condition_failed(stored_item, version_check.as_ref()));
+        }
+
+        items.insert(
+            canonical_key(&key),
+            StoredItem {
+                key,
+                item: serialized,
+            },
+        );
+
+        Ok(item)
+    }
+
+    fn delete_item(
+        &self,
+        item: E,
+        condition: Option<Expression>,
+    ) -> Result<DeleteItemOutput, DynamoRepositoryError> {
+        let (condition, version_check) = prepare_delete(&item, condition);
+        condition.as_ref().map(Expression::validate).transpose()?;
+        let key = canonical_key(&item.serialize_primary_key()?);
+        let mut items = self.lock();
+        let stored_item = items.get(&key).map(|stored| &stored.item);
+
+        if !condition.is_none_or(|condition| matches(&condition, stored_item)) {
+            return Err(condition_failed(stored_item, version_check.as_ref()));
+        }
+
+        items.remove(&key);
+
+        Ok(DeleteItemOutput::builder().build())
+    }
+
+    fn update_item<Index: RepositoryIndex>(
+        &self,
+        index: Index,
+        update: Update,
+        condition: Option<Expression>,
+    ) -> Result<E, DynamoRepositoryError> {
+        let (update, condition, version_check) = prepare_update::<E>(update, condition)?;
+        update.validate()?;
+        condition.validate()?;
+        let key = index.to_key()?;
+        let mut items = self.lock();
+
+        let stored_item = match items.get(&canonical_key(&key)) {
+            Some(stored) if matches(&condition, Some(&stored.item)) => stored.item.clone(),
+            stored => {
+                let stored_item = stored.map(|stored| &stored.item);
+                return Err(update_condition_failed(stored_item, version_check.as_ref()));
+            }
+        };
+
+        let item = apply_update(stored_item, &update);
+        let entity = E::from_attribute_values(item.clone())?;
+
+        items.insert(canonical_key(&key), StoredItem { key, item });
+
+        Ok(entity)
+    }
+
+    fn query_items<Index: RepositoryIndex>(
+        &self,
+        query_data: &QueryData<Index>,
+    ) -> Result<QueryResult<Item>, DynamoRepositoryError> {
+        let index_name = query_data.get_index().get_index_name();
+        let (_, sort_attribute) = key_attributes(index_name);
+        // Fails on the values the DynamoDB request couldn't be built with
+        query_data.get_expression_data()?;
+        let key_conditions = query_data.get_key_conditions()?;
+        let items = self.lock();
+
+        let mut candidates: Vec<&StoredItem> = items
+            .values()
+            .filter(|stored| {
+                key_conditions
+                    .iter()
+                    .all(|condition| key_matches(condition, &stored.item))
+            })
+            .collect();
+
+        let forward = query_data.scan_index_forward.unwrap_or(true);
+
+        // The map is ordered by primary key already, which breaks ties on the sort key
+        candidates
+            .sort_by(|a, b| compare_keys(a.item.get(&sort_attribute), b.item.get(&sort_attribute)));
+
+        if !forward {
+            candidates.reverse();
+        }
+
+        let start = match &query_data.last_evaluated_key {
+            Some(last_evaluated_key) => {
+                let key = primary_key(last_evaluated_key, index_name);
+
+                candidates.partition_point(|stored| {
+                    let ordering = compare_keys(
+                        stored.item.get(&sort_attribute),
+                        last_evaluated_key.get(&sort_attribute),
+                    )
+                    .then_with(|| canonical_key(&stored.key).cmp(&key));
+
+                    if forward {
+                        ordering.is_le()
+                    } else {
+                        ordering.is_ge()
+                    }
+                })
+            }
+            None => 0,
+        };
+
+        Ok(self.page(
+            &candidates[start..],
+            index_name,
+            query_data.limit,
+            query_data.filter.as_ref(),
+            query_data.projection.as_deref(),
+        ))
+    }
+
+    fn scan_items(&self, scan_data: &ScanData) -> QueryResult<Item> {
+        let (partition_attribute, _) = key_attributes(scan_data.index_name);
+        let items = self.lock();
+
+        let candidates: Vec<&StoredItem> = items
+            .values()
+            .filter(|stored| stored.item.contains_key(&partition_attribute))
+            .filter(|stored| match scan_data.segment {
+                Some((segment, total_segments)) => {
+                    let mut hasher = DefaultHasher::new();
+                    format!("{:?}", stored.item.get(&partition_attribute)).hash(&mut hasher);
+
+                    hasher.finish() % total_segments.max(1) as u64 == segment as u64
+                }
+                None => true,
+            })
+            .collect();
+
+        let start = match &scan_data.last_evaluated_key {
+            Some(last_evaluated_key) => {
+                let key = primary_key(last_evaluated_key, scan_data.index_name);
+
+                candidates.partition_point(|stored| canonical_key(&stored.key) <= key)
+            }
+            None => 0,
+        };
+
+        self.page(
+            &candidates[start..],
+            scan_data.index_name,
+            scan_data.limit,
+            scan_data.filter.as_ref(),
+            scan_data.projection.as_deref(),
+        )
+    }
+
+    /// Reads up to `limit` items, then filters them, as `Limit` applies before the filter
+    fn page(
+        &self,
+        candidates: &[&StoredItem],
+        index_name: Option<&str>,
+        limit: Option<i32>,
+        filter: Option<&Expression>,
+        projection: Option<&[String]>,
+    ) -> QueryResult<Item> {
+        let limit = limit.map_or(candidates.len(), |limit| limit.max(0) as usize);
+        let evaluated = &candidates[..limit.min(candidates.len())];
+
+        let last_evaluated_key = match evaluated.last() {
+            Some(last) if evaluated.len() == limit => {
+                let (partition_attribute, sort_attribute) = key_attributes(index_name);
+                let mut key = last.key.clone();
+
+                for attribute in [partition_attribute, sort_attribute] {
+                    if let Some(value) = last.item.get(&attribute) {
+                        key.insert(attribute, value.clone());
+                    }
+                }
+
+                Some(key)
+            }
+            _ => None,
+        };
+
+        let items = evaluated
+            .iter()
+            .filter(|stored| filter.is_none_or(|filter| matches(filter, Some(&stored.item))))
+            .map(|stored| match projection {
+                Some(paths) => project(&stored.item, paths),
+                None => stored.item.clone(),
+            })
+            .collect();
+
+        QueryResult {
+            items,
+            last_evaluated_key,
+        }
+    }
+}
+
+/// The items of an [`InMemoryRepository`], where the transactions writing to it are applied
+#[derive(Debug, Clone)]
+pub struct InMemoryTable {
+    items: Arc<Mutex<BTreeMap<String, StoredItem>>>,
+}
+
+impl InMemoryTable {
+    /// Checks the conditions of every operation first, and only applies them when they all pass,
+    /// like DynamoDB cancels a transaction
+    pub(crate) fn commit(
+        targets: &[(&'static str, TransactionTarget)],
+        operations: Vec<TransactionOperation>,
+    ) -> Result<(), DynamoRepositoryError> {
+        for operation in &operations {
+            operation.write.validate()?;
+        }
+
+        let mut tables: Vec<(&'static str, &InMemoryTable)> = Vec::new();
+
+        for (table_name, target) in targets {
+            match target {
+                TransactionTarget::InMemory(table) => tables.push((table_name, table)),
+                TransactionTarget::Client(_) => {
+                    return Err(DynamoRepositoryError::MixedTransaction)
+                }
+            }
+        }
+
+        // Repositories of different entities can share their items, they're locked once and in
+        // the same order by every transaction
+        let mut stores: Vec<&Arc<Mutex<BTreeMap<String, StoredItem>>>> =
+            tables.iter().map(|(_, table)| &table.items).collect();
+        stores.sort_by_key(|items| Arc::as_ptr(items));
+        stores.dedup_by(|a, b| Arc::ptr_eq(a, b));
+
+        let mut guards: Vec<MutexGuard<'_, BTreeMap<String, StoredItem>>> = stores
+            .iter()
+            .map(|items| {
+                items
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+            })
+            .collect();
+
+        let store_of = |table_name: &str| {
+            let (_, table) = tables
+                .iter()
+                .find(|(name, _)| *name == table_name)
+                .expect("every operation's table is a target");
+
+            stores
+                .iter()
+                .position(|items| Arc::ptr_eq(items, &table.items))
+                .expect("every target has a store")
+        };
+
+        let failures: Vec<TransactionFailure> = operations
+            .iter()
+            .enumerate()
+            .filter(|(_, operation)| {
+                let items = &guards[store_of(operation.table_name)];
+                let stored_item = items
+                    .get(&canonical_key(operation.write.key()))
+                    .map(|stored| &stored.item);
+
+                !match &operation.write {
+                    TransactionWrite::Put { condition, .. }
+                    | TransactionWrite::Delete { condition, .. } => condition
+                        .as_ref()
+                        .is_none_or(|condition| matches(condition, stored_item)),
+                    TransactionWrite::Update { condition, .. }
+                    | TransactionWrite::ConditionCheck { condition, .. } => {
+                        matches(condition, stored_item)
+                    }
+                }
+            })
+            .map(|(index, operation)| TransactionFailure {
+                index,
+                operation: operation.description.clone(),
+                code: "ConditionalCheckFailed".to_string(),
+                message: Some("The conditional request failed".to_string()),
+            })
+            .collect();
+
+        if !failures.is_empty() {
+            return Err(DynamoRepositoryError::TransactionCanceled(failures));
+        }
+
+        for operation in operations {
+            let items = &mut guards[store_of(operation.table_name)];
+
+            match operation.write {
+                TransactionWrite::Put { key, item, .. } => {
+                    items.insert(canonical_key(&key), StoredItem { key, item });
+                }
+                TransactionWrite::Update { key, update, .. } => {
+                    let stored_item = items
+                        .get(&canonical_key(&key))
+                        .map_or_else(|| key.clone(), |stored| stored.item.clone());
+                    let item = apply_update(stored_item, &update);
+
+                    items.insert(canonical_key(&key), StoredItem { key, item });
+                }
+                TransactionWrite::Delete { key, .. } => {
+                    items.remove(&canonical_key(&key));
+                }
+                TransactionWrite::ConditionCheck { .. } => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl<E> DynamoRepository<E> for InMemoryRepository<E>
+where
+    E: Entity,
+    E::PrimaryKey: Serialize,
+    E::IndexFields: Serialize,
+{
+    fn get_table_name(&self) -> &'static str {
+        self.table_name
+    }
+
+    fn get_client(&self) -> &'_ aws_sdk_dynamodb::Client {
+        &self.client
+    }
+
+    fn get_transaction_target(&self) -> TransactionTarget {
+        TransactionTarget::InMemory(InMemoryTable {
+            items: self.items.clone(),
+        })
+    }
+
+    async fn ensure_table(&self) -> Result<(), DynamoRepositoryError> {
+        Ok(())
+    }
+
+    async fn create(&self, item: E) -> Result<E, DynamoRepositoryError> {
+        self.put_item(item, Some(create_condition::<E>()), PutMode::Create)
+    }
+
+    async fn upsert(&self, item: E) -> Result<E, DynamoRepositoryError> {
+        self.put_item(item, None, PutMode::Replace)
+    }
+
+    async fn upsert_with_condition(
+        &self,
+        item: E,
+        condition: Expression,
+    ) -> Result<E, DynamoRepositoryError> {
+        self.put_item(item, Some(condition), PutMode::Replace)
+    }
+
+    async fn delete(&self, item: E) -> Result<DeleteItemOutput, DynamoRepositoryError> {
+        self.delete_item(item, None)
+    }
+
+    async fn delete_with_condition(
+        &self,
+        item: E,
+        condition: Expression,
+    ) -> Result<DeleteItemOutput, DynamoRepositoryError> {
+        self.delete_item(item, Some(condition))
+    }
+
+    async fn update<Index: RepositoryIndex>(
+        &self,
+        index: Index,
+        update: Update,
+    ) -> Result<E, DynamoRepositoryError> {
+        self.update_item(index, update, None)
+    }
+
+    async fn update_with_condition<Index: RepositoryIndex>(
+        &self,
+        index: Index,
+        update: Update,
+        condition: Expression,
+    ) -> Result<E, DynamoRepositoryError> {
+        self.update_item(index, update, Some(condition))
+    }
+
+    async fn find<Index: RepositoryIndex>(
+        &self,
+        index: Index,
+    ) -> Result<Option<E>, DynamoRepositoryError> {
+        let item = self
+            .lock()
+            .get(&canonical_key(&index.to_key()?))
+            .map(|stored| stored.item.clone())
+            .filter(|item| !E::is_expired(item));
+
+        Ok(item.map(E::from_attribute_values).transpose()?)
+    }
+
+    async fn find_view<V: View<Entity = E>, Index: RepositoryIndex>(
+        &self,
+        index: Index,
+    ) -> Result<Option<V>, DynamoRepositoryError> {
+        let item = self
+            .lock()
+            .get(&canonical_key(&index.to_key()?))
+            .map(|stored| project(&stored.item, &view_projection::<V>()));
+
+        match item {
+            Some(item) => read_view(item),
+            None => Ok(None),
+        }
+    }
+
+    async fn batch_get<Index: RepositoryIndex>(
+        &self,
+        indexes: Vec<Index>,
+    ) -> Result<Vec<E>, DynamoRepositoryError> {
+        // Duplicate keys are read once, like the batches of the DynamoDB repository do
+        let keys = indexes
+            .iter()
+            .map(|index| Ok(canonical_key(&index.to_key()?)))
+            .collect::<Result<BTreeSet<_>, DynamoRepositoryError>>()?;
+        let items = self.lock();
+
+        Ok(keys
+            .iter()
+            .filter_map(|key| items.get(key))
+            .filter_map(|stored| read_entity(stored.item.clone()).transpose())
+            .collect::<Result<Vec<E>, _>>()?)
+    }
+
+    async fn batch_write(
+        &self,
+        operations: Vec<BatchWriteOperation<E>>,
+    ) -> Result<(), DynamoRepositoryError> {
+        // Serializes every operation first, so a batch with a malformed item writes nothing
+        let writes = operations
+            .into_iter()
+            .map(|operation| match operation {
+                BatchWriteOperation::Put(mut item) => {
+                    prepare_batch_put(&mut item);
+
+                    Ok((
+                        item.serialize_primary_key()?,
+                        Some(item.serialize_with_indexes()?),
+                    ))
+                }
+                BatchWriteOperation::Delete(item) => Ok((item.serialize_primary_key()?, None)),
+            })
+            .collect::<Result<Vec<_>, DynamoRepositoryError>>()?;
+        let mut items = self.lock();
+
+        for (key, item) in writes {
+            match item {
+                Some(item) => {
+                    items.insert(canonical_key(&key), StoredItem { key, item });
+                }
+                None => {
+                    items.remove(&canonical_key(&key));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn query<Index: RepositoryIndex>(
+        &self,
+        query_data: QueryData<Index>,
+    ) -> Result<QueryResult<E>, DynamoRepositoryError> {
+        let page = self.query_items(&query_data)?;
+
+        QueryResult::read_items(page.items, page.last_evaluated_key, read_entity)
+    }
+
+    async fn query_view<V: View<Entity = E>, Index: RepositoryIndex>(
+        &self,
+        query_data: QueryData<Index>,
+    ) -> Result<QueryResult<V>, DynamoRepositoryError> {
+        let page = self.query_items(&query_data.with_projection(view_projection::<V>()))?;
+
+        QueryResult::read_items(page.items, page.last_evaluated_key, read_view)
+    }
+
+    async fn query_collection<T: EntityEnum, Index: RepositoryIndex>(
+        &self,
+        query_data: QueryData<Index>,
+    ) -> Result<QueryResult<T>, DynamoRepositoryError> {
+        let page = self.query_items(&query_data)?;
+
+        QueryResult::read_items(page.items, page.last_evaluated_key, T::from_item)
+    }
+
+    async fn scan(&self, scan_data: ScanData) -> Result<QueryResult<E>, DynamoRepositoryError> {
+        scan_data
+            .filter
+            .as_ref()
+            .map(Expression::validate)
+            .transpose()?;
+        let page = self.scan_items(&scan_data);
+
+        QueryResult::read_items(page.items, page.last_evaluated_key, read_entity)
+    }
+}
+
+/// Partition and sort key attributes of the base table or a global secondary index
+fn key_attributes(index_name: Option<&str>) -> (String, String) {
+    match index_name {
+        Some(index_name) => (format!("{}_pk", index_name), format!("{}_sk", index_name)),
+        None => ("pk".to_string(), "sk".to_string()),
+    }
+}
+
+/// The primary key within the `LastEvaluatedKey` of a page, which holds the index keys as well
+fn primary_key(last_evaluated_key: &LastEvaluatedKey, index_name: Option<&str>) -> String {
+    let key: Item = match index_name {
+        Some(index_name) => last_evaluated_key
+            .iter()
+            .filter(|(attribute, _)| !attribute.starts_with(&format!("{}_", index_name)))
+            .map(|(attribute, value)| (attribute.clone(), value.clone()))
+            .collect(),
+        None => last_evaluated_key.clone(),
+    };
+
+    canonical_key(&key)
+}
+
+pub(crate) fn canonical_key(key: &Item) -> String {
+    key.iter()
+        .collect::<BTreeMap<_, _>>()
+        .into_iter()
+        .map(|(attribute, value)| format!("{}={:?}", attribute, value))
+        .collect::<Vec<_>>()
+        .join("\u{1f}")
+}
+
+fn project(item: &Item, paths: &[String]) -> Item {
+    item.iter()
+        .filter(|(attribute, _)| {
+            paths
+                .iter()
+                .any(|path| path.split(['.', '[']).next() == Some(attribute.as_str()))
+        })
+        .map(|(attribute, value)| (attribute.clone(), value.clone()))
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum PathSegment {
+    Attribute(String),
+    Index(usize),
+}
+
+fn parse_path(path: &str) -> Vec<PathSegment> {
+    path.split('.')
+        .flat_map(|segment| {
+            let mut parts = segment.split('[');
+            let attribute = parts.next().unwrap_or_default().to_string();
+
+            std::iter::once(PathSegment::Attribute(attribute)).chain(parts.filter_map(|index| {
+                index
+                    .trim_end_matches(']')
+                    .parse()
+                    .ok()
+                    .map(PathSegment::Index)
+            }))
+        })
+        .collect()
+}
+
+fn resolve<'a>(item: &'a Item, path: &str) -> Option<&'a AttributeValue> {
+    let segments = parse_path(path);
+    let (PathSegment::Attribute(attribute), rest) = segments.split_first()? else {
+        return None;
+    };
+
+    rest.iter()
+        .try_fold(item.get(attribute)?, |value, segment| {
+            match (value, segment) {
+                (AttributeValue::M(map), PathSegment::Attribute(attribute)) => map.get(attribute),
+                (AttributeValue::L(list), PathSegment::Index(index)) => list.get(*index),
+                _ => None,
+            }
+        })
+}
+
+/// Invalid document paths are ignored, where DynamoDB would reject the update
+fn set_path(item: &mut Item, path: &str, value: AttributeValue) {
+    let segments = parse_path(path);
+
+    if let Some((PathSegment::Attribute(attribute), rest)) = segments.split_first() {
+        match rest.is_empty() {
+            true => {
+                item.insert(attribute.clone(), value);
+            }
+            false => {
+                if let Some(target) = item.get_mut(attribute) {
+                    set_nested(target, rest, value);
+                }
+            }
+        }
+    }
+}
+
+fn set_nested(target: &mut AttributeValue, path: &[PathSegment], value: AttributeValue) {
+    let Some((segment, rest)) = path.split_first() else {
+        return;
+    };
+
+    match (target, segment) {
+        (AttributeValue::M(map), PathSegment::Attribute(attribute)) if rest.is_empty() => {
+            map.insert(attribute.clone(), value);
+        }
+        (AttributeValue::M(map), PathSegment::Attribute(attribute)) => {
+            if let Some(target) = map.get_mut(attribute) {
+                set_nested(target, rest, value);
+            }
+        }
+        (AttributeValue::L(list), PathSegment::Index(index)) if rest.is_empty() => {
+            match list.get_mut(*index) {
+                Some(target) => *target = value,
+                None => list.push(value),
+            }
+        }
+        (AttributeValue::L(list), PathSegment::Index(index)) => {
+            if let Some(target) = list.get_mut(*index) {
+                set_nested(target, rest, value);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn remove_path(item: &mut Item, path: &str) {
+    let segments = parse_path(path);
+
+    if let Some((PathSegment::Attribute(attribute), rest)) = segments.split_first() {
+        match rest.is_empty() {
+            true => {
+                item.remove(attribute);
+            }
+            false => {
+                if let Some(target) = item.get_mut(attribute) {
+                    remove_nested(target, rest);
+                }
+            }
+        }
+    }
+}
+
+fn remove_nested(target: &mut AttributeValue, path: &[PathSegment]) {
+    let Some((segment, rest)) = path.split_first() else {
+        return;
+    };
+
+    match (target, segment) {
+        (AttributeValue::M(map), PathSegment::Attribute(attribute)) if rest.is_empty() => {
+            map.remove(attribute);
+        }
+        (AttributeValue::M(map), PathSegment::Attribute(attribute)) => {
+            if let Some(target) = map.get_mut(attribute) {
+                remove_nested(target, rest);
+            }
+        }
+        (AttributeValue::L(list), PathSegment::Index(index))
+            if rest.is_empty() && *index < list.len() =>
+        {
+            list.remove(*index);
+        }
+        (AttributeValue::L(list), PathSegment::Index(index)) => {
+            if let Some(target) = list.get_mut(*index) {
+                remove_nested(target, rest);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn apply_update(mut item: Item, update: &Update) -> Item {
+    for action in update.get_actions() {
+        match action {
+            UpdateAction::Set(path, value) => set_path(&mut item, path, value.clone()),
+            UpdateAction::Remove(path) => remove_path(&mut item, path),
+            UpdateAction::Add(path, value) => {
+                let added = match (resolve(&item, path), value) {
+                    (Some(AttributeValue::N(current)), AttributeValue::N(amount)) => {
+                        AttributeValue::N(add_numbers(current, amount))
+                    }
+                    (Some(AttributeValue::Ss(current)), AttributeValue::Ss(values)) => {
+                        AttributeValue::Ss(union(current, values))
+                    }
+                    (Some(AttributeValue::Ns(current)), AttributeValue::Ns(values)) => {
+                        AttributeValue::Ns(union(current, values))
+                    }
+                    (Some(AttributeValue::Bs(current)), AttributeValue::Bs(values)) => {
+                        AttributeValue::Bs(union(current, values))
+                    }
+                    (Some(current), _) => current.clone(),
+                    (None, value) => value.clone(),
+                };
+
+                set_path(&mut item, path, added);
+            }
+            UpdateAction::Append(path, AttributeValue::L(values)) => {
+                let mut list = match resolve(&item, path) {
+                    Some(AttributeValue::L(list)) => list.clone(),
+                    _ => Vec::new(),
+                };
+                list.extend(values.iter().cloned());
+
+                set_path(&mut item, path, AttributeValue::L(list));
+            }
+            UpdateAction::Increment(path, AttributeValue::N(amount)) => {
+                let current = match resolve(&item, path) {
+                    Some(AttributeValue::N(current)) => current.clone(),
+                    _ => "0".to_string(),
+                };
+
+                set_path(
+                    &mut item,
+                    path,
+                    AttributeValue::N(add_numbers(&current, amount)),
+                );
+            }
+            UpdateAction::Append(..) | UpdateAction::Increment(..) => {}
+        }
+    }
+
+    item
+}
+
+fn add_numbers(a: &str, b: &str) -> String {
+    match (a.parse::<i128>(), b.parse::<i128>()) {
+        (Ok(a), Ok(b)) => (a + b).to_string(),
+        _ => (parse_number(a) + parse_number(b)).to_string(),
+    }
+}
+
+fn union<T: Clone + PartialEq>(current: &[T], values: &[T]) -> Vec<T> {
+    let mut union = current.to_vec();
+    union.extend(
+        values
+            .iter()
+            .filter(|value| !current.contains(value))
+            .cloned(),
+    );
+    union
+}
+
+fn parse_number(number: &str) -> f64 {
+    number.parse().unwrap_or(f64::NAN)
+}
+
+/// Orders values the way DynamoDB orders sort keys: numbers numerically, strings and binaries
+/// by their bytes. Missing values go first.
+fn compare_keys(a: Option<&AttributeValue>, b: Option<&AttributeValue>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => compare(a, b).unwrap_or(Ordering::Equal),
+        (a, b) => a.is_some().cmp(&b.is_some()),
+    }
+}
+
+fn compare(a: &AttributeValue, b: &AttributeValue) -> Option<Ordering> {
+    match (a, b) {
+        (AttributeValue::N(a), AttributeValue::N(b)) => {
+            parse_number(a).partial_cmp(&parse_number(b))
+        }
+        (AttributeValue::S(a), AttributeValue::S(b)) => Some(a.as_bytes().cmp(b.as_bytes())),
+        (AttributeValue::B(a), AttributeValue::B(b)) => Some(a.as_ref().cmp(b.as_ref())),
+        (a, b) if a == b => Some(Ordering::Equal),
+        _ => None,
+    }
+}
+
+fn equals(a: &AttributeValue, b: &AttributeValue) -> bool {
+    compare(a, b) == Some(Ordering::Equal)
+}
+
+fn begins_with(value: &AttributeValue, prefix: &AttributeValue) -> bool {
+    match (value, prefix) {
+        (AttributeValue::S(value), AttributeValue::S(prefix)) => value.starts_with(prefix.as_str()),
+        (AttributeValue::B(value), AttributeValue::B(prefix)) => {
+            value.as_ref().starts_with(prefix.as_ref())
+        }
+        _ => false,
+    }
+}
+
+fn size(value: &AttributeValue) -> Option<AttributeValue> {
+    let size = match value {
+        AttributeValue::S(value) => value.len(),
+        AttributeValue::B(value) => value.as_ref().len(),
+        AttributeValue::L(list) => list.len(),
+        AttributeValue::M(map) => map.len(),
+        AttributeValue::Ss(set) => set.len(),
+        AttributeValue::Ns(set) => set.len(),
+        AttributeValue::Bs(set) => set.len(),
+        _ => return None,
+    };
+
+    Some(AttributeValue::N(size.to_string()))
+}
+
+fn key_matches(condition: &KeyCondition, item: &Item) -> bool {
+    let Some(value) = item.get(condition.get_attribute()) else {
+        return false;
+    };
+    let ordering = |other: &AttributeValue| compare(value, other);
+
+    let Ok(operator) = condition.get_operator() else {
+        return false;
+    };
+
+    match operator {
+        KeyOperator::Equals(other) => equals(value, other),
+        KeyOperator::LessThan(other) => ordering(other).is_some_and(Ordering::is_lt),
+        KeyOperator::LessThanOrEquals(other) => ordering(other).is_some_and(Ordering::is_le),
+        KeyOperator::GreaterThan(other) => ordering(other).is_some_and(Ordering::is_gt),
+        KeyOperator::GreaterThanOrEquals(other) => ordering(other).is_some_and(Ordering::is_ge),
+        KeyOperator::Between(low, high) => {
+            ordering(low).is_some_and(Ordering::is_ge)
+                && ordering(high).is_some_and(Ordering::is_le)
+        }
+        KeyOperator::BeginsWith(prefix) => begins_with(value, prefix),
+    }
+}
+
+/// Evaluates a condition or filter on an item, a missing item has no attributes
+fn matches(expression: &Expression, item: Option<&Item>) -> bool {
+    let empty = Item::new();
+    let item = item.unwrap_or(&empty);
+
+    let operand = |operand: &Operand| match operand {
+        Operand::Attribute(path) => resolve(item, path).cloned(),
+        Operand::Size(path) => resolve(item, path).and_then(size),
+    };
+
+    match expression {
+        Expression::Compare(left, comparator, right) => match operand(left) {
+            Some(left) => {
+                let ordering = compare(&left, right);
+
+                match comparator {
+                    Comparator::Equals => ordering == Some(Ordering::Equal),
+                    Comparator::NotEquals => ordering != Some(Ordering::Equal),
+                    Comparator::LessThan => ordering.is_some_and(Ordering::is_lt),
+                    Comparator::LessThanOrEquals => ordering.is_some_and(Ordering::is_le),
+                    Comparator::GreaterThan => ordering.is_some_and(Ordering::is_gt),
+                    Comparator::GreaterThanOrEquals => ordering.is_some_and(Ordering::is_ge),
+                }
+            }
+            None => *comparator == Comparator::NotEquals,
+        },
+        Expression::Between(value, low, high) => operand(value).is_some_and(|value| {
+            compare(&value, low).is_some_and(Ordering::is_ge)
+                && compare(&value, high).is_some_and(Ordering::is_le)
+        }),
+        Expression::In(value, values) => {
+            operand(value).is_some_and(|value| values.iter().any(|other| equals(&value, other)))
+        }
+        Expression::BeginsWith(path, prefix) => {
+            resolve(item, path).is_some_and(|value| begins_with(value, prefix))
+        }
+        Expression::Contains(path, element) => match (resolve(item, path), element) {
+            (Some(AttributeValue::S(value)), AttributeValue::S(element)) => {
+                value.contains(element.as_str())
+            }
+            (Some(AttributeValue::Ss(set)), AttributeValue::S(element)) => set.contains(element),
+            (Some(AttributeValue::Ns(set)), AttributeValue::N(element)) => {
+                set.iter().any(|value| {
+                    equals(
+                        &AttributeValue::N(value.clone()),
+                        &AttributeValue::N(element.clone()),
+                    )
+                })
+            }
+            (Some(AttributeValue::Bs(set)), AttributeValue::B(element)) => set.contains(element),
+            (Some(AttributeValue::L(list)), element) => {
+                list.iter().any(|value| equals(value, element))
+            }
+            _ => false,
+        },
+        Expression::AttributeExists(path) => resolve(item, path).is_some(),
+        Expression::AttributeNotExists(path) => resolve(item, path).is_none(),
+        Expression::And(left, right) => matches(left, Some(item)) && matches(right, Some(item)),
+        Expression::Or(left, right) => matches(left, Some(item)) || matches(right, Some(item)),
+        Expression::Not(expression) => !matches(expression, Some(item)),
+        Expression::Invalid(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{DateTime, Duration, Utc};
+    use futures::executor::block_on;
+    use futures::TryStreamExt;
+    use serde::{Deserialize, Serialize};
+
+    use crate::prelude::{
+        attribute, BatchWriteOperation, DynamoRepository, DynamoRepositoryError, Entity,
+        EntityEnum, InMemoryRepository, KeyCondition, QueryData, ScanData, Update, View,
+    };
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Entity)]
+    #[entity(pk = "TASK#{project}", sk = "TASK_ID#{id}")]
+    #[gsi(name = "gsi1", pk = "ASSIGNEE#{assignee}", sk = "{priority}")]
+    struct TaskEntity {
+        project: String,
+        id: u32,
+        assignee: String,
+        priority: u32,
+        #[serde(default)]
+        tags: Vec<String>,
+        #[entity(version)]
+        #[serde(default)]
+        version: u64,
+        #[entity(created_at)]
+        #[serde(default)]
+        created_at: DateTime<Utc>,
+        #[entity(updated_at)]
+        #[serde(default)]
+        updated_at: DateTime<Utc>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Entity)]
+    #[entity(pk = "TASK#{project}", sk = "TASK_ID#{task_id}#COMMENT#{id}")]
+    struct CommentEntity {
+        project: String,
+        task_id: u32,
+        id: u32,
+        body: String,
+    }
+
+    #[derive(Debug, PartialEq, EntityEnum)]
+    enum ProjectItem {
+        Task(TaskEntity),
+        Comment(CommentEntity),
+    }
+
+    #[derive(Debug, PartialEq, Deserialize, View)]
+    #[view(entity = TaskEntity)]
+    struct TaskAssignee {
+        id: u32,
+        assignee: String,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, Entity)]
+    #[entity(pk = "SESSION#{id}", sk = "SESSION")]
+    struct SessionEntity {
+        id: u32,
+        #[entity(ttl)]
+        #[serde(with = "crate::prelude::epoch_seconds")]
+        expires_at: DateTime<Utc>,
+    }
+
+    fn task(id: u32, assignee: &str, priority: u32) -> TaskEntity {
+        TaskEntity {
+            project: "orm".to_string(),
+            id,
+            assignee: assignee.to_string(),
+            priority,
+            tags: Vec::new(),
+            version: 0,
+            created_at: DateTime::default(),
+            updated_at: DateTime::default(),
+        }
+    }
+
+    fn repository() -> InMemoryRepository<TaskEntity> {
+        let repository = InMemoryRepository::new("tasks");
+
+        for (id, assignee, priority) in [(3, "ann", 2), (1, "bob", 1), (2, "ann", 1), (4, "ann", 3)]
+        {
+            block_on(repository.create(task(id, assignee, priority))).unwrap();
+        }
+
+        repository
+    }
+
+    #[test]
+    fn it_finds_items_by_key() {
+        let repository = repository();
+
+        let found =
+            block_on(repository.find(TaskPrimaryKey::find_by_project_and_id("orm".to_string(), 3)))
+                .unwrap();
+
+        assert_eq!(found.map(|task| (task.id, task.version)), Some((3, 1)));
+    }
+
+    #[test]
+    fn it_fails_conditions() {
+        let repository = repository();
+
+        assert!(matches!(
+            block_on(repository.create(task(3, "bob", 1))),
+            Err(DynamoRepositoryError::ItemAlreadyExists)
+        ));
+        assert!(matches!(
+            block_on(repository.upsert(task(3, "bob", 1))),
+            Err(DynamoRepositoryError::VersionConflict {
+                expected: 0,
+                found: Some(1)
+            })
+        ));
+        assert!(matches!(
+            block_on(
+                repository.update(
+                    TaskPrimaryKey::find_by_project_and_id("orm".to_string(), 9),
+                    Update::new()
+                        .set("assignee", "bob")
+                        .with_expected_version(1),
+                )
+            ),
+            Err(DynamoRepositoryError::ItemNotFoundError)
+        ));
+    }
+
+    #[test]
+    fn it_queries_in_sort_order_with_paging() {
+        let repository = repository();
+        let query = QueryData::new(
+            TaskPartitionKey::find_all_by_project("orm".to_string()),
+            None,
+        )
+        .with_limit(3);
+
+        let page = block_on(repository.query(query.clone())).unwrap();
+        let all: Vec<TaskEntity> =
+            block_on(repository.query_stream(query.clone()).try_collect()).unwrap();
+        let descending: Vec<TaskEntity> = block_on(
+            repository
+                .query_stream(query.with_scan_index_forward(false))
+                .try_collect(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            page.items.iter().map(|task| task.id).collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+        assert!(page.last_evaluated_key.is_some());
+        assert_eq!(
+            all.iter().map(|task| task.id).collect::<Vec<_>>(),
+            [1, 2, 3, 4]
+        );
+        assert_eq!(
+            descending.iter().map(|task| task.id).collect::<Vec<_>>(),
+            [4, 3, 2, 1]
+        );
+    }
+
+    #[test]
+    fn it_queries_global_secondary_indexes() {
+        let repository = repository();
+        let query = QueryData::new(TaskGsi1Index::find_all_by_assignee("ann".to_string()), None)
+            .with_key_condition(KeyCondition::ge("gsi1_sk", "2"))
+            .with_filter(attribute("id").ne(4));
+
+        let tasks = block_on(repository.query(query)).unwrap().items;
+
+        assert_eq!(tasks.iter().map(|task| task.id).collect::<Vec<_>>(), [3]);
+    }
+
+    #[test]
+    fn it_reads_views() {
+        let repository = repository();
+        let query = QueryData::new(TaskGsi1Index::find_all_by_assignee("bob".to_string()), None);
+
+        let found: Option<TaskAssignee> = block_on(
+            repository.find_view(TaskPrimaryKey::find_by_project_and_id("orm".to_string(), 3)),
+        )
+        .unwrap();
+        let queried: Vec<TaskAssignee> = block_on(repository.query_view(query)).unwrap().items;
+
+        assert_eq!(TaskAssignee::get_projection(), ["id", "assignee"]);
+        assert_eq!(
+            found,
+            Some(TaskAssignee {
+                id: 3,
+                assignee: "ann".to_string()
+            })
+        );
+        assert_eq!(
+            queried,
+            [TaskAssignee {
+                id: 1,
+                assignee: "bob".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn it_applies_updates() {
+        let repository = repository();
+
+        let updated = block_on(
+            repository.update(
+                TaskPrimaryKey::find_by_project_and_id("orm".to_string(), 1),
+                Update::new()
+                    .append("tags", ["bug"])
+                    .increment("priority", 2)
+                    .with_expected_version(1),
+            ),
+        )
+        .unwrap();
+
+        assert_eq!(updated.tags, ["bug"]);
+        assert_eq!(updated.priority, 3);
+        assert_eq!(updated.version, 2);
+    }
+
+    #[test]
+    fn it_checks_versions_of_updates() {
+        let repository = repository();
+        let key = || TaskPrimaryKey::find_by_project_and_id("orm".to_string(), 1);
+        let update = || Update::new().set("priority", 5);
+
+        block_on(repository.update(key(), update().with_expected_version(1))).unwrap();
+        let stale = block_on(repository.update(key(), update().with_expected_version(1)));
+        let unversioned = block_on(repository.update(key(), update()));
+
+        assert!(matches!(
+            stale,
+            Err(DynamoRepositoryError::VersionConflict {
+                expected: 1,
+                found: Some(2)
+            })
+        ));
+        assert!(matches!(
+            unversioned,
+            Err(DynamoRepositoryError::MissingVersion)
+        ));
+    }
+
+    #[test]
+    fn it_stamps_timestamps() {
+        let repository = repository();
+        let key = || TaskPrimaryKey::find_by_project_and_id("orm".to_string(), 1);
+
+        let created = block_on(repository.get(key())).unwrap();
+        block_on(repository.upsert(created.clone())).unwrap();
+        let upserted = block_on(repository.get(key())).unwrap();
+        let update = Update::new().set("priority", 5).with_expected_version(2);
+        let updated = block_on(repository.update(key(), update)).unwrap();
+
+        assert!(created.created_at > DateTime::<Utc>::default());
+        assert_eq!(created.updated_at, created.created_at);
+        assert_eq!(upserted.created_at, created.created_at);
+        assert!(upserted.updated_at >= created.updated_at);
+        assert!(updated.updated_at >= upserted.updated_at);
+    }
+
+    #[test]
+    fn it_stamps_batch_written_items() {
+        let repository = repository();
+        let key = |id| TaskPrimaryKey::find_by_project_and_id("orm".to_string(), id);
+
+        let stored = block_on(repository.get(key(1))).unwrap();
+        block_on(repository.batch_write(vec![
+            BatchWriteOperation::Put(stored.clone()),
+            BatchWriteOperation::Put(task(5, "bob", 1)),
+        ]))
+        .unwrap();
+        let replaced = block_on(repository.get(key(1))).unwrap();
+        let created = block_on(repository.get(key(5))).unwrap();
+
+        assert_eq!(replaced.created_at, stored.created_at);
+        assert!(replaced.updated_at >= stored.updated_at);
+        assert_eq!(replaced.version, 2);
+        assert!(created.created_at > DateTime::<Utc>::default());
+        assert_eq!(created.updated_at, created.created_at);
+        assert_eq!(created.version, 1);
+    }
+
+    #[test]
+    fn it_scans_every_segment() {
+        let repository = repository();
+
+        let mut ids: Vec<u32> = block_on(
+            repository
+                .parallel_scan(ScanData::new().with_limit(1), 3)
+                .map_ok(|task| task.id)
+                .try_collect(),
+        )
+        .unwrap();
+        ids.sort();
+
+        assert_eq!(ids, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn it_queries_collections_of_entity_types() {
+        let repository = repository();
+        let comments: InMemoryRepository<CommentEntity> = repository.for_entity();
+        let comment = CommentEntity {
+            project: "orm".to_string(),
+            task_id: 1,
+            id: 1,
+            body: "Looks good".to_string(),
+        };
+        block_on(comments.create(comment.clone())).unwrap();
+        let query = QueryData::new(
+            TaskPartitionKey::find_all_by_project("orm".to_string()),
+            None,
+        );
+
+        let tasks = block_on(repository.query(query.clone())).unwrap().items;
+        let collection: Vec<ProjectItem> =
+            block_on(repository.query_collection(query)).unwrap().items;
+        // The key of the comment, read as a task
+        let batch = block_on(repository.batch_get(vec![
+            CommentPrimaryKey::find_by_project_and_task_id_and_id("orm".to_string(), 1, 1),
+        ]))
+        .unwrap();
+
+        assert_eq!(tasks.len(), 4);
+        assert_eq!(collection.len(), 5);
+        assert!(collection.contains(&ProjectItem::Comment(comment)));
+        assert_eq!(batch, []);
+    }
+
+    #[test]
+    fn it_leaves_out_expired_items() {
+        let repository: InMemoryRepository<SessionEntity> = InMemoryRepository::new("sessions");
+        let expired = SessionEntity {
+            id: 1,
+            expires_at: Utc::now() - Duration::minutes(1),
+        };
+        let renewed = SessionEntity {
+            expires_at: Utc::now() + Duration::minutes(1),
+            ..expired.clone()
+        };
+
+        block_on(repository.create(expired)).unwrap();
+
+        assert!(block_on(repository.find(SessionPrimaryKey::find_by_id(1)))
+            .unwrap()
+            .is_none());
+        assert!(block_on(repository.scan(ScanData::new()))
+            .unwrap()
+            .items
+            .is_empty());
+
+        // The expired item doesn't block creating it again
+        block_on(repository.create(renewed)).unwrap();
+
+        assert!(block_on(repository.find(SessionPrimaryKey::find_by_id(1)))
+            .unwrap()
+            .is_some());
+    }
+}
@@ -1,2 +1,20 @@
+pub mod attributes;
+pub mod batch;
+pub mod cache;
+pub mod cursor;
 pub mod entity;
+pub mod expression;
+pub mod instrument;
+pub mod key_condition;
+pub mod memory;
+#[allow(clippy::module_inception)]
 pub mod repository;
+pub mod retry;
+pub mod scan;
+pub mod stream;
+pub mod table;
+pub mod transaction;
+pub mod ttl;
+pub mod update;
+pub mod view;
+mod write;
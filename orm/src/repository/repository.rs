@@ -1,31 +1,229 @@
 use std::collections::HashMap;
 
-use aws_sdk_dynamodb::error::SdkError;
+use aws_sdk_dynamodb::error::{BuildError, ProvideErrorMetadata, SdkError};
+use aws_sdk_dynamodb::operation::batch_get_item::BatchGetItemError;
+use aws_sdk_dynamodb::operation::batch_write_item::BatchWriteItemError;
+use aws_sdk_dynamodb::operation::create_table::CreateTableError;
 use aws_sdk_dynamodb::operation::delete_item::{DeleteItemError, DeleteItemOutput};
+use aws_sdk_dynamodb::operation::describe_table::DescribeTableError;
+use aws_sdk_dynamodb::operation::describe_time_to_live::DescribeTimeToLiveError;
 use aws_sdk_dynamodb::operation::get_item::GetItemError;
-use aws_sdk_dynamodb::operation::put_item::{PutItemError, PutItemOutput};
+use aws_sdk_dynamodb::operation::put_item::PutItemError;
+use aws_sdk_dynamodb::operation::query::builders::QueryFluentBuilder;
 use aws_sdk_dynamodb::operation::query::{QueryError, QueryOutput};
-use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::operation::scan::{ScanError, ScanOutput};
+use aws_sdk_dynamodb::operation::transact_write_items::TransactWriteItemsError;
+use aws_sdk_dynamodb::operation::update_item::UpdateItemError;
+use aws_sdk_dynamodb::operation::update_table::UpdateTableError;
+use aws_sdk_dynamodb::operation::update_time_to_live::UpdateTimeToLiveError;
+use aws_sdk_dynamodb::types::{AttributeValue, ReturnConsumedCapacity};
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryFutureExt};
 use serde::Serialize;
-use serde_dynamo::{to_item, Item};
 use thiserror::Error;
 
-use crate::repository::entity::Entity;
+use crate::repository::attributes::ExpressionAttributes;
+use crate::repository::batch::{
+    batch_get_items, batch_write_items, delete_request, put_request, BatchWriteOperation,
+};
+use crate::repository::cursor::CursorError;
+use crate::repository::entity::{to_attribute_map, AttributeMap, Entity, EntityEnum};
+use crate::repository::expression::Expression;
+use crate::repository::instrument::Operation;
+use crate::repository::key_condition::KeyCondition;
+use crate::repository::retry::RetryPolicy;
+use crate::repository::scan::ScanData;
+use crate::repository::stream::QueryStream;
+use crate::repository::table::{SchemaDrift, TableDefinition};
+use crate::repository::transaction::{TransactionFailure, TransactionTarget};
+use crate::repository::ttl::create_condition;
+use crate::repository::update::Update;
+use crate::repository::view::{read_view, view_projection, View};
+use crate::repository::write::{delete_item, prepare_batch_put, put_item, update_item, PutMode};
+use crate::service::hooks::ValidationErrors;
 
 #[derive(Error, Debug)]
 pub enum DynamoRepositoryError {
     #[error("Error putting item")]
-    PutItemError(#[from] SdkError<PutItemError>),
+    PutItemError(#[source] Box<SdkError<PutItemError>>),
     #[error("Error deleting item")]
-    DeleteItemError(#[from] SdkError<DeleteItemError>),
+    DeleteItemError(#[source] Box<SdkError<DeleteItemError>>),
     #[error("Error getting item")]
-    QueryError(#[from] SdkError<QueryError>),
+    QueryError(#[source] Box<SdkError<QueryError>>),
     #[error("Error querying items")]
-    GetItemError(#[from] SdkError<GetItemError>),
+    GetItemError(#[source] Box<SdkError<GetItemError>>),
+    #[error("Error scanning items")]
+    ScanError(#[source] Box<SdkError<ScanError>>),
+    #[error("Error updating item")]
+    UpdateItemError(#[source] Box<SdkError<UpdateItemError>>),
+    #[error("Error batch getting items")]
+    BatchGetItemError(#[source] Box<SdkError<BatchGetItemError>>),
+    #[error("Error batch writing items")]
+    BatchWriteItemError(#[source] Box<SdkError<BatchWriteItemError>>),
+    #[error("Error building request")]
+    BuildError(#[from] BuildError),
+    #[error("{0} items were still unprocessed after retrying the batch")]
+    UnprocessedItemsError(usize),
+    #[error("Error writing transaction")]
+    TransactWriteItemsError(#[source] Box<SdkError<TransactWriteItemsError>>),
+    #[error("Transaction was canceled: {}", join_failures(.0))]
+    TransactionCanceled(Vec<TransactionFailure>),
+    #[error("Transactions can't contain more than {0} operations")]
+    TransactionTooLarge(usize),
+    #[error("Transactions can't mix in-memory and DynamoDB repositories")]
+    MixedTransaction,
+    #[error("Error serializing item: {0}")]
+    SerializationError(#[source] serde_dynamo::Error),
     #[error("Error deserializing item")]
     DeserializationError(#[from] serde_dynamo::Error),
+    #[error("Item has no `_type` attribute to tell its entity type")]
+    MissingEntityType,
     #[error("Item wasn't found during get operation")]
     ItemNotFoundError,
+    #[error("Item already exists")]
+    ItemAlreadyExists,
+    #[error("Condition of the write wasn't met")]
+    ConditionFailed { item: Option<AttributeMap> },
+    #[error("Error describing table")]
+    DescribeTableError(#[source] Box<SdkError<DescribeTableError>>),
+    #[error("Error creating table")]
+    CreateTableError(#[source] Box<SdkError<CreateTableError>>),
+    #[error("Error updating table")]
+    UpdateTableError(#[source] Box<SdkError<UpdateTableError>>),
+    #[error("Error describing time to live")]
+    DescribeTimeToLiveError(#[source] Box<SdkError<DescribeTimeToLiveError>>),
+    #[error("Error updating time to live")]
+    UpdateTimeToLiveError(#[source] Box<SdkError<UpdateTimeToLiveError>>),
+    #[error("Table differs from its definition: {}", join_drift(.0))]
+    SchemaDrift(Vec<SchemaDrift>),
+    #[error("Table {0} didn't become active in time")]
+    TableNotActive(String),
+    #[error("Invalid cursor: {0}")]
+    InvalidCursor(#[from] CursorError),
+    #[error("Item was modified concurrently, expected version {expected} but found {found:?}")]
+    VersionConflict { expected: u64, found: Option<u64> },
+    #[error("Updates of versioned entities need the version they expect")]
+    MissingVersion,
+    #[error("Validation failed: {0}")]
+    ValidationFailed(ValidationErrors),
+    #[error("Entity hook failed: {0}")]
+    HookFailed(#[source] anyhow::Error),
+}
+
+/// The SDK errors are hundreds of bytes each, so they're boxed to keep results small
+macro_rules! boxed_sdk_errors {
+    ($($variant:ident($error:ty)),* $(,)?) => {
+        $(
+            impl From<SdkError<$error>> for DynamoRepositoryError {
+                fn from(err: SdkError<$error>) -> Self {
+                    Self::$variant(Box::new(err))
+                }
+            }
+        )*
+    };
+}
+
+boxed_sdk_errors!(
+    PutItemError(PutItemError),
+    DeleteItemError(DeleteItemError),
+    QueryError(QueryError),
+    GetItemError(GetItemError),
+    ScanError(ScanError),
+    UpdateItemError(UpdateItemError),
+    BatchGetItemError(BatchGetItemError),
+    BatchWriteItemError(BatchWriteItemError),
+    TransactWriteItemsError(TransactWriteItemsError),
+    DescribeTableError(DescribeTableError),
+    CreateTableError(CreateTableError),
+    UpdateTableError(UpdateTableError),
+    DescribeTimeToLiveError(DescribeTimeToLiveError),
+    UpdateTimeToLiveError(UpdateTimeToLiveError),
+);
+
+impl DynamoRepositoryError {
+    /// Error code DynamoDB returned, e.g. `ProvisionedThroughputExceededException`
+    pub fn error_code(&self) -> Option<&str> {
+        self.sdk_error().and_then(|err| err.code())
+    }
+
+    /// Whether DynamoDB rejected the request for exceeding the throughput of the table or the
+    /// request rate of the account
+    pub fn is_throttled(&self) -> bool {
+        matches!(
+            self.error_code(),
+            Some(
+                "ProvisionedThroughputExceededException"
+                    | "ThrottlingException"
+                    | "RequestLimitExceeded"
+            )
+        )
+    }
+
+    /// Whether DynamoDB couldn't be reached, didn't respond in time or failed internally
+    pub fn is_unavailable(&self) -> bool {
+        self.sdk_error().is_some_and(|err| err.is_unavailable())
+    }
+
+    fn sdk_error(&self) -> Option<&dyn SdkErrorDetails> {
+        match self {
+            Self::PutItemError(err) => Some(&**err),
+            Self::DeleteItemError(err) => Some(&**err),
+            Self::QueryError(err) => Some(&**err),
+            Self::GetItemError(err) => Some(&**err),
+            Self::ScanError(err) => Some(&**err),
+            Self::UpdateItemError(err) => Some(&**err),
+            Self::BatchGetItemError(err) => Some(&**err),
+            Self::BatchWriteItemError(err) => Some(&**err),
+            Self::TransactWriteItemsError(err) => Some(&**err),
+            Self::DescribeTableError(err) => Some(&**err),
+            Self::CreateTableError(err) => Some(&**err),
+            Self::UpdateTableError(err) => Some(&**err),
+            Self::DescribeTimeToLiveError(err) => Some(&**err),
+            Self::UpdateTimeToLiveError(err) => Some(&**err),
+            _ => None,
+        }
+    }
+}
+
+/// The details of an `SdkError` that don't depend on the operation
+trait SdkErrorDetails {
+    fn code(&self) -> Option<&str>;
+
+    fn is_unavailable(&self) -> bool;
+}
+
+impl<E: ProvideErrorMetadata, R> SdkErrorDetails for SdkError<E, R> {
+    fn code(&self) -> Option<&str> {
+        ProvideErrorMetadata::code(self)
+    }
+
+    fn is_unavailable(&self) -> bool {
+        match self {
+            SdkError::DispatchFailure(_)
+            | SdkError::TimeoutError(_)
+            | SdkError::ResponseError(_) => true,
+            _ => matches!(
+                ProvideErrorMetadata::code(self),
+                Some("InternalServerError" | "ServiceUnavailable")
+            ),
+        }
+    }
+}
+
+fn join_failures(failures: &[TransactionFailure]) -> String {
+    failures
+        .iter()
+        .map(|failure| failure.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn join_drift(drift: &[SchemaDrift]) -> String {
+    drift
+        .iter()
+        .map(|drift| drift.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 impl Serialize for DynamoRepositoryError {
@@ -38,38 +236,85 @@ impl Serialize for DynamoRepositoryError {
 }
 
 #[derive(Debug)]
-pub struct QueryResult<E: Entity> {
-    pub items: Vec<E>,
+pub struct QueryResult<T> {
+    pub items: Vec<T>,
     pub last_evaluated_key: Option<LastEvaluatedKey>,
 }
 
-impl<E: Entity> TryFrom<QueryOutput> for QueryResult<E> {
-    type Error = DynamoRepositoryError;
-
-    fn try_from(query_output: QueryOutput) -> Result<Self, Self::Error> {
-        let items = query_output
-            .items
-            .ok_or(DynamoRepositoryError::ItemNotFoundError)?
+impl<T> QueryResult<T> {
+    /// Reads the items of a page with `read`, leaving out the items it returns `None` for
+    pub(crate) fn read_items(
+        items: Vec<AttributeMap>,
+        last_evaluated_key: Option<LastEvaluatedKey>,
+        read: impl FnMut(AttributeMap) -> Result<Option<T>, DynamoRepositoryError>,
+    ) -> Result<Self, DynamoRepositoryError> {
+        let items = items
             .into_iter()
-            .map(|item| E::from_attribute_values(item))
-            .collect::<Result<Vec<E>, _>>()?;
+            .map(read)
+            .filter_map(Result::transpose)
+            .collect::<Result<Vec<T>, _>>()?;
 
         Ok(QueryResult {
             items,
-            last_evaluated_key: query_output.last_evaluated_key,
+            last_evaluated_key,
         })
     }
 }
 
+/// Deserializes the item, or returns `None` when it belongs to another entity type or expired
+pub(crate) fn read_entity<E: Entity>(
+    item: AttributeMap,
+) -> Result<Option<E>, DynamoRepositoryError> {
+    if !E::is_entity_type(&item) || E::is_expired(&item) {
+        return Ok(None);
+    }
+
+    Ok(Some(E::from_attribute_values(item)?))
+}
+
+impl<E: Entity> TryFrom<QueryOutput> for QueryResult<E> {
+    type Error = DynamoRepositoryError;
+
+    fn try_from(query_output: QueryOutput) -> Result<Self, Self::Error> {
+        QueryResult::read_items(
+            query_output
+                .items
+                .ok_or(DynamoRepositoryError::ItemNotFoundError)?,
+            query_output.last_evaluated_key,
+            read_entity,
+        )
+    }
+}
+
+impl<E: Entity> TryFrom<ScanOutput> for QueryResult<E> {
+    type Error = DynamoRepositoryError;
+
+    fn try_from(scan_output: ScanOutput) -> Result<Self, Self::Error> {
+        QueryResult::read_items(
+            scan_output.items.unwrap_or_default(),
+            scan_output.last_evaluated_key,
+            read_entity,
+        )
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct QueryData<Index: RepositoryIndex> {
     index: Index,
-    last_evaluated_key: Option<LastEvaluatedKey>,
+    key_conditions: Vec<KeyCondition>,
+    pub(crate) filter: Option<Expression>,
+    pub(crate) projection: Option<Vec<String>>,
+    pub(crate) scan_index_forward: Option<bool>,
+    pub(crate) limit: Option<i32>,
+    pub(crate) last_evaluated_key: Option<LastEvaluatedKey>,
 }
 
 pub struct ExpressionData {
-    key_condition_expression: String,
-    expression_attribute_values: HashMap<String, AttributeValue>,
+    pub key_condition_expression: String,
+    pub filter_expression: Option<String>,
+    pub projection_expression: Option<String>,
+    pub expression_attribute_names: Option<HashMap<String, String>>,
+    pub expression_attribute_values: Option<HashMap<String, AttributeValue>>,
 }
 
 pub type LastEvaluatedKey = HashMap<String, AttributeValue>;
@@ -78,31 +323,131 @@ impl<Index: RepositoryIndex> QueryData<Index> {
     pub fn new(index: Index, last_evaluated_key: Option<LastEvaluatedKey>) -> Self {
         Self {
             index,
+            key_conditions: Vec::new(),
+            filter: None,
+            projection: None,
+            scan_index_forward: None,
+            limit: None,
             last_evaluated_key,
         }
     }
 
-    pub fn get_expression_data(&self) -> ExpressionData {
-        let serialized: Item = to_item(&self.index).expect("Failed to serialize index");
+    pub fn get_index(&self) -> &Index {
+        &self.index
+    }
 
-        let mut key_condition_expression = Vec::new();
-        let mut expression_attribute_values = HashMap::new();
+    /// Maximum number of items DynamoDB reads for a single page
+    pub fn with_limit(mut self, limit: i32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
 
-        for (key, value) in serialized.iter() {
-            key_condition_expression.push(format!("{} = :{}", key, key));
-            expression_attribute_values.insert(format!(":{}", key), value.clone().into());
-        }
+    pub fn with_last_evaluated_key(mut self, last_evaluated_key: Option<LastEvaluatedKey>) -> Self {
+        self.last_evaluated_key = last_evaluated_key;
+        self
+    }
 
-        ExpressionData {
-            key_condition_expression: key_condition_expression.join(" AND "),
-            expression_attribute_values,
-        }
+    /// Reads the items in ascending order of the sort key, the default, or in descending order
+    pub fn with_scan_index_forward(mut self, scan_index_forward: bool) -> Self {
+        self.scan_index_forward = Some(scan_index_forward);
+        self
+    }
+
+    /// Adds a key condition, typically a range condition on the sort key. It replaces the
+    /// equality condition the index would otherwise produce for the same attribute.
+    pub fn with_key_condition(mut self, key_condition: KeyCondition) -> Self {
+        self.key_conditions.push(key_condition);
+        self
+    }
+
+    /// Filters the items DynamoDB read before returning them. Filtered items still consume
+    /// read capacity, so prefer key conditions where possible.
+    pub fn with_filter(mut self, filter: Expression) -> Self {
+        self.filter = Some(match self.filter {
+            Some(existing) => existing.and(filter),
+            None => filter,
+        });
+        self
+    }
+
+    /// Only reads the given attributes, or document paths, of every item. Items are still
+    /// deserialized into the entity, so the projection has to keep every field that has no
+    /// `#[serde(default)]`, see [`DynamoRepository::query_view`] otherwise.
+    pub fn with_projection<P: Into<String>>(mut self, paths: impl IntoIterator<Item = P>) -> Self {
+        self.projection = Some(paths.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// The equality conditions of the index, followed by the added key conditions
+    pub fn get_key_conditions(&self) -> Result<Vec<KeyCondition>, DynamoRepositoryError> {
+        Ok(KeyCondition::from_index(&self.index)?
+            .into_iter()
+            .filter(|index_condition| {
+                !self
+                    .key_conditions
+                    .iter()
+                    .any(|condition| condition.get_attribute() == index_condition.get_attribute())
+            })
+            .chain(self.key_conditions.iter().cloned())
+            .collect())
+    }
+
+    /// Sets the parameters on a `Query` request
+    pub(crate) fn apply(
+        &self,
+        request: QueryFluentBuilder,
+    ) -> Result<QueryFluentBuilder, DynamoRepositoryError> {
+        let expression_data = self.get_expression_data()?;
+
+        Ok(request
+            .set_index_name(self.index.get_index_name().map(String::from))
+            .set_exclusive_start_key(self.last_evaluated_key.clone())
+            .set_limit(self.limit)
+            .set_scan_index_forward(self.scan_index_forward)
+            .set_expression_attribute_names(expression_data.expression_attribute_names)
+            .set_expression_attribute_values(expression_data.expression_attribute_values)
+            .key_condition_expression(expression_data.key_condition_expression)
+            .set_filter_expression(expression_data.filter_expression)
+            .set_projection_expression(expression_data.projection_expression)
+            .return_consumed_capacity(ReturnConsumedCapacity::Total))
+    }
+
+    pub fn get_expression_data(&self) -> Result<ExpressionData, DynamoRepositoryError> {
+        let mut attributes = ExpressionAttributes::new();
+
+        let key_conditions = self
+            .get_key_conditions()?
+            .iter()
+            .map(|condition| condition.build(&mut attributes))
+            .collect::<Result<Vec<_>, _>>()?;
+        let filter_expression = self
+            .filter
+            .as_ref()
+            .map(|filter| filter.build(&mut attributes))
+            .transpose()?;
+        let projection_expression = self
+            .projection
+            .as_ref()
+            .map(|paths| attributes.projection(paths));
+
+        Ok(ExpressionData {
+            key_condition_expression: key_conditions.join(" AND "),
+            filter_expression,
+            projection_expression,
+            expression_attribute_names: attributes.names(),
+            expression_attribute_values: attributes.values(),
+        })
     }
 }
 
 pub trait RepositoryIndex: Send + Serialize + Clone {
-    fn to_key(&self) -> HashMap<String, AttributeValue> {
-        to_item(self).expect("Failed to serialize index")
+    /// Name of the global secondary index this index queries, or `None` for the base table
+    fn get_index_name(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn to_key(&self) -> Result<AttributeMap, DynamoRepositoryError> {
+        to_attribute_map(self)
     }
 }
 
@@ -116,58 +461,255 @@ where
     fn get_table_name(&self) -> &'static str;
     fn get_client(&self) -> &'_ aws_sdk_dynamodb::Client;
 
-    async fn create(&self, item: E) -> Result<PutItemOutput, DynamoRepositoryError> {
-        Ok(self
-            .get_client()
-            .put_item()
-            .table_name(self.get_table_name())
-            .set_item(Some(item.serialize_with_indexes()))
-            .set_condition_expression(Some("attribute_not_exists(pk)".to_string()))
-            .send()
-            .await?)
+    /// Definition the table is provisioned from, override it to change the billing mode or set a
+    /// time to live attribute
+    fn get_table_definition(&self) -> TableDefinition {
+        TableDefinition::new::<E>(self.get_table_name())
     }
 
-    async fn upsert(&self, item: E) -> Result<PutItemOutput, DynamoRepositoryError> {
-        Ok(self
-            .get_client()
-            .put_item()
-            .table_name(self.get_table_name())
-            .set_item(Some(item.serialize_with_indexes()))
-            .send()
-            .await?)
+    /// Policy every request of the repository is retried with. Override it to tune the attempts,
+    /// and keep the policy in the repository when it has a
+    /// [`RateLimiter`](crate::repository::retry::RateLimiter), so its calls share
+    /// the rate.
+    fn get_retry_policy(&self) -> RetryPolicy {
+        RetryPolicy::default()
+    }
+
+    /// Where [`Transaction`](crate::repository::transaction::Transaction)s writing to the
+    /// repository are committed, DynamoDB through the client unless overridden
+    fn get_transaction_target(&self) -> TransactionTarget {
+        TransactionTarget::Client(self.get_client().clone())
+    }
+
+    /// Creates the table when it doesn't exist yet, see [`TableDefinition::ensure`]
+    async fn ensure_table(&self) -> Result<(), DynamoRepositoryError> {
+        self.get_table_definition().ensure(self.get_client()).await
+    }
+
+    /// Writes an item that must not exist yet, and returns it as written, with its first version
+    /// and timestamps
+    async fn create(&self, item: E) -> Result<E, DynamoRepositoryError> {
+        put_item(
+            self.get_client(),
+            self.get_table_name(),
+            &self.get_retry_policy(),
+            item,
+            Some(create_condition::<E>()),
+            PutMode::Create,
+        )
+        .await
+    }
+
+    /// Writes the item, replacing the stored one, and returns it as written
+    async fn upsert(&self, item: E) -> Result<E, DynamoRepositoryError> {
+        put_item(
+            self.get_client(),
+            self.get_table_name(),
+            &self.get_retry_policy(),
+            item,
+            None,
+            PutMode::Replace,
+        )
+        .await
+    }
+
+    /// Writes the item only when the stored item matches `condition`
+    async fn upsert_with_condition(
+        &self,
+        item: E,
+        condition: Expression,
+    ) -> Result<E, DynamoRepositoryError> {
+        put_item(
+            self.get_client(),
+            self.get_table_name(),
+            &self.get_retry_policy(),
+            item,
+            Some(condition),
+            PutMode::Replace,
+        )
+        .await
     }
 
     async fn delete(&self, item: E) -> Result<DeleteItemOutput, DynamoRepositoryError> {
-        Ok(self
-            .get_client()
-            .delete_item()
-            .table_name(self.get_table_name())
-            .set_key(Some(item.serialize_primary_key()))
-            .send()
-            .await?)
+        delete_item(
+            self.get_client(),
+            self.get_table_name(),
+            &self.get_retry_policy(),
+            item,
+            None,
+        )
+        .await
+    }
+
+    /// Deletes the item only when the stored item matches `condition`
+    async fn delete_with_condition(
+        &self,
+        item: E,
+        condition: Expression,
+    ) -> Result<DeleteItemOutput, DynamoRepositoryError> {
+        delete_item(
+            self.get_client(),
+            self.get_table_name(),
+            &self.get_retry_policy(),
+            item,
+            Some(condition),
+        )
+        .await
     }
+
+    /// Applies `update` to the stored item in a single `UpdateItem` call and returns the updated
+    /// entity. Fails with `ItemNotFoundError` when there's no item with the given primary key, and
+    /// on versioned entities with `MissingVersion` or `VersionConflict` unless the update expects
+    /// the stored version.
+    async fn update<Index: RepositoryIndex>(
+        &self,
+        index: Index,
+        update: Update,
+    ) -> Result<E, DynamoRepositoryError> {
+        update_item(
+            self.get_client(),
+            self.get_table_name(),
+            &self.get_retry_policy(),
+            index,
+            update,
+            None,
+        )
+        .await
+    }
+
+    /// Applies `update` only when the stored item matches `condition`
+    async fn update_with_condition<Index: RepositoryIndex>(
+        &self,
+        index: Index,
+        update: Update,
+        condition: Expression,
+    ) -> Result<E, DynamoRepositoryError> {
+        update_item(
+            self.get_client(),
+            self.get_table_name(),
+            &self.get_retry_policy(),
+            index,
+            update,
+            Some(condition),
+        )
+        .await
+    }
+
+    /// Looks up a single item by its primary key, or `None` when it expired. Secondary indexes
+    /// can only be queried.
     async fn find<Index: RepositoryIndex>(
         &self,
         index: Index,
     ) -> Result<Option<E>, DynamoRepositoryError> {
-        println!("Finding item");
+        let request = self
+            .get_client()
+            .get_item()
+            .table_name(self.get_table_name())
+            .set_key(Some(index.to_key()?))
+            .return_consumed_capacity(ReturnConsumedCapacity::Total);
+
         Ok(
-            match self
-                .get_client()
-                .get_item()
-                .table_name(self.get_table_name())
-                .set_key(Some(index.to_key()))
-                .send()
-                .await
-                .map_err(|err| DynamoRepositoryError::from(err))?
+            match Operation::new("GetItem", self.get_table_name())
+                .run(&self.get_retry_policy(), || {
+                    request.clone().send().map_err(DynamoRepositoryError::from)
+                })
+                .await?
                 .item
             {
-                Some(item) => Some(E::from_attribute_values(item)?),
-                None => None,
+                Some(item) if !E::is_expired(&item) => Some(E::from_attribute_values(item)?),
+                _ => None,
             },
         )
     }
 
+    /// Looks up the view of a single item by its primary key, only reading the attributes of
+    /// the view
+    async fn find_view<V: View<Entity = E>, Index: RepositoryIndex>(
+        &self,
+        index: Index,
+    ) -> Result<Option<V>, DynamoRepositoryError> {
+        let mut attributes = ExpressionAttributes::new();
+        let projection_expression = attributes.projection(&view_projection::<V>());
+
+        let request = self
+            .get_client()
+            .get_item()
+            .table_name(self.get_table_name())
+            .set_key(Some(index.to_key()?))
+            .projection_expression(projection_expression)
+            .set_expression_attribute_names(attributes.names())
+            .return_consumed_capacity(ReturnConsumedCapacity::Total);
+
+        match Operation::new("GetItem", self.get_table_name())
+            .run(&self.get_retry_policy(), || {
+                request.clone().send().map_err(DynamoRepositoryError::from)
+            })
+            .await?
+            .item
+        {
+            Some(item) => read_view(item),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads many items by their primary keys, see [`batch_get_items`]. Items that don't exist
+    /// are left out, and the order of the result is unspecified.
+    async fn batch_get<Index: RepositoryIndex>(
+        &self,
+        indexes: Vec<Index>,
+    ) -> Result<Vec<E>, DynamoRepositoryError> {
+        let keys = indexes
+            .iter()
+            .map(|index| index.to_key())
+            .collect::<Result<_, _>>()?;
+
+        let items = batch_get_items(
+            self.get_client(),
+            self.get_table_name(),
+            &self.get_retry_policy(),
+            keys,
+        )
+        .await?;
+
+        items
+            .into_iter()
+            .filter_map(|item| read_entity(item).transpose())
+            .collect()
+    }
+
+    /// Puts and deletes many items, see [`batch_write_items`]. The operations aren't atomic.
+    async fn batch_write(
+        &self,
+        operations: Vec<BatchWriteOperation<E>>,
+    ) -> Result<(), DynamoRepositoryError> {
+        let requests = operations
+            .into_iter()
+            .map(|operation| match operation {
+                BatchWriteOperation::Put(mut item) => {
+                    prepare_batch_put(&mut item);
+
+                    Ok((
+                        item.serialize_primary_key()?,
+                        put_request(item.serialize_with_indexes()?)?,
+                    ))
+                }
+                BatchWriteOperation::Delete(item) => {
+                    let key = item.serialize_primary_key()?;
+
+                    Ok((key.clone(), delete_request(key)?))
+                }
+            })
+            .collect::<Result<Vec<_>, DynamoRepositoryError>>()?;
+
+        batch_write_items(
+            self.get_client(),
+            self.get_table_name(),
+            &self.get_retry_policy(),
+            requests,
+        )
+        .await
+    }
+
     async fn get<Index: RepositoryIndex>(&self, id: Index) -> Result<E, DynamoRepositoryError> {
         self.find(id)
             .await?
@@ -178,19 +720,124 @@ where
         &self,
         query_data: QueryData<Index>,
     ) -> Result<QueryResult<E>, DynamoRepositoryError> {
-        let expression_data = query_data.clone().get_expression_data();
-        Ok(self
-            .get_client()
-            .query()
-            .set_exclusive_start_key(query_data.last_evaluated_key.clone())
-            .set_expression_attribute_values(Some(expression_data.expression_attribute_values))
-            .key_condition_expression(expression_data.key_condition_expression)
-            .table_name(self.get_table_name())
-            .send()
-            .await
-            .map_err(|err| DynamoRepositoryError::from(err))?
+        let request =
+            query_data.apply(self.get_client().query().table_name(self.get_table_name()))?;
+
+        Ok(Operation::new("Query", self.get_table_name())
+            .with_index(query_data.get_index().get_index_name())
+            .run(&self.get_retry_policy(), || {
+                request.clone().send().map_err(DynamoRepositoryError::from)
+            })
+            .await?
+            .try_into()?)
+    }
+
+    /// Queries the views of the items, only reading the attributes of the view. Replaces any
+    /// projection set on `query_data`.
+    async fn query_view<V: View<Entity = E>, Index: RepositoryIndex>(
+        &self,
+        query_data: QueryData<Index>,
+    ) -> Result<QueryResult<V>, DynamoRepositoryError> {
+        let query_data = query_data.with_projection(view_projection::<V>());
+        let request =
+            query_data.apply(self.get_client().query().table_name(self.get_table_name()))?;
+        let query_output = Operation::new("Query", self.get_table_name())
+            .with_index(query_data.get_index().get_index_name())
+            .run(&self.get_retry_policy(), || {
+                request.clone().send().map_err(DynamoRepositoryError::from)
+            })
+            .await?;
+
+        QueryResult::read_items(
+            query_output.items.unwrap_or_default(),
+            query_output.last_evaluated_key,
+            read_view,
+        )
+    }
+
+    /// Queries an item collection holding several entity types, such as a note and its
+    /// comments sharing a partition key, reading every item into the variant of its type. Items
+    /// of types `T` doesn't know are left out.
+    async fn query_collection<T: EntityEnum, Index: RepositoryIndex>(
+        &self,
+        query_data: QueryData<Index>,
+    ) -> Result<QueryResult<T>, DynamoRepositoryError> {
+        let request =
+            query_data.apply(self.get_client().query().table_name(self.get_table_name()))?;
+        let query_output = Operation::new("Query", self.get_table_name())
+            .with_index(query_data.get_index().get_index_name())
+            .run(&self.get_retry_policy(), || {
+                request.clone().send().map_err(DynamoRepositoryError::from)
+            })
+            .await?;
+
+        QueryResult::read_items(
+            query_output.items.unwrap_or_default(),
+            query_output.last_evaluated_key,
+            T::from_item,
+        )
+    }
+
+    /// Streams the items of all pages of the query, see [`QueryStream`]
+    fn query_stream<'a, Index: RepositoryIndex + 'a>(
+        &'a self,
+        query_data: QueryData<Index>,
+    ) -> QueryStream<'a, E> {
+        let start_key = query_data.last_evaluated_key.clone();
+        let page_size = query_data.limit;
+
+        QueryStream::new(start_key, page_size, move |last_evaluated_key, limit| {
+            let mut query_data = query_data
+                .clone()
+                .with_last_evaluated_key(last_evaluated_key);
+            query_data.limit = limit;
+
+            self.query(query_data)
+        })
+    }
+
+    /// Reads a single page of the whole table, or of a single segment of it
+    async fn scan(&self, scan_data: ScanData) -> Result<QueryResult<E>, DynamoRepositoryError> {
+        let request =
+            scan_data.apply(self.get_client().scan().table_name(self.get_table_name()))?;
+
+        Ok(Operation::new("Scan", self.get_table_name())
+            .with_index(scan_data.index_name)
+            .run(&self.get_retry_policy(), || {
+                request.clone().send().map_err(DynamoRepositoryError::from)
+            })
+            .await?
             .try_into()?)
     }
+
+    /// Streams the items of all pages of the scan, see [`QueryStream`]
+    fn scan_stream(&self, scan_data: ScanData) -> QueryStream<'_, E> {
+        let start_key = scan_data.last_evaluated_key.clone();
+        let page_size = scan_data.limit;
+
+        QueryStream::new(start_key, page_size, move |last_evaluated_key, limit| {
+            let mut scan_data = scan_data
+                .clone()
+                .with_last_evaluated_key(last_evaluated_key);
+            scan_data.limit = limit;
+
+            self.scan(scan_data)
+        })
+    }
+
+    /// Scans the table with `segments` workers at once, merging their items into one stream in
+    /// no particular order. Each segment pages through its part of the table like
+    /// [`DynamoRepository::scan_stream`].
+    fn parallel_scan(
+        &self,
+        scan_data: ScanData,
+        segments: i32,
+    ) -> BoxStream<'_, Result<E, DynamoRepositoryError>> {
+        futures::stream::select_all((0..segments.max(1)).map(|segment| {
+            self.scan_stream(scan_data.clone().with_segment(segment, segments.max(1)))
+        }))
+        .boxed()
+    }
 }
 
 // #[cfg(test)]
@@ -0,0 +1,426 @@
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::repository::repository::DynamoRepositoryError;
+
+/// Kinds of errors a [`RetryPolicy`] can retry
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RetryableError {
+    /// DynamoDB rejected the request for exceeding the throughput of the table or the request
+    /// rate of the account, including transactions canceled for throttling
+    Throttled,
+    /// DynamoDB couldn't be reached, didn't respond in time or failed internally
+    Unavailable,
+    /// A transaction was canceled because another transaction was writing the same items
+    TransactionConflict,
+}
+
+impl RetryableError {
+    /// The kind of `err`, or `None` when it can't succeed by sending the request again
+    pub fn of(err: &DynamoRepositoryError) -> Option<Self> {
+        let canceled_for = |code: &str| {
+            matches!(err, DynamoRepositoryError::TransactionCanceled(failures)
+                if failures.iter().any(|failure| failure.code == code))
+        };
+
+        if err.is_throttled() || canceled_for("ThrottlingError") {
+            Some(RetryableError::Throttled)
+        } else if err.is_unavailable() {
+            Some(RetryableError::Unavailable)
+        } else if canceled_for("TransactionConflict") {
+            Some(RetryableError::TransactionConflict)
+        } else {
+            None
+        }
+    }
+}
+
+/// How a repository retries the requests DynamoDB failed temporarily. Attempts are spaced with
+/// exponential backoff and full jitter, and optionally paced by a shared [`RateLimiter`]. This
+/// comes on top of the retries of the SDK client itself.
+///
+/// Single-item writes are only retried when throttled by default, see
+/// [`RetryPolicy::with_retry_writes_on`].
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use orm::prelude::{RateLimiter, RetryPolicy, RetryableError};
+///
+/// let policy = RetryPolicy::default()
+///     .with_max_attempts(5)
+///     .with_backoff(Duration::from_millis(20), Duration::from_secs(2))
+///     .with_retry_on([RetryableError::Throttled])
+///     .with_rate_limiter(RateLimiter::new(100.0));
+/// ```
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    retry_on: Vec<RetryableError>,
+    retry_writes_on: Vec<RetryableError>,
+    rate_limiter: Option<RateLimiter>,
+}
+
+impl Default for RetryPolicy {
+    /// Three attempts at throttled and unavailable requests, backing off from 50ms up to 5s
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(50),
+            max_delay: Duration::from_secs(5),
+            retry_on: vec![RetryableError::Throttled, RetryableError::Unavailable],
+            retry_writes_on: vec![RetryableError::Throttled],
+            rate_limiter: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Sends every request once, surfacing the first error
+    pub fn none() -> Self {
+        Self::default().with_max_attempts(1)
+    }
+
+    /// Total number of attempts, including the first one
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// The delay before the second attempt, doubling on every following one up to `max_delay`.
+    /// The actual delay is a random duration up to that.
+    pub fn with_backoff(mut self, base_delay: Duration, max_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self.max_delay = max_delay;
+        self
+    }
+
+    pub fn with_retry_on(mut self, retry_on: impl IntoIterator<Item = RetryableError>) -> Self {
+        self.retry_on = retry_on.into_iter().collect();
+        self
+    }
+
+    /// Errors single-item writes are retried on, out of the ones of `with_retry_on`. Only
+    /// `Throttled` by default: an unavailable write may still have been applied, and sending it
+    /// again would apply its increments, appends and version bump twice. Add `Unavailable` when
+    /// the writes of the repository are idempotent.
+    pub fn with_retry_writes_on(
+        mut self,
+        retry_writes_on: impl IntoIterator<Item = RetryableError>,
+    ) -> Self {
+        self.retry_writes_on = retry_writes_on.into_iter().collect();
+        self
+    }
+
+    /// Paces every attempt through `rate_limiter`, which clones of the policy share
+    pub fn with_rate_limiter(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    pub fn get_rate_limiter(&self) -> Option<&RateLimiter> {
+        self.rate_limiter.as_ref()
+    }
+
+    /// Runs `operation` until it succeeds, fails with an error the policy doesn't retry, or runs
+    /// out of attempts
+    pub async fn run<T, F, Fut>(&self, mut operation: F) -> Result<T, DynamoRepositoryError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, DynamoRepositoryError>>,
+    {
+        let mut attempt = 1;
+
+        loop {
+            self.acquire().await;
+
+            let err = match operation().await {
+                Ok(output) => {
+                    self.on_success();
+                    return Ok(output);
+                }
+                Err(err) => err,
+            };
+
+            let kind = RetryableError::of(&err);
+
+            if kind == Some(RetryableError::Throttled) {
+                self.on_throttle();
+            }
+
+            if attempt >= self.max_attempts
+                || !kind.is_some_and(|kind| self.retry_on.contains(&kind))
+            {
+                return Err(err);
+            }
+
+            sleep(self.backoff(attempt)).await;
+            attempt += 1;
+        }
+    }
+
+    /// The policy of `PutItem`, `UpdateItem` and `DeleteItem` requests
+    pub(crate) fn for_writes(&self) -> Self {
+        Self {
+            retry_on: self
+                .retry_on
+                .iter()
+                .copied()
+                .filter(|kind| self.retry_writes_on.contains(kind))
+                .collect(),
+            ..self.clone()
+        }
+    }
+
+    /// Waits for the rate limiter, if any, to let the next request through
+    pub(crate) async fn acquire(&self) {
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.acquire().await;
+        }
+    }
+
+    pub(crate) fn on_success(&self) {
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.on_success();
+        }
+    }
+
+    pub(crate) fn on_throttle(&self) {
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.on_throttle();
+        }
+    }
+
+    /// Random delay up to the exponential backoff of `attempt`
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        let ceiling = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt - 1))
+            .min(self.max_delay);
+
+        ceiling.mul_f64(random())
+    }
+}
+
+/// Client side rate limiter that adapts to the throughput of the table. Every throttled request
+/// halves the rate, every successful one raises it again by a twentieth of the maximum, so
+/// long-running batch jobs settle just below what DynamoDB accepts. Clones share their state.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    min_rate: f64,
+    max_rate: f64,
+    state: Arc<Mutex<RateLimiterState>>,
+}
+
+#[derive(Debug)]
+struct RateLimiterState {
+    rate: f64,
+    next_slot: Instant,
+}
+
+impl RateLimiter {
+    /// Starts at `max_rate` requests per second and never drops below one per second
+    pub fn new(max_rate: f64) -> Self {
+        let max_rate = max_rate.max(f64::MIN_POSITIVE);
+
+        Self {
+            min_rate: max_rate.min(1.0),
+            max_rate,
+            state: Arc::new(Mutex::new(RateLimiterState {
+                rate: max_rate,
+                next_slot: Instant::now(),
+            })),
+        }
+    }
+
+    pub fn with_min_rate(mut self, min_rate: f64) -> Self {
+        self.min_rate = min_rate.clamp(f64::MIN_POSITIVE, self.max_rate);
+        self
+    }
+
+    /// Requests per second currently let through
+    pub fn get_rate(&self) -> f64 {
+        self.lock().rate
+    }
+
+    /// Waits for the next free slot
+    pub async fn acquire(&self) {
+        let now = Instant::now();
+        let slot = {
+            let mut state = self.lock();
+            let slot = state.next_slot.max(now);
+            state.next_slot = slot + Duration::from_secs_f64(1.0 / state.rate);
+            slot
+        };
+
+        sleep(slot - now).await;
+    }
+
+    pub fn on_success(&self) {
+        let mut state = self.lock();
+        state.rate = (state.rate + self.max_rate / 20.0).min(self.max_rate);
+    }
+
+    pub fn on_throttle(&self) {
+        let mut state = self.lock();
+        state.rate = (state.rate / 2.0).max(self.min_rate);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, RateLimiterState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+pub(crate) async fn sleep(duration: Duration) {
+    if !duration.is_zero() {
+        tokio::time::sleep(duration).await;
+    }
+}
+
+/// Random idempotency token of a request, which DynamoDB expects to be 36 characters at most
+pub(crate) fn request_token() -> String {
+    let bits = (RandomState::new().build_hasher().finish() as u128) << 64
+        | RandomState::new().build_hasher().finish() as u128;
+
+    format!("{:032x}", bits)
+}
+
+/// Uniformly distributed number in `[0, 1)`, good enough for jitter without pulling in a random
+/// number generator
+fn random() -> f64 {
+    let bits = RandomState::new().build_hasher().finish();
+
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+    use std::time::Duration;
+
+    use aws_sdk_dynamodb::error::SdkError;
+    use aws_sdk_dynamodb::operation::put_item::PutItemError;
+    use futures::executor::block_on;
+
+    use crate::prelude::{
+        DynamoRepositoryError, RateLimiter, RetryPolicy, RetryableError, TransactionFailure,
+    };
+
+    fn canceled(code: &str) -> DynamoRepositoryError {
+        DynamoRepositoryError::TransactionCanceled(vec![TransactionFailure {
+            index: 0,
+            operation: "Put into tasks".to_string(),
+            code: code.to_string(),
+            message: None,
+        }])
+    }
+
+    #[test]
+    fn it_retries_until_attempts_run_out() {
+        let policy = RetryPolicy::default()
+            .with_max_attempts(3)
+            .with_backoff(Duration::ZERO, Duration::ZERO);
+        let attempts = Cell::new(0);
+
+        let result: Result<(), _> = block_on(policy.run(|| {
+            attempts.set(attempts.get() + 1);
+            async { Err(canceled("ThrottlingError")) }
+        }));
+
+        assert!(result.is_err());
+        assert_eq!(attempts.get(), 3);
+    }
+
+    #[test]
+    fn it_only_retries_configured_errors() {
+        let policy = RetryPolicy::default()
+            .with_backoff(Duration::ZERO, Duration::ZERO)
+            .with_retry_on([RetryableError::TransactionConflict]);
+        let attempts = Cell::new(0);
+
+        let result = block_on(policy.run(|| {
+            attempts.set(attempts.get() + 1);
+            let result = match attempts.get() {
+                1 => Err(canceled("TransactionConflict")),
+                _ => Ok(attempts.get()),
+            };
+            async move { result }
+        }));
+
+        assert_eq!(result.unwrap(), 2);
+
+        attempts.set(0);
+        let result: Result<(), _> = block_on(policy.run(|| {
+            attempts.set(attempts.get() + 1);
+            async { Err(canceled("ThrottlingError")) }
+        }));
+
+        assert!(result.is_err());
+        assert_eq!(attempts.get(), 1);
+        assert_eq!(
+            RetryableError::of(&DynamoRepositoryError::ItemNotFoundError),
+            None
+        );
+    }
+
+    #[test]
+    fn it_only_retries_writes_when_throttled() {
+        let policy = RetryPolicy::default().with_backoff(Duration::ZERO, Duration::ZERO);
+        let attempts = Cell::new(0);
+        let unavailable = || {
+            attempts.set(attempts.get() + 1);
+            async {
+                Err::<(), _>(DynamoRepositoryError::from(
+                    SdkError::<PutItemError>::timeout_error("timed out"),
+                ))
+            }
+        };
+
+        assert!(block_on(policy.for_writes().run(unavailable)).is_err());
+        assert_eq!(attempts.get(), 1);
+
+        attempts.set(0);
+        assert!(block_on(policy.run(unavailable)).is_err());
+        assert_eq!(attempts.get(), 3);
+
+        attempts.set(0);
+        let policy = policy.with_retry_writes_on([RetryableError::Unavailable]);
+        assert!(block_on(policy.for_writes().run(unavailable)).is_err());
+        assert_eq!(attempts.get(), 3);
+    }
+
+    #[test]
+    fn it_keeps_backoff_within_bounds() {
+        let policy = RetryPolicy::default()
+            .with_backoff(Duration::from_millis(100), Duration::from_millis(300));
+
+        assert!(policy.backoff(1) < Duration::from_millis(100));
+        assert!(policy.backoff(5) < Duration::from_millis(300));
+    }
+
+    #[test]
+    fn it_adapts_rate_to_throttling() {
+        let rate_limiter = RateLimiter::new(100.0).with_min_rate(10.0);
+        let policy = RetryPolicy::none().with_rate_limiter(rate_limiter.clone());
+
+        let _: Result<(), _> = block_on(policy.run(|| async { Err(canceled("ThrottlingError")) }));
+        assert_eq!(rate_limiter.get_rate(), 50.0);
+
+        rate_limiter.on_throttle();
+        rate_limiter.on_throttle();
+        assert_eq!(rate_limiter.get_rate(), 12.5);
+
+        rate_limiter.on_throttle();
+        assert_eq!(rate_limiter.get_rate(), 10.0);
+
+        rate_limiter.on_success();
+        assert_eq!(rate_limiter.get_rate(), 15.0);
+    }
+}
@@ -0,0 +1,105 @@
+use aws_sdk_dynamodb::operation::scan::builders::ScanFluentBuilder;
+use aws_sdk_dynamodb::types::ReturnConsumedCapacity;
+
+use crate::repository::attributes::ExpressionAttributes;
+use crate::repository::expression::Expression;
+use crate::repository::repository::{DynamoRepositoryError, LastEvaluatedKey};
+
+/// Parameters of a `Scan`, which reads every item of the table or index:
+///
+/// ```
+/// use orm::prelude::{attribute, ScanData};
+///
+/// let scan = ScanData::new()
+///     .with_filter(attribute("encoded").not_exists())
+///     .with_projection(["pk", "sk", "id", "body"]);
+/// ```
+///
+/// Items are deserialized into the entity, so a projection has to keep every field that has no
+/// `#[serde(default)]`.
+#[derive(Debug, Clone, Default)]
+pub struct ScanData {
+    pub(crate) index_name: Option<&'static str>,
+    pub(crate) filter: Option<Expression>,
+    pub(crate) projection: Option<Vec<String>>,
+    pub(crate) limit: Option<i32>,
+    pub(crate) segment: Option<(i32, i32)>,
+    pub(crate) last_evaluated_key: Option<LastEvaluatedKey>,
+}
+
+impl ScanData {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Scans a global secondary index instead of the base table
+    pub fn with_index_name(mut self, index_name: &'static str) -> Self {
+        self.index_name = Some(index_name);
+        self
+    }
+
+    /// Filters the items DynamoDB read before returning them. Filtered items still consume
+    /// read capacity.
+    pub fn with_filter(mut self, filter: Expression) -> Self {
+        self.filter = Some(match self.filter {
+            Some(existing) => existing.and(filter),
+            None => filter,
+        });
+        self
+    }
+
+    /// Only reads the given attributes, or document paths, of every item
+    pub fn with_projection<P: Into<String>>(mut self, paths: impl IntoIterator<Item = P>) -> Self {
+        self.projection = Some(paths.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Maximum number of items DynamoDB reads for a single page
+    pub fn with_limit(mut self, limit: i32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Only scans `segment` out of `total_segments`, see [`DynamoRepository::parallel_scan`]
+    ///
+    /// [`DynamoRepository::parallel_scan`]: crate::prelude::DynamoRepository::parallel_scan
+    pub fn with_segment(mut self, segment: i32, total_segments: i32) -> Self {
+        self.segment = Some((segment, total_segments));
+        self
+    }
+
+    pub fn with_last_evaluated_key(mut self, last_evaluated_key: Option<LastEvaluatedKey>) -> Self {
+        self.last_evaluated_key = last_evaluated_key;
+        self
+    }
+
+    /// Sets the parameters on a `Scan` request
+    pub(crate) fn apply(
+        &self,
+        request: ScanFluentBuilder,
+    ) -> Result<ScanFluentBuilder, DynamoRepositoryError> {
+        let mut attributes = ExpressionAttributes::new();
+
+        let filter_expression = self
+            .filter
+            .as_ref()
+            .map(|filter| filter.build(&mut attributes))
+            .transpose()?;
+        let projection_expression = self
+            .projection
+            .as_ref()
+            .map(|paths| attributes.projection(paths));
+
+        Ok(request
+            .set_index_name(self.index_name.map(String::from))
+            .set_filter_expression(filter_expression)
+            .set_projection_expression(projection_expression)
+            .set_expression_attribute_names(attributes.names())
+            .set_expression_attribute_values(attributes.values())
+            .set_limit(self.limit)
+            .set_segment(self.segment.map(|(segment, _)| segment))
+            .set_total_segments(self.segment.map(|(_, total_segments)| total_segments))
+            .set_exclusive_start_key(self.last_evaluated_key.clone())
+            .return_consumed_capacity(ReturnConsumedCapacity::Total))
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::future::BoxFuture;
+use futures::Stream;
+
+use crate::repository::entity::Entity;
+use crate::repository::repository::{DynamoRepositoryError, LastEvaluatedKey, QueryResult};
+
+type FetchPage<'a, E> = Box<
+    dyn FnMut(
+            Option<LastEvaluatedKey>,
+            Option<i32>,
+        ) -> BoxFuture<'a, Result<QueryResult<E>, DynamoRepositoryError>>
+        + Send
+        + 'a,
+>;
+
+/// Stream of the items of a query or scan, fetching pages lazily as the items are consumed.
+///
+/// No page is requested before the stream is polled, and dropping the stream stops the paging,
+/// so `StreamExt::take_while` and friends stop early without reading the rest of the partition:
+///
+/// ```ignore
+/// let recent: Vec<NoteEntity> = service
+///     .query_stream(QueryData::new(NotePartitionKey::find_all(), None))
+///     .prefetch(1)
+///     .take(50)
+///     .try_collect()
+///     .await?;
+/// ```
+pub struct QueryStream<'a, E: Entity> {
+    fetch_page: FetchPage<'a, E>,
+    in_flight: Option<BoxFuture<'a, Result<QueryResult<E>, DynamoRepositoryError>>>,
+    pages: VecDeque<VecDeque<E>>,
+    next_key: Option<LastEvaluatedKey>,
+    has_more: bool,
+    prefetch: usize,
+    page_size: Option<i32>,
+    remaining: Option<usize>,
+    done: bool,
+}
+
+// Items are only ever moved out of the buffer, never pinned
+impl<E: Entity> Unpin for QueryStream<'_, E> {}
+
+impl<'a, E: Entity> QueryStream<'a, E> {
+    /// `fetch_page` is called with the key to continue from and the maximum number of items
+    /// to read
+    pub fn new<F>(
+        start_key: Option<LastEvaluatedKey>,
+        page_size: Option<i32>,
+        fetch_page: F,
+    ) -> Self
+    where
+        F: FnMut(
+                Option<LastEvaluatedKey>,
+                Option<i32>,
+            ) -> BoxFuture<'a, Result<QueryResult<E>, DynamoRepositoryError>>
+            + Send
+            + 'a,
+    {
+        Self {
+            fetch_page: Box::new(fetch_page),
+            in_flight: None,
+            pages: VecDeque::new(),
+            next_key: start_key,
+            has_more: true,
+            prefetch: 0,
+            page_size,
+            remaining: None,
+            done: false,
+        }
+    }
+
+    /// Number of pages to fetch ahead of the page being consumed
+    pub fn prefetch(mut self, pages: usize) -> Self {
+        self.prefetch = pages;
+        self
+    }
+
+    /// Ends the stream after `items` items. Unlike `StreamExt::take`, the `Limit` of the last
+    /// requests is lowered as well, so no capacity is spent on items that won't be returned.
+    pub fn take(mut self, items: usize) -> Self {
+        self.remaining = Some(items);
+        self
+    }
+
+    fn requested_items(&self) -> usize {
+        self.pages.iter().map(VecDeque::len).sum()
+    }
+
+    fn next_limit(&self) -> Option<i32> {
+        let remaining = self.remaining.map(|remaining| {
+            i32::try_from(remaining.saturating_sub(self.requested_items())).unwrap_or(i32::MAX)
+        });
+
+        match (self.page_size, remaining) {
+            (Some(page_size), Some(remaining)) => Some(page_size.min(remaining)),
+            (page_size, remaining) => page_size.or(remaining),
+        }
+    }
+}
+
+impl<E: Entity> Stream for QueryStream<'_, E> {
+    type Item = Result<E, DynamoRepositoryError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            if this.done || this.remaining == Some(0) {
+                return Poll::Ready(None);
+            }
+
+            let limit = this.next_limit();
+
+            if this.in_flight.is_none()
+                && this.has_more
+                && this.pages.len() <= this.prefetch
+                && limit != Some(0)
+            {
+                let start_key = this.next_key.take();
+                this.in_flight = Some((this.fetch_page)(start_key, limit));
+            }
+
+            if let Some(in_flight) = this.in_flight.as_mut() {
+                if let Poll::Ready(result) = in_flight.as_mut().poll(cx) {
+                    this.in_flight = None;
+
+                    match result {
+                        Ok(page) => {
+                            this.has_more = page.last_evaluated_key.is_some();
+                            this.next_key = page.last_evaluated_key;
+                            this.pages.push_back(page.items.into());
+                        }
+                        Err(err) => {
+                            this.done = true;
+                            return Poll::Ready(Some(Err(err)));
+                        }
+                    }
+
+                    continue;
+                }
+            }
+
+            match this.pages.front_mut() {
+                Some(page) => match page.pop_front() {
+                    Some(item) => {
+                        if let Some(remaining) = this.remaining.as_mut() {
+                            *remaining -= 1;
+                        }
+
+                        return Poll::Ready(Some(Ok(item)));
+                    }
+                    None => {
+                        this.pages.pop_front();
+                    }
+                },
+                None if this.in_flight.is_none() => {
+                    this.done = true;
+                }
+                None => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use aws_sdk_dynamodb::types::AttributeValue;
+    use futures::executor::block_on;
+    use futures::{FutureExt, TryStreamExt};
+    use serde::{Deserialize, Serialize};
+
+    use crate::prelude::{Entity, QueryResult, QueryStream};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize, Entity)]
+    #[entity(pk = "NUMBER", sk = "{value}")]
+    struct NumberEntity {
+        value: usize,
+    }
+
+    /// Pages of three numbers, up to 10, recording the limit of every request
+    fn numbers(requests: Arc<Mutex<Vec<Option<i32>>>>) -> QueryStream<'static, NumberEntity> {
+        QueryStream::new(None, Some(3), move |start_key, limit| {
+            requests.lock().unwrap().push(limit);
+
+            let start = start_key.map_or(0, |key| match &key["value"] {
+                AttributeValue::N(value) => value.parse::<usize>().unwrap(),
+                _ => unreachable!(),
+            });
+            let end = (start + limit.unwrap() as usize).min(10);
+
+            async move {
+                Ok(QueryResult {
+                    items: (start..end).map(|value| NumberEntity { value }).collect(),
+                    last_evaluated_key: (end < 10).then(|| {
+                        HashMap::from([("value".to_string(), AttributeValue::N(end.to_string()))])
+                    }),
+                })
+            }
+            .boxed()
+        })
+    }
+
+    #[test]
+    fn it_streams_every_page() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let items: Vec<NumberEntity> = block_on(numbers(requests.clone()).try_collect()).unwrap();
+
+        assert_eq!(items.len(), 10);
+        assert_eq!(items[9], NumberEntity { value: 9 });
+        assert_eq!(requests.lock().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn it_lowers_the_limit_when_taking() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let items: Vec<NumberEntity> =
+            block_on(numbers(requests.clone()).take(4).try_collect()).unwrap();
+
+        assert_eq!(items.len(), 4);
+        assert_eq!(*requests.lock().unwrap(), vec![Some(3), Some(1)]);
+    }
+
+    #[test]
+    fn it_prefetches_pages() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let mut stream = numbers(requests.clone()).prefetch(1);
+
+        block_on(stream.try_next()).unwrap();
+
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+}
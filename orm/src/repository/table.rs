@@ -0,0 +1,593 @@
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+use aws_sdk_dynamodb::operation::create_table::CreateTableError;
+use aws_sdk_dynamodb::operation::describe_table::DescribeTableError;
+use aws_sdk_dynamodb::types::{
+    AttributeDefinition, BillingMode, CreateGlobalSecondaryIndexAction, GlobalSecondaryIndex,
+    GlobalSecondaryIndexUpdate, IndexStatus, KeySchemaElement, KeyType, Projection, ProjectionType,
+    ProvisionedThroughput, ScalarAttributeType, TableDescription, TableStatus,
+    TimeToLiveSpecification, TimeToLiveStatus,
+};
+
+use crate::repository::entity::Entity;
+use crate::repository::repository::DynamoRepositoryError;
+
+/// How often the table status is checked while waiting for a new table or index to become active
+const ACTIVE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const MAX_ACTIVE_POLLS: u32 = 240;
+/// Indexes are filled with the items of the table before they become active, which takes longer
+const MAX_INDEX_POLLS: u32 = 1_800;
+
+/// Key attributes of an entity's table, as declared through `#[entity]` and `#[gsi]`.
+///
+/// The partition and sort keys are `pk` and `sk`, and the ones of a global secondary index are
+/// `<name>_pk` and `<name>_sk`. All of them are strings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeySchema {
+    pub sort_key: bool,
+    pub indexes: Vec<IndexSchema>,
+}
+
+impl Default for KeySchema {
+    fn default() -> Self {
+        Self {
+            sort_key: true,
+            indexes: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexSchema {
+    pub name: &'static str,
+    pub sort_key: bool,
+}
+
+/// A difference between the declared table and the live one
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaDrift {
+    /// Key attributes of the table, or of the index when set, as `attribute HASH|RANGE`
+    KeySchema {
+        index: Option<String>,
+        expected: Vec<String>,
+        found: Vec<String>,
+    },
+    MissingIndex(String),
+    UnexpectedIndex(String),
+    BillingMode {
+        expected: BillingMode,
+        found: BillingMode,
+    },
+    TimeToLive {
+        expected: Option<String>,
+        found: Option<String>,
+    },
+}
+
+impl Display for SchemaDrift {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SchemaDrift::KeySchema {
+                index,
+                expected,
+                found,
+            } => write!(
+                f,
+                "key schema of {} is [{}] instead of [{}]",
+                index.as_deref().unwrap_or("the table"),
+                found.join(", "),
+                expected.join(", ")
+            ),
+            SchemaDrift::MissingIndex(index) => write!(f, "index {} is missing", index),
+            SchemaDrift::UnexpectedIndex(index) => write!(f, "index {} isn't declared", index),
+            SchemaDrift::BillingMode { expected, found } => write!(
+                f,
+                "billing mode is {} instead of {}",
+                found.as_str(),
+                expected.as_str()
+            ),
+            SchemaDrift::TimeToLive { expected, found } => write!(
+                f,
+                "time to live attribute is {:?} instead of {:?}",
+                found, expected
+            ),
+        }
+    }
+}
+
+/// The `CreateTable` definition of an entity's table:
+///
+/// ```
+/// use orm::prelude::TableDefinition;
+/// # #[derive(serde::Serialize, serde::Deserialize, orm::prelude::Entity)]
+/// # #[entity(pk = "NOTE", sk = "NOTE_ID#{id}")]
+/// # struct NoteEntity { id: u32 }
+///
+/// let definition = TableDefinition::new::<NoteEntity>("notes")
+///     .with_provisioned_throughput(5, 5)
+///     .with_ttl_attribute("expires_at");
+/// ```
+///
+/// Tables are billed per request unless a provisioned throughput is set. Time to live is enabled on
+/// the `#[entity(ttl)]` field of the entity, unless another attribute is set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableDefinition {
+    table_name: String,
+    key_schema: KeySchema,
+    throughput: Option<(i64, i64)>,
+    ttl_attribute: Option<String>,
+}
+
+impl TableDefinition {
+    pub fn new<E: Entity>(table_name: impl Into<String>) -> Self {
+        Self {
+            table_name: table_name.into(),
+            key_schema: E::get_key_schema(),
+            throughput: None,
+            ttl_attribute: E::get_ttl_attribute().map(String::from),
+        }
+    }
+
+    /// Provisions read and write capacity units for the table and each of its indexes
+    pub fn with_provisioned_throughput(mut self, read: i64, write: i64) -> Self {
+        self.throughput = Some((read, write));
+        self
+    }
+
+    pub fn with_ttl_attribute(mut self, attribute: impl Into<String>) -> Self {
+        self.ttl_attribute = Some(attribute.into());
+        self
+    }
+
+    pub fn get_table_name(&self) -> &str {
+        &self.table_name
+    }
+
+    pub fn get_ttl_attribute(&self) -> Option<&str> {
+        self.ttl_attribute.as_deref()
+    }
+
+    pub fn billing_mode(&self) -> BillingMode {
+        match self.throughput {
+            Some(_) => BillingMode::Provisioned,
+            None => BillingMode::PayPerRequest,
+        }
+    }
+
+    pub fn key_schema(&self) -> Result<Vec<KeySchemaElement>, DynamoRepositoryError> {
+        key_schema("pk", self.key_schema.sort_key.then_some("sk"))
+    }
+
+    pub fn attribute_definitions(&self) -> Result<Vec<AttributeDefinition>, DynamoRepositoryError> {
+        self.key_attributes()
+            .into_iter()
+            .map(|attribute| {
+                Ok(AttributeDefinition::builder()
+                    .attribute_name(attribute)
+                    .attribute_type(ScalarAttributeType::S)
+                    .build()?)
+            })
+            .collect()
+    }
+
+    /// Indexes project all attributes, so they can be queried like the table
+    pub fn global_secondary_indexes(
+        &self,
+    ) -> Result<Vec<GlobalSecondaryIndex>, DynamoRepositoryError> {
+        self.key_schema
+            .indexes
+            .iter()
+            .map(|index| {
+                let (pk, sk) = index_attributes(index);
+
+                Ok(GlobalSecondaryIndex::builder()
+                    .index_name(index.name)
+                    .set_key_schema(Some(key_schema(&pk, sk.as_deref())?))
+                    .projection(
+                        Projection::builder()
+                            .projection_type(ProjectionType::All)
+                            .build(),
+                    )
+                    .set_provisioned_throughput(self.provisioned_throughput()?)
+                    .build()?)
+            })
+            .collect()
+    }
+
+    pub fn provisioned_throughput(
+        &self,
+    ) -> Result<Option<ProvisionedThroughput>, DynamoRepositoryError> {
+        self.throughput
+            .map(|(read, write)| {
+                Ok(ProvisionedThroughput::builder()
+                    .read_capacity_units(read)
+                    .write_capacity_units(write)
+                    .build()?)
+            })
+            .transpose()
+    }
+
+    /// Creates the table when it doesn't exist and waits for it to become active. Indexes missing
+    /// from an existing table are added one at a time, as DynamoDB allows, and filled with its
+    /// items. Any other difference from the definition fails with `SchemaDrift`.
+    #[tracing::instrument(
+        name = "dynamodb",
+        skip_all,
+        fields(table = %self.table_name, operation = "EnsureTable")
+    )]
+    pub async fn ensure(
+        &self,
+        client: &aws_sdk_dynamodb::Client,
+    ) -> Result<(), DynamoRepositoryError> {
+        let table = match self.describe(client).await? {
+            Some(table) => table,
+            None if self.create(client).await? => return Ok(()),
+            // Another instance starting at the same time created it first, it's checked like any
+            // existing table
+            None => self.wait_until_active(client).await?,
+        };
+        let (missing, drift): (Vec<_>, Vec<_>) = self
+            .drift(&table, self.describe_ttl(client).await?.as_deref())
+            .into_iter()
+            .partition(|drift| matches!(drift, SchemaDrift::MissingIndex(_)));
+
+        for missing in missing {
+            if let SchemaDrift::MissingIndex(index) = missing {
+                self.create_index(client, &index).await?;
+            }
+        }
+
+        match drift.is_empty() {
+            true => Ok(()),
+            false => Err(DynamoRepositoryError::SchemaDrift(drift)),
+        }
+    }
+
+    /// Differences between the definition and the live `table`, whose time to live attribute is
+    /// `ttl_attribute`
+    pub fn drift(&self, table: &TableDescription, ttl_attribute: Option<&str>) -> Vec<SchemaDrift> {
+        let mut drift = Vec::new();
+
+        let expected = describe_key_schema(&self.key_schema().unwrap_or_default());
+        let found = describe_key_schema(table.key_schema());
+
+        if expected != found {
+            drift.push(SchemaDrift::KeySchema {
+                index: None,
+                expected,
+                found,
+            });
+        }
+
+        for index in &self.key_schema.indexes {
+            let (pk, sk) = index_attributes(index);
+            let expected = describe_key_schema(&key_schema(&pk, sk.as_deref()).unwrap_or_default());
+
+            match table
+                .global_secondary_indexes()
+                .iter()
+                .find(|live| live.index_name() == Some(index.name))
+            {
+                Some(live) => {
+                    let found = describe_key_schema(live.key_schema());
+
+                    if expected != found {
+                        drift.push(SchemaDrift::KeySchema {
+                            index: Some(index.name.to_string()),
+                            expected,
+                            found,
+                        });
+                    }
+                }
+                None => drift.push(SchemaDrift::MissingIndex(index.name.to_string())),
+            }
+        }
+
+        for live in table.global_secondary_indexes() {
+            let name = live.index_name().unwrap_or_default();
+
+            if !self
+                .key_schema
+                .indexes
+                .iter()
+                .any(|index| index.name == name)
+            {
+                drift.push(SchemaDrift::UnexpectedIndex(name.to_string()));
+            }
+        }
+
+        // Tables created with provisioned throughput may not report a billing mode
+        let billing_mode = table
+            .billing_mode_summary()
+            .and_then(|summary| summary.billing_mode())
+            .cloned()
+            .unwrap_or(BillingMode::Provisioned);
+
+        if billing_mode != self.billing_mode() {
+            drift.push(SchemaDrift::BillingMode {
+                expected: self.billing_mode(),
+                found: billing_mode,
+            });
+        }
+
+        if ttl_attribute != self.get_ttl_attribute() {
+            drift.push(SchemaDrift::TimeToLive {
+                expected: self.ttl_attribute.clone(),
+                found: ttl_attribute.map(String::from),
+            });
+        }
+
+        drift
+    }
+
+    async fn describe(
+        &self,
+        client: &aws_sdk_dynamodb::Client,
+    ) -> Result<Option<TableDescription>, DynamoRepositoryError> {
+        match client
+            .describe_table()
+            .table_name(&self.table_name)
+            .send()
+            .await
+        {
+            Ok(output) => Ok(output.table),
+            Err(err) => match err.as_service_error() {
+                Some(DescribeTableError::ResourceNotFoundException(_)) => Ok(None),
+                _ => Err(err.into()),
+            },
+        }
+    }
+
+    /// The time to live attribute, when enabled
+    async fn describe_ttl(
+        &self,
+        client: &aws_sdk_dynamodb::Client,
+    ) -> Result<Option<String>, DynamoRepositoryError> {
+        let description = client
+            .describe_time_to_live()
+            .table_name(&self.table_name)
+            .send()
+            .await?
+            .time_to_live_description;
+
+        Ok(description
+            .filter(|description| {
+                matches!(
+                    description.time_to_live_status(),
+                    Some(TimeToLiveStatus::Enabled | TimeToLiveStatus::Enabling)
+                )
+            })
+            .and_then(|description| description.attribute_name))
+    }
+
+    /// Creates the table with its time to live, or returns `false` when it already exists
+    async fn create(
+        &self,
+        client: &aws_sdk_dynamodb::Client,
+    ) -> Result<bool, DynamoRepositoryError> {
+        let indexes = self.global_secondary_indexes()?;
+
+        let created = client
+            .create_table()
+            .table_name(&self.table_name)
+            .set_key_schema(Some(self.key_schema()?))
+            .set_attribute_definitions(Some(self.attribute_definitions()?))
+            .set_global_secondary_indexes(Some(indexes).filter(|indexes| !indexes.is_empty()))
+            .billing_mode(self.billing_mode())
+            .set_provisioned_throughput(self.provisioned_throughput()?)
+            .send()
+            .await;
+
+        if let Err(err) = created {
+            return match err.as_service_error() {
+                Some(CreateTableError::ResourceInUseException(_)) => Ok(false),
+                _ => Err(err.into()),
+            };
+        }
+
+        self.wait_until_active(client).await?;
+
+        if let Some(ttl_attribute) = &self.ttl_attribute {
+            client
+                .update_time_to_live()
+                .table_name(&self.table_name)
+                .time_to_live_specification(
+                    TimeToLiveSpecification::builder()
+                        .enabled(true)
+                        .attribute_name(ttl_attribute)
+                        .build()?,
+                )
+                .send()
+                .await?;
+        }
+
+        Ok(true)
+    }
+
+    /// Adds the declared index `name` to the existing table and waits until it's filled
+    async fn create_index(
+        &self,
+        client: &aws_sdk_dynamodb::Client,
+        name: &str,
+    ) -> Result<(), DynamoRepositoryError> {
+        let Some(index) = self
+            .global_secondary_indexes()?
+            .into_iter()
+            .find(|index| index.index_name() == name)
+        else {
+            return Ok(());
+        };
+
+        client
+            .update_table()
+            .table_name(&self.table_name)
+            .set_attribute_definitions(Some(self.attribute_definitions()?))
+            .global_secondary_index_updates(
+                GlobalSecondaryIndexUpdate::builder()
+                    .create(
+                        CreateGlobalSecondaryIndexAction::builder()
+                            .index_name(name)
+                            .set_key_schema(Some(index.key_schema))
+                            .set_projection(index.projection)
+                            .set_provisioned_throughput(index.provisioned_throughput)
+                            .build()?,
+                    )
+                    .build(),
+            )
+            .send()
+            .await?;
+
+        self.wait_for(client, MAX_INDEX_POLLS).await?;
+
+        Ok(())
+    }
+
+    async fn wait_until_active(
+        &self,
+        client: &aws_sdk_dynamodb::Client,
+    ) -> Result<TableDescription, DynamoRepositoryError> {
+        self.wait_for(client, MAX_ACTIVE_POLLS).await
+    }
+
+    /// Waits until the table and all of its indexes are active
+    async fn wait_for(
+        &self,
+        client: &aws_sdk_dynamodb::Client,
+        polls: u32,
+    ) -> Result<TableDescription, DynamoRepositoryError> {
+        for _ in 0..polls {
+            if let Some(table) = self.describe(client).await?.filter(|table| {
+                table.table_status == Some(TableStatus::Active)
+                    && table
+                        .global_secondary_indexes()
+                        .iter()
+                        .all(|index| index.index_status == Some(IndexStatus::Active))
+            }) {
+                return Ok(table);
+            }
+
+            tokio::time::sleep(ACTIVE_POLL_INTERVAL).await;
+        }
+
+        Err(DynamoRepositoryError::TableNotActive(
+            self.table_name.clone(),
+        ))
+    }
+
+    /// Every key attribute of the table and its indexes
+    fn key_attributes(&self) -> Vec<String> {
+        let mut attributes = vec!["pk".to_string()];
+
+        if self.key_schema.sort_key {
+            attributes.push("sk".to_string());
+        }
+
+        for index in &self.key_schema.indexes {
+            let (pk, sk) = index_attributes(index);
+
+            attributes.push(pk);
+            attributes.extend(sk);
+        }
+
+        attributes
+    }
+}
+
+fn index_attributes(index: &IndexSchema) -> (String, Option<String>) {
+    (
+        format!("{}_pk", index.name),
+        index.sort_key.then(|| format!("{}_sk", index.name)),
+    )
+}
+
+fn key_schema(pk: &str, sk: Option<&str>) -> Result<Vec<KeySchemaElement>, DynamoRepositoryError> {
+    std::iter::once((pk, KeyType::Hash))
+        .chain(sk.map(|sk| (sk, KeyType::Range)))
+        .map(|(attribute, key_type)| {
+            Ok(KeySchemaElement::builder()
+                .attribute_name(attribute)
+                .key_type(key_type)
+                .build()?)
+        })
+        .collect()
+}
+
+fn describe_key_schema(key_schema: &[KeySchemaElement]) -> Vec<String> {
+    key_schema
+        .iter()
+        .map(|element| {
+            format!(
+                "{} {}",
+                element.attribute_name(),
+                element.key_type().as_str()
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use aws_sdk_dynamodb::types::{
+        BillingMode, BillingModeSummary, GlobalSecondaryIndexDescription, TableDescription,
+    };
+    use serde::{Deserialize, Serialize};
+
+    use crate::prelude::{Entity, SchemaDrift, TableDefinition};
+
+    #[derive(Serialize, Deserialize, Entity)]
+    #[entity(pk = "USER", sk = "USER_ID#{id}")]
+    #[gsi(name = "gsi1", pk = "EMAIL#{email}")]
+    struct UserEntity {
+        id: u32,
+        email: String,
+    }
+
+    #[test]
+    fn it_defines_keys_and_indexes() {
+        let definition = TableDefinition::new::<UserEntity>("users");
+
+        let attributes: Vec<String> = definition
+            .attribute_definitions()
+            .unwrap()
+            .iter()
+            .map(|attribute| attribute.attribute_name().to_string())
+            .collect();
+        let indexes = definition.global_secondary_indexes().unwrap();
+
+        assert_eq!(attributes, ["pk", "sk", "gsi1_pk"]);
+        assert_eq!(indexes[0].index_name(), "gsi1");
+        assert_eq!(indexes[0].key_schema().len(), 1);
+    }
+
+    #[test]
+    fn it_detects_drift() {
+        let definition = TableDefinition::new::<UserEntity>("users").with_ttl_attribute("ttl");
+
+        let table = TableDescription::builder()
+            .set_key_schema(Some(definition.key_schema().unwrap()))
+            .global_secondary_indexes(
+                GlobalSecondaryIndexDescription::builder()
+                    .index_name("gsi2")
+                    .build(),
+            )
+            .billing_mode_summary(
+                BillingModeSummary::builder()
+                    .billing_mode(BillingMode::PayPerRequest)
+                    .build(),
+            )
+            .build();
+
+        assert_eq!(
+            definition.drift(&table, None),
+            [
+                SchemaDrift::MissingIndex("gsi1".to_string()),
+                SchemaDrift::UnexpectedIndex("gsi2".to_string()),
+                SchemaDrift::TimeToLive {
+                    expected: Some("ttl".to_string()),
+                    found: None
+                },
+            ]
+        );
+    }
+}
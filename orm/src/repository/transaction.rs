@@ -0,0 +1,824 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+use aws_sdk_dynamodb::error::SdkError;
+use aws_sdk_dynamodb::operation::transact_write_items::TransactWriteItemsError;
+use aws_sdk_dynamodb::types::error::TransactionCanceledException;
+use aws_sdk_dynamodb::types::{
+    AttributeValue, ConditionCheck, Delete, Put, ReturnConsumedCapacity, TransactWriteItem,
+    Update as UpdateRequest,
+};
+use futures::TryFutureExt;
+use serde::Serialize;
+
+use crate::repository::attributes::ExpressionAttributes;
+use crate::repository::entity::Entity;
+use crate::repository::expression::Expression;
+use crate::repository::instrument::Operation;
+use crate::repository::memory::InMemoryTable;
+use crate::repository::repository::{DynamoRepository, DynamoRepositoryError, RepositoryIndex};
+use crate::repository::retry::{request_token, RetryPolicy};
+use crate::repository::ttl::create_condition;
+use crate::repository::update::Update;
+use crate::repository::write::{prepare_delete, prepare_put, prepare_update, PutMode};
+
+/// Maximum number of operations of a single `TransactWriteItems` call
+pub const TRANSACTION_LIMIT: usize = 100;
+
+type Item = HashMap<String, AttributeValue>;
+
+/// Why a single operation of a canceled transaction failed
+#[derive(Debug, Clone)]
+pub struct TransactionFailure {
+    /// Position of the operation in the transaction
+    pub index: usize,
+    /// Description of the operation, e.g. `Delete from notes`
+    pub operation: String,
+    /// Cancellation code, e.g. `ConditionalCheckFailed`
+    pub code: String,
+    pub message: Option<String>,
+}
+
+impl Display for TransactionFailure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "operation {} ({}) failed with {}",
+            self.index, self.operation, self.code
+        )?;
+
+        if let Some(message) = &self.message {
+            write!(f, ": {}", message)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Where a repository commits the transactions it takes part in, see
+/// [`DynamoRepository::get_transaction_target`]
+#[derive(Debug, Clone)]
+pub enum TransactionTarget {
+    /// DynamoDB, through `TransactWriteItems`
+    Client(aws_sdk_dynamodb::Client),
+    /// The items of an [`InMemoryRepository`](crate::repository::memory::InMemoryRepository)
+    InMemory(InMemoryTable),
+}
+
+/// A single write of a transaction, built into a `TransactWriteItem` when it's sent to DynamoDB
+#[derive(Debug, Clone)]
+pub(crate) enum TransactionWrite {
+    Put {
+        key: Item,
+        item: Item,
+        condition: Option<Expression>,
+    },
+    Update {
+        key: Item,
+        update: Update,
+        condition: Expression,
+    },
+    Delete {
+        key: Item,
+        condition: Option<Expression>,
+    },
+    ConditionCheck {
+        key: Item,
+        condition: Expression,
+    },
+}
+
+impl TransactionWrite {
+    pub(crate) fn key(&self) -> &Item {
+        match self {
+            Self::Put { key, .. }
+            | Self::Update { key, .. }
+            | Self::Delete { key, .. }
+            | Self::ConditionCheck { key, .. } => key,
+        }
+    }
+
+    /// Fails with the error of a value that couldn't be serialized
+    pub(crate) fn validate(&self) -> Result<(), DynamoRepositoryError> {
+        match self {
+            Self::Put { condition, .. } | Self::Delete { condition, .. } => {
+                condition.as_ref().map(Expression::validate).transpose()?;
+                Ok(())
+            }
+            Self::Update {
+                update, condition, ..
+            } => {
+                update.validate()?;
+                condition.validate()
+            }
+            Self::ConditionCheck { condition, .. } => condition.validate(),
+        }
+    }
+
+    fn build(self, table_name: &str) -> Result<TransactWriteItem, DynamoRepositoryError> {
+        let mut attributes = ExpressionAttributes::new();
+
+        let item = match self {
+            Self::Put {
+                item, condition, ..
+            } => {
+                let condition_expression = condition
+                    .map(|condition| condition.build(&mut attributes))
+                    .transpose()?;
+
+                TransactWriteItem::builder().put(
+                    Put::builder()
+                        .table_name(table_name)
+                        .set_item(Some(item))
+                        .set_condition_expression(condition_expression)
+                        .set_expression_attribute_names(attributes.names())
+                        .set_expression_attribute_values(attributes.values())
+                        .build()?,
+                )
+            }
+            Self::Update {
+                key,
+                update,
+                condition,
+            } => {
+                let update_expression = update.build(&mut attributes)?;
+                let condition_expression = condition.build(&mut attributes)?;
+
+                TransactWriteItem::builder().update(
+                    UpdateRequest::builder()
+                        .table_name(table_name)
+                        .set_key(Some(key))
+                        .update_expression(update_expression)
+                        .condition_expression(condition_expression)
+                        .set_expression_attribute_names(attributes.names())
+                        .set_expression_attribute_values(attributes.values())
+                        .build()?,
+                )
+            }
+            Self::Delete { key, condition } => {
+                let condition_expression = condition
+                    .map(|condition| condition.build(&mut attributes))
+                    .transpose()?;
+
+                TransactWriteItem::builder().delete(
+                    Delete::builder()
+                        .table_name(table_name)
+                        .set_key(Some(key))
+                        .set_condition_expression(condition_expression)
+                        .set_expression_attribute_names(attributes.names())
+                        .set_expression_attribute_values(attributes.values())
+                        .build()?,
+                )
+            }
+            Self::ConditionCheck { key, condition } => {
+                let condition_expression = condition.build(&mut attributes)?;
+
+                TransactWriteItem::builder().condition_check(
+                    ConditionCheck::builder()
+                        .table_name(table_name)
+                        .set_key(Some(key))
+                        .condition_expression(condition_expression)
+                        .set_expression_attribute_names(attributes.names())
+                        .set_expression_attribute_values(attributes.values())
+                        .build()?,
+                )
+            }
+        };
+
+        Ok(item.build())
+    }
+}
+
+/// A write of a transaction with the table it writes to
+#[derive(Debug, Clone)]
+pub(crate) struct TransactionOperation {
+    pub(crate) table_name: &'static str,
+    /// E.g. `Delete from notes`
+    pub(crate) description: String,
+    pub(crate) write: TransactionWrite,
+}
+
+/// Collects writes on any entity and repository and commits them atomically through
+/// `TransactWriteItems`:
+///
+/// ```ignore
+/// Transaction::new()
+///     .create(&notes_repository, note)
+///     .put(&tags_repository, tag)
+///     .condition_check(&notebooks_repository, notebook_key, attribute("archived").not_exists())
+///     .commit()
+///     .await?;
+/// ```
+///
+/// Versioned entities are checked and bumped the same way as their single-item counterparts.
+///
+/// Transactions on [`InMemoryRepository`](crate::repository::memory::InMemoryRepository)s are
+/// applied to their items instead, and can't include repositories backed by DynamoDB.
+#[derive(Default)]
+pub struct Transaction {
+    retry_policy: Option<RetryPolicy>,
+    /// Tables written to, with where their repository commits transactions
+    targets: Vec<(&'static str, TransactionTarget)>,
+    operations: Vec<TransactionOperation>,
+    error: Option<DynamoRepositoryError>,
+}
+
+impl Transaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Puts an item that must not exist yet
+    pub fn create<E, R>(self, repository: &R, item: E) -> Self
+    where
+        E: Entity,
+        E::PrimaryKey: Serialize,
+        E::IndexFields: Serialize,
+        R: DynamoRepository<E>,
+    {
+        self.put_item(
+            repository,
+            item,
+            Some(create_condition::<E>()),
+            PutMode::Create,
+        )
+    }
+
+    pub fn put<E, R>(self, repository: &R, item: E) -> Self
+    where
+        E: Entity,
+        E::PrimaryKey: Serialize,
+        E::IndexFields: Serialize,
+        R: DynamoRepository<E>,
+    {
+        self.put_item(repository, item, None, PutMode::Replace)
+    }
+
+    pub fn put_with_condition<E, R>(self, repository: &R, item: E, condition: Expression) -> Self
+    where
+        E: Entity,
+        E::PrimaryKey: Serialize,
+        E::IndexFields: Serialize,
+        R: DynamoRepository<E>,
+    {
+        self.put_item(repository, item, Some(condition), PutMode::Replace)
+    }
+
+    pub fn update<E, R, Index>(self, repository: &R, index: Index, update: Update) -> Self
+    where
+        E: Entity,
+        E::PrimaryKey: Serialize,
+        E::IndexFields: Serialize,
+        R: DynamoRepository<E>,
+        Index: RepositoryIndex,
+    {
+        self.update_item(repository, index, update, None)
+    }
+
+    pub fn update_with_condition<E, R, Index>(
+        self,
+        repository: &R,
+        index: Index,
+        update: Update,
+        condition: Expression,
+    ) -> Self
+    where
+        E: Entity,
+        E::PrimaryKey: Serialize,
+        E::IndexFields: Serialize,
+        R: DynamoRepository<E>,
+        Index: RepositoryIndex,
+    {
+        self.update_item(repository, index, update, Some(condition))
+    }
+
+    pub fn delete<E, R>(self, repository: &R, item: E) -> Self
+    where
+        E: Entity,
+        E::PrimaryKey: Serialize,
+        E::IndexFields: Serialize,
+        R: DynamoRepository<E>,
+    {
+        self.delete_item(repository, item, None)
+    }
+
+    pub fn delete_with_condition<E, R>(self, repository: &R, item: E, condition: Expression) -> Self
+    where
+        E: Entity,
+        E::PrimaryKey: Serialize,
+        E::IndexFields: Serialize,
+        R: DynamoRepository<E>,
+    {
+        self.delete_item(repository, item, Some(condition))
+    }
+
+    /// Fails the transaction unless the item at `index` matches `condition`, without writing it
+    pub fn condition_check<E, R, Index>(
+        self,
+        repository: &R,
+        index: Index,
+        condition: Expression,
+    ) -> Self
+    where
+        E: Entity,
+        E::PrimaryKey: Serialize,
+        E::IndexFields: Serialize,
+        R: DynamoRepository<E>,
+        Index: RepositoryIndex,
+    {
+        let write = index
+            .to_key()
+            .map(|key| TransactionWrite::ConditionCheck { key, condition });
+
+        self.push(
+            repository,
+            format!("ConditionCheck on {}", repository.get_table_name()),
+            write,
+        )
+    }
+
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    /// Commits all operations at once. When DynamoDB cancels the transaction, the error lists
+    /// which operations failed and why. Retries share a request token, so a transaction whose
+    /// response got lost isn't applied twice.
+    pub async fn commit(self) -> Result<(), DynamoRepositoryError> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+
+        let client = match self.targets.first() {
+            None => return Ok(()),
+            Some((_, TransactionTarget::InMemory(_))) => {
+                return InMemoryTable::commit(&self.targets, self.operations);
+            }
+            Some((_, TransactionTarget::Client(client))) => client.clone(),
+        };
+
+        let tables = self
+            .targets
+            .iter()
+            .map(|(table_name, _)| *table_name)
+            .collect::<Vec<_>>()
+            .join(",");
+        let descriptions: Vec<String> = self
+            .operations
+            .iter()
+            .map(|operation| operation.description.clone())
+            .collect();
+        let items = self
+            .operations
+            .into_iter()
+            .map(|operation| operation.write.build(operation.table_name))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let request = client
+            .transact_write_items()
+            .set_transact_items(Some(items))
+            .client_request_token(request_token())
+            .return_consumed_capacity(ReturnConsumedCapacity::Total);
+
+        Operation::new("TransactWriteItems", tables)
+            .with_items(descriptions.len())
+            .run(&self.retry_policy.unwrap_or_default(), || {
+                request
+                    .clone()
+                    .send()
+                    .map_err(|err| commit_error(err, &descriptions))
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    fn put_item<E, R>(
+        self,
+        repository: &R,
+        mut item: E,
+        condition: Option<Expression>,
+        mode: PutMode,
+    ) -> Self
+    where
+        E: Entity,
+        E::PrimaryKey: Serialize,
+        E::IndexFields: Serialize,
+        R: DynamoRepository<E>,
+    {
+        let (condition, _) = prepare_put(&mut item, condition, mode);
+
+        let write = item.serialize_primary_key().and_then(|key| {
+            Ok(TransactionWrite::Put {
+                key,
+                item: item.serialize_with_indexes()?,
+                condition,
+            })
+        });
+
+        self.push(
+            repository,
+            format!("Put into {}", repository.get_table_name()),
+            write,
+        )
+    }
+
+    fn update_item<E, R, Index>(
+        self,
+        repository: &R,
+        index: Index,
+        update: Update,
+        condition: Option<Expression>,
+    ) -> Self
+    where
+        E: Entity,
+        E::PrimaryKey: Serialize,
+        E::IndexFields: Serialize,
+        R: DynamoRepository<E>,
+        Index: RepositoryIndex,
+    {
+        let write = prepare_update::<E>(update, condition).and_then(|(update, condition, _)| {
+            index.to_key().map(|key| TransactionWrite::Update {
+                key,
+                update,
+                condition,
+            })
+        });
+
+        self.push(
+            repository,
+            format!("Update in {}", repository.get_table_name()),
+            write,
+        )
+    }
+
+    fn delete_item<E, R>(self, repository: &R, item: E, condition: Option<Expression>) -> Self
+    where
+        E: Entity,
+        E::PrimaryKey: Serialize,
+        E::IndexFields: Serialize,
+        R: DynamoRepository<E>,
+    {
+        let (condition, _) = prepare_delete(&item, condition);
+
+        let write = item
+            .serialize_primary_key()
+            .map(|key| TransactionWrite::Delete { key, condition });
+
+        self.push(
+            repository,
+            format!("Delete from {}", repository.get_table_name()),
+            write,
+        )
+    }
+
+    fn push<E, R>(
+        mut self,
+        repository: &R,
+        description: String,
+        write: Result<TransactionWrite, DynamoRepositoryError>,
+    ) -> Self
+    where
+        E: Entity,
+        E::PrimaryKey: Serialize,
+        E::IndexFields: Serialize,
+        R: DynamoRepository<E>,
+    {
+        let write = match write {
+            Ok(write) => write,
+            Err(err) => return self.fail(err),
+        };
+
+        if self.operations.len() >= TRANSACTION_LIMIT {
+            return self.fail(DynamoRepositoryError::TransactionTooLarge(
+                TRANSACTION_LIMIT,
+            ));
+        }
+
+        self.use_repository(repository);
+        self.operations.push(TransactionOperation {
+            table_name: repository.get_table_name(),
+            description,
+            write,
+        });
+        self
+    }
+
+    /// The first repository of the transaction provides the retry policy, and every table where
+    /// the transaction is committed
+    fn use_repository<E, R>(&mut self, repository: &R)
+    where
+        E: Entity,
+        E::PrimaryKey: Serialize,
+        E::IndexFields: Serialize,
+        R: DynamoRepository<E>,
+    {
+        let table_name = repository.get_table_name();
+
+        if self.targets.iter().any(|(table, _)| *table == table_name) {
+            return;
+        }
+
+        let target = repository.get_transaction_target();
+        let in_memory =
+            |target: &TransactionTarget| matches!(target, TransactionTarget::InMemory(_));
+
+        // In-memory items and DynamoDB tables can't be written atomically together
+        if self
+            .targets
+            .first()
+            .is_some_and(|(_, first)| in_memory(first) != in_memory(&target))
+            && self.error.is_none()
+        {
+            self.error = Some(DynamoRepositoryError::MixedTransaction);
+        }
+
+        if self.retry_policy.is_none() {
+            self.retry_policy = Some(repository.get_retry_policy());
+        }
+
+        self.targets.push((table_name, target));
+    }
+
+    /// Keeps the first error, which `commit` returns without sending anything
+    fn fail(mut self, err: DynamoRepositoryError) -> Self {
+        if self.error.is_none() {
+            self.error = Some(err);
+        }
+
+        self
+    }
+}
+
+/// Lists the failed operations of a canceled transaction
+fn commit_error(
+    err: SdkError<TransactWriteItemsError>,
+    operations: &[String],
+) -> DynamoRepositoryError {
+    match err.as_service_error() {
+        Some(TransactWriteItemsError::TransactionCanceledException(exception)) => {
+            DynamoRepositoryError::TransactionCanceled(cancellation_failures(exception, operations))
+        }
+        _ => err.into(),
+    }
+}
+
+/// The operations DynamoDB gave a cancellation reason for, leaving out the ones with code `None`
+fn cancellation_failures(
+    exception: &TransactionCanceledException,
+    operations: &[String],
+) -> Vec<TransactionFailure> {
+    exception
+        .cancellation_reasons
+        .iter()
+        .flatten()
+        .enumerate()
+        .filter_map(|(index, reason)| {
+            let code = reason.code.clone().filter(|code| code != "None")?;
+
+            Some(TransactionFailure {
+                index,
+                operation: operations.get(index).cloned().unwrap_or_default(),
+                code,
+                message: reason.message.clone(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use aws_sdk_dynamodb::types::error::TransactionCanceledException;
+    use aws_sdk_dynamodb::types::CancellationReason;
+    use futures::executor::block_on;
+    use serde::{Deserialize, Serialize};
+
+    use super::cancellation_failures;
+    use crate::prelude::{
+        attribute, DynamoRepository, DynamoRepositoryError, Entity, InMemoryRepository,
+        Transaction, Update, TRANSACTION_LIMIT,
+    };
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Entity)]
+    #[entity(pk = "ACCOUNT#{id}", sk = "ACCOUNT")]
+    struct AccountEntity {
+        id: u32,
+        balance: i64,
+        #[entity(version)]
+        #[serde(default)]
+        version: u64,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Entity)]
+    #[entity(pk = "TRANSFER#{id}", sk = "TRANSFER")]
+    struct TransferEntity {
+        id: u32,
+        from: u32,
+        to: u32,
+        amount: i64,
+    }
+
+    fn account(id: u32, balance: i64) -> AccountEntity {
+        AccountEntity {
+            id,
+            balance,
+            version: 0,
+        }
+    }
+
+    fn repositories() -> (
+        InMemoryRepository<AccountEntity>,
+        InMemoryRepository<TransferEntity>,
+    ) {
+        let accounts = InMemoryRepository::new("accounts");
+        let transfers = InMemoryRepository::new("transfers");
+
+        block_on(accounts.create(account(1, 100))).unwrap();
+        block_on(accounts.create(account(2, 0))).unwrap();
+
+        (accounts, transfers)
+    }
+
+    fn balances(accounts: &InMemoryRepository<AccountEntity>) -> Vec<(i64, u64)> {
+        [1, 2]
+            .map(|id| {
+                let account = block_on(accounts.get(AccountPrimaryKey::find_by_id(id))).unwrap();
+                (account.balance, account.version)
+            })
+            .to_vec()
+    }
+
+    #[test]
+    fn it_commits_every_operation() {
+        let (accounts, transfers) = repositories();
+        let transfer = TransferEntity {
+            id: 1,
+            from: 1,
+            to: 2,
+            amount: 30,
+        };
+
+        block_on(
+            Transaction::new()
+                .update(
+                    &accounts,
+                    AccountPrimaryKey::find_by_id(1),
+                    Update::new().add("balance", -30).with_expected_version(1),
+                )
+                .update(
+                    &accounts,
+                    AccountPrimaryKey::find_by_id(2),
+                    Update::new().add("balance", 30).with_expected_version(1),
+                )
+                .create(&transfers, transfer.clone())
+                .commit(),
+        )
+        .unwrap();
+
+        assert_eq!(balances(&accounts), vec![(70, 2), (30, 2)]);
+        assert_eq!(
+            block_on(transfers.get(TransferPrimaryKey::find_by_id(1))).unwrap(),
+            transfer
+        );
+    }
+
+    #[test]
+    fn it_cancels_the_transaction_when_a_condition_fails() {
+        let (accounts, transfers) = repositories();
+
+        let result = block_on(
+            Transaction::new()
+                .update(
+                    &accounts,
+                    AccountPrimaryKey::find_by_id(1),
+                    Update::new().add("balance", -30).with_expected_version(1),
+                )
+                .create(&accounts, account(2, 0))
+                .condition_check(
+                    &accounts,
+                    AccountPrimaryKey::find_by_id(1),
+                    attribute("balance").gt(1000),
+                )
+                .put(
+                    &transfers,
+                    TransferEntity {
+                        id: 1,
+                        from: 1,
+                        to: 2,
+                        amount: 30,
+                    },
+                )
+                .commit(),
+        );
+
+        let Err(DynamoRepositoryError::TransactionCanceled(failures)) = result else {
+            panic!("expected a canceled transaction, got {:?}", result);
+        };
+
+        assert_eq!(
+            failures
+                .iter()
+                .map(|failure| (failure.index, failure.operation.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(1, "Put into accounts"), (2, "ConditionCheck on accounts")]
+        );
+        assert_eq!(balances(&accounts), vec![(100, 1), (0, 1)]);
+        assert!(transfers.is_empty());
+    }
+
+    #[test]
+    fn it_rejects_more_operations_than_the_limit() {
+        let accounts = InMemoryRepository::new("accounts");
+
+        let transaction = (0..=TRANSACTION_LIMIT as u32)
+            .fold(Transaction::new(), |transaction, id| {
+                transaction.put(&accounts, account(id, 0))
+            });
+
+        assert_eq!(transaction.len(), TRANSACTION_LIMIT);
+        assert!(matches!(
+            block_on(transaction.commit()),
+            Err(DynamoRepositoryError::TransactionTooLarge(
+                TRANSACTION_LIMIT
+            ))
+        ));
+        assert!(accounts.is_empty());
+    }
+
+    #[test]
+    fn it_rejects_mixing_in_memory_and_dynamodb_repositories() {
+        let accounts = InMemoryRepository::new("accounts");
+        let client = accounts.get_client().clone();
+        let transfers = TransferRepository { client };
+
+        let result = block_on(
+            Transaction::new()
+                .put(&accounts, account(1, 0))
+                .delete(
+                    &transfers,
+                    TransferEntity {
+                        id: 1,
+                        from: 1,
+                        to: 2,
+                        amount: 30,
+                    },
+                )
+                .commit(),
+        );
+
+        assert!(matches!(
+            result,
+            Err(DynamoRepositoryError::MixedTransaction)
+        ));
+        assert!(accounts.is_empty());
+    }
+
+    #[test]
+    fn it_maps_cancellation_reasons_to_operations() {
+        let exception = TransactionCanceledException::builder()
+            .cancellation_reasons(CancellationReason::builder().code("None").build())
+            .cancellation_reasons(
+                CancellationReason::builder()
+                    .code("ConditionalCheckFailed")
+                    .message("The conditional request failed")
+                    .build(),
+            )
+            .cancellation_reasons(CancellationReason::builder().code("None").build())
+            .build();
+        let operations = [
+            "Update in accounts".to_string(),
+            "Put into transfers".to_string(),
+            "Delete from accounts".to_string(),
+        ];
+
+        let failures = cancellation_failures(&exception, &operations);
+
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].index, 1);
+        assert_eq!(failures[0].operation, "Put into transfers");
+        assert_eq!(failures[0].code, "ConditionalCheckFailed");
+        assert_eq!(
+            failures[0].message.as_deref(),
+            Some("The conditional request failed")
+        );
+    }
+
+    struct TransferRepository {
+        client: aws_sdk_dynamodb::Client,
+    }
+
+    #[async_trait::async_trait]
+    impl DynamoRepository<TransferEntity> for TransferRepository {
+        fn get_table_name(&self) -> &'static str {
+            "transfers"
+        }
+
+        fn get_client(&self) -> &'_ aws_sdk_dynamodb::Client {
+            &self.client
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use aws_sdk_dynamodb::types::AttributeValue;
+
+use crate::repository::entity::{AttributeMap, Entity};
+use crate::repository::expression::{attribute, Expression};
+
+/// Serializes a `DateTime<Utc>` as the epoch seconds DynamoDB expects of a time to live
+/// attribute:
+///
+/// ```
+/// use chrono::{DateTime, Utc};
+/// use orm::prelude::Entity;
+/// # use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize, Entity)]
+/// #[entity(pk = "SHARE_LINK#{id}", sk = "SHARE_LINK")]
+/// struct ShareLinkEntity {
+///     id: String,
+///     #[entity(ttl)]
+///     #[serde(with = "orm::prelude::epoch_seconds")]
+///     expires_at: DateTime<Utc>,
+/// }
+///
+/// assert_eq!(ShareLinkEntity::get_ttl_attribute(), Some("expires_at"));
+/// ```
+pub use chrono::serde::ts_seconds as epoch_seconds;
+
+/// Serializes an `Option<DateTime<Utc>>` as epoch seconds, for items that only expire once the
+/// time is set, like trashed notes
+pub use chrono::serde::ts_seconds_option as epoch_seconds_option;
+
+/// Whether the time to live of the item has passed. DynamoDB deletes expired items in the
+/// background, typically within a few days, so reads have to leave them out until then.
+pub(crate) fn is_expired(item: &AttributeMap, ttl_attribute: &str) -> bool {
+    match item.get(ttl_attribute) {
+        Some(AttributeValue::N(expires_at)) => expires_at
+            .parse::<f64>()
+            .is_ok_and(|expires_at| expires_at <= now() as f64),
+        _ => false,
+    }
+}
+
+/// Condition of `create`, which also replaces an item that expired but wasn't deleted yet
+pub(crate) fn create_condition<E: Entity>() -> Expression {
+    let condition = attribute("pk").not_exists();
+
+    match E::get_ttl_attribute() {
+        Some(ttl_attribute) => condition.or(attribute(ttl_attribute).le(now())),
+        None => condition,
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as i64)
+}
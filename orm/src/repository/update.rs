@@ -0,0 +1,229 @@
+use aws_sdk_dynamodb::types::AttributeValue;
+use serde::Serialize;
+use serde_dynamo::to_attribute_value;
+
+use crate::repository::attributes::ExpressionAttributes;
+use crate::repository::repository::DynamoRepositoryError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UpdateAction {
+    Set(String, AttributeValue),
+    Remove(String),
+    /// Adds to a number, or adds elements to a set
+    Add(String, AttributeValue),
+    /// Appends a list to a list attribute, creating it when missing
+    Append(String, AttributeValue),
+    /// Increments a number attribute, starting from zero when missing
+    Increment(String, AttributeValue),
+}
+
+/// The changes of an `UpdateItem` call:
+///
+/// ```
+/// use orm::prelude::Update;
+///
+/// let update = Update::new()
+///     .set("title", "New title")
+///     .remove("archived_at")
+///     .append("tags", ["rust"])
+///     .increment("views", 1);
+/// ```
+///
+/// Fields the primary key or index fields are formatted from should be changed through `upsert`,
+/// as an update doesn't rewrite the key attributes. Updates of versioned entities need the version
+/// the caller read, see [`Update::with_expected_version`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Update {
+    actions: Vec<UpdateAction>,
+    expected_version: Option<u64>,
+    /// The first value that couldn't be serialized, `build` fails with it
+    error: Option<serde_dynamo::Error>,
+}
+
+impl Update {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(self, path: impl Into<String>, value: impl Serialize) -> Self {
+        self.try_action(to_value(value).map(|value| UpdateAction::Set(path.into(), value)))
+    }
+
+    pub fn remove(self, path: impl Into<String>) -> Self {
+        self.action(UpdateAction::Remove(path.into()))
+    }
+
+    pub fn add(self, path: impl Into<String>, value: impl Serialize) -> Self {
+        self.try_action(to_value(value).map(|value| UpdateAction::Add(path.into(), value)))
+    }
+
+    pub fn append<V: Serialize>(
+        self,
+        path: impl Into<String>,
+        values: impl IntoIterator<Item = V>,
+    ) -> Self {
+        let values = values.into_iter().map(to_value).collect::<Result<_, _>>();
+
+        self.try_action(
+            values.map(|values| UpdateAction::Append(path.into(), AttributeValue::L(values))),
+        )
+    }
+
+    /// Increments a number attribute, use a negative amount to decrement it
+    pub fn increment(self, path: impl Into<String>, amount: impl Serialize) -> Self {
+        self.try_action(to_value(amount).map(|amount| UpdateAction::Increment(path.into(), amount)))
+    }
+
+    /// Version the stored item of a versioned entity must have, the update fails with a version
+    /// conflict otherwise
+    pub fn with_expected_version(mut self, version: u64) -> Self {
+        self.expected_version = Some(version);
+        self
+    }
+
+    pub fn action(mut self, action: UpdateAction) -> Self {
+        self.actions.push(action);
+        self
+    }
+
+    fn try_action(mut self, action: Result<UpdateAction, serde_dynamo::Error>) -> Self {
+        match action {
+            Ok(action) => self.action(action),
+            Err(err) => {
+                self.error.get_or_insert(err);
+                self
+            }
+        }
+    }
+
+    pub fn get_actions(&self) -> &[UpdateAction] {
+        &self.actions
+    }
+
+    pub fn get_expected_version(&self) -> Option<u64> {
+        self.expected_version
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    /// Fails with the error of the first value that couldn't be serialized
+    pub(crate) fn validate(&self) -> Result<(), DynamoRepositoryError> {
+        match &self.error {
+            Some(err) => Err(DynamoRepositoryError::SerializationError(err.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// Renders the `UpdateExpression`, registering its names and values as placeholders. Fails
+    /// when one of its values couldn't be serialized.
+    pub fn build(
+        &self,
+        attributes: &mut ExpressionAttributes,
+    ) -> Result<String, DynamoRepositoryError> {
+        self.validate()?;
+
+        let mut set = Vec::new();
+        let mut remove = Vec::new();
+        let mut add = Vec::new();
+
+        for action in &self.actions {
+            match action {
+                UpdateAction::Set(path, value) => {
+                    set.push(format!(
+                        "{} = {}",
+                        attributes.name(path),
+                        attributes.value(value.clone())
+                    ));
+                }
+                UpdateAction::Remove(path) => remove.push(attributes.name(path)),
+                UpdateAction::Add(path, value) => {
+                    add.push(format!(
+                        "{} {}",
+                        attributes.name(path),
+                        attributes.value(value.clone())
+                    ));
+                }
+                UpdateAction::Append(path, values) => {
+                    let name = attributes.name(path);
+
+                    set.push(format!(
+                        "{} = list_append(if_not_exists({}, {}), {})",
+                        name,
+                        name,
+                        attributes.value(AttributeValue::L(Vec::new())),
+                        attributes.value(values.clone())
+                    ));
+                }
+                UpdateAction::Increment(path, amount) => {
+                    let name = attributes.name(path);
+
+                    set.push(format!(
+                        "{} = if_not_exists({}, {}) + {}",
+                        name,
+                        name,
+                        attributes.value(AttributeValue::N("0".to_string())),
+                        attributes.value(amount.clone())
+                    ));
+                }
+            }
+        }
+
+        Ok([("SET", set), ("REMOVE", remove), ("ADD", add)]
+            .into_iter()
+            .filter(|(_, clauses)| !clauses.is_empty())
+            .map(|(keyword, clauses)| format!("{} {}", keyword, clauses.join(", ")))
+            .collect::<Vec<_>>()
+            .join(" "))
+    }
+}
+
+fn to_value(value: impl Serialize) -> Result<AttributeValue, serde_dynamo::Error> {
+    to_attribute_value(value)
+}
+
+#[cfg(test)]
+mod test {
+    use serde::ser::Error;
+    use serde::{Serialize, Serializer};
+
+    use crate::prelude::{DynamoRepositoryError, ExpressionAttributes, Update};
+
+    struct Unserializable;
+
+    impl Serialize for Unserializable {
+        fn serialize<S: Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+            Err(S::Error::custom("not serializable"))
+        }
+    }
+
+    #[test]
+    fn it_groups_actions_by_clause() {
+        let mut attributes = ExpressionAttributes::new();
+
+        let update = Update::new()
+            .set("title", "Hello")
+            .add("tags", 1)
+            .remove("body")
+            .increment("views", 2);
+
+        assert_eq!(
+            update.build(&mut attributes).unwrap(),
+            "SET #n0 = :v0, #n3 = if_not_exists(#n3, :v2) + :v3 REMOVE #n2 ADD #n1 :v1"
+        );
+        assert_eq!(attributes.names().unwrap()["#n3"], "views");
+    }
+
+    #[test]
+    fn it_fails_to_build_values_that_dont_serialize() {
+        let update = Update::new()
+            .set("title", "Hello")
+            .append("tags", [Unserializable]);
+
+        assert!(matches!(
+            update.build(&mut ExpressionAttributes::new()),
+            Err(DynamoRepositoryError::SerializationError(_))
+        ));
+    }
+}
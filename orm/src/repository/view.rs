@@ -0,0 +1,55 @@
+use serde::Deserialize;
+
+use crate::repository::entity::{AttributeMap, Entity, ENTITY_TYPE_ATTRIBUTE};
+use crate::repository::repository::DynamoRepositoryError;
+
+/// A read-only subset of the attributes of an entity. Reading a view sends a
+/// `ProjectionExpression`, so DynamoDB only returns the attributes the view needs. Usually
+/// derived, reading the fields of the view:
+///
+/// ```
+/// use orm::prelude::{Entity, View};
+/// # use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize, Entity)]
+/// #[entity(pk = "NOTE", sk = "NOTE_ID#{id}")]
+/// struct NoteEntity { id: u32, title: String, body: String, encoded: Vec<f32> }
+///
+/// #[derive(Deserialize, View)]
+/// #[view(entity = NoteEntity)]
+/// struct NoteTitle { id: u32, title: String }
+///
+/// assert_eq!(NoteTitle::get_projection(), ["id", "title"]);
+/// ```
+pub trait View: for<'a> Deserialize<'a> + Send + 'static {
+    type Entity: Entity;
+
+    /// Attributes, or document paths, the view reads
+    fn get_projection() -> Vec<&'static str>;
+}
+
+/// Projection of the view, along with the entity type attribute to tell the items of its
+/// entity apart from other entity types in the table, and the time to live attribute to tell
+/// expired items apart
+pub(crate) fn view_projection<V: View>() -> Vec<String> {
+    let mut projection: Vec<String> = V::get_projection().into_iter().map(String::from).collect();
+
+    if V::Entity::get_entity_type().is_some() {
+        projection.push(ENTITY_TYPE_ATTRIBUTE.to_string());
+    }
+
+    if let Some(ttl_attribute) = V::Entity::get_ttl_attribute() {
+        projection.push(ttl_attribute.to_string());
+    }
+
+    projection
+}
+
+/// Deserializes the item, or returns `None` when it belongs to another entity type or expired
+pub(crate) fn read_view<V: View>(item: AttributeMap) -> Result<Option<V>, DynamoRepositoryError> {
+    if !V::Entity::is_entity_type(&item) || V::Entity::is_expired(&item) {
+        return Ok(None);
+    }
+
+    Ok(Some(serde_dynamo::from_item(item)?))
+}
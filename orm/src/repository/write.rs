@@ -0,0 +1,347 @@
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::operation::delete_item::{DeleteItemError, DeleteItemOutput};
+use aws_sdk_dynamodb::operation::put_item::PutItemError;
+use aws_sdk_dynamodb::operation::update_item::UpdateItemError;
+use aws_sdk_dynamodb::types::{
+    AttributeValue, ReturnConsumedCapacity, ReturnValue, ReturnValuesOnConditionCheckFailure,
+};
+use chrono::{DateTime, Utc};
+use futures::TryFutureExt;
+use serde::Serialize;
+
+use crate::repository::attributes::ExpressionAttributes;
+use crate::repository::entity::Entity;
+use crate::repository::expression::{attribute, Expression};
+use crate::repository::instrument::Operation;
+use crate::repository::repository::{DynamoRepositoryError, RepositoryIndex};
+use crate::repository::retry::RetryPolicy;
+use crate::repository::update::Update;
+
+/// Optimistic locking check of a write on a versioned entity
+pub(crate) struct VersionCheck {
+    attribute: &'static str,
+    expected: u64,
+}
+
+impl VersionCheck {
+    fn new<E: Entity>(item: &E) -> Option<Self> {
+        E::get_version_attribute().map(|attribute| Self {
+            attribute,
+            expected: item.get_version(),
+        })
+    }
+
+    /// Items written before they were versioned have no version attribute yet
+    fn condition(&self) -> Expression {
+        match self.expected {
+            0 => attribute(self.attribute).not_exists(),
+            expected => attribute(self.attribute).eq(expected),
+        }
+    }
+
+    fn and(&self, condition: Option<Expression>) -> Expression {
+        match condition {
+            Some(condition) => condition.and(self.condition()),
+            None => self.condition(),
+        }
+    }
+
+    /// The version of the item DynamoDB returned with a failed condition check, if it differs
+    /// from the expected one
+    pub(crate) fn conflict(
+        &self,
+        stored_item: Option<&HashMap<String, AttributeValue>>,
+    ) -> Option<DynamoRepositoryError> {
+        let found = stored_item
+            .and_then(|item| item.get(self.attribute))
+            .and_then(|version| version.as_n().ok())
+            .and_then(|version| version.parse::<u64>().ok());
+
+        match (self.expected, found) {
+            (0, None) => None,
+            (expected, Some(found)) if expected == found => None,
+            (expected, found) => Some(DynamoRepositoryError::VersionConflict { expected, found }),
+        }
+    }
+}
+
+/// Whether a put creates a new item or replaces the stored one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum PutMode {
+    Create,
+    Replace,
+}
+
+impl PutMode {
+    /// The error of a put whose condition failed, given the stored item DynamoDB returned with
+    /// the failure. Creating only fails when the item exists.
+    pub(crate) fn condition_failed(
+        self,
+        stored_item: Option<&HashMap<String, AttributeValue>>,
+        version_check: Option<&VersionCheck>,
+    ) -> DynamoRepositoryError {
+        match self {
+            PutMode::Create => DynamoRepositoryError::ItemAlreadyExists,
+            PutMode::Replace => condition_failed(stored_item, version_check),
+        }
+    }
+}
+
+/// The error of a write whose condition failed, given the stored item DynamoDB returned with the
+/// failure: a version conflict when the versions differ, a failed condition otherwise
+pub(crate) fn condition_failed(
+    stored_item: Option<&HashMap<String, AttributeValue>>,
+    version_check: Option<&VersionCheck>,
+) -> DynamoRepositoryError {
+    version_check
+        .and_then(|version_check| version_check.conflict(stored_item))
+        .unwrap_or_else(|| DynamoRepositoryError::ConditionFailed {
+            item: stored_item.cloned(),
+        })
+}
+
+/// The error of an update whose condition failed, which also fails when there's no stored item
+pub(crate) fn update_condition_failed(
+    stored_item: Option<&HashMap<String, AttributeValue>>,
+    version_check: Option<&VersionCheck>,
+) -> DynamoRepositoryError {
+    match stored_item {
+        Some(_) => condition_failed(stored_item, version_check),
+        None => DynamoRepositoryError::ItemNotFoundError,
+    }
+}
+
+/// Stamps the timestamps of `item`. A created item gets its first version, a replaced versioned
+/// item gets its version bumped and the version check added to `condition`.
+pub(crate) fn prepare_put<E: Entity>(
+    item: &mut E,
+    condition: Option<Expression>,
+    mode: PutMode,
+) -> (Option<Expression>, Option<VersionCheck>) {
+    let now = Utc::now();
+    item.set_updated_at(now);
+
+    if mode == PutMode::Create {
+        item.set_created_at(now);
+
+        if E::get_version_attribute().is_some() {
+            item.set_version(1);
+        }
+
+        return (condition, None);
+    }
+
+    match VersionCheck::new(item) {
+        Some(version_check) => {
+            item.set_version(version_check.expected + 1);
+            (Some(version_check.and(condition)), Some(version_check))
+        }
+        None => (condition, None),
+    }
+}
+
+/// Stamps an item `batch_write` puts, which can't check whether it's stored. Its version is
+/// bumped and its update time refreshed, and a creation time left at its default is stamped too.
+pub(crate) fn prepare_batch_put<E: Entity>(item: &mut E) {
+    let now = Utc::now();
+    item.set_updated_at(now);
+
+    if item.get_created_at() == Some(DateTime::default()) {
+        item.set_created_at(now);
+    }
+
+    if E::get_version_attribute().is_some() {
+        item.set_version(item.get_version() + 1);
+    }
+}
+
+/// Adds the version check of a versioned `item` to `condition`
+pub(crate) fn prepare_delete<E: Entity>(
+    item: &E,
+    condition: Option<Expression>,
+) -> (Option<Expression>, Option<VersionCheck>) {
+    match VersionCheck::new(item) {
+        Some(version_check) => (Some(version_check.and(condition)), Some(version_check)),
+        None => (condition, None),
+    }
+}
+
+/// Refreshes the update time and makes sure the item exists, as `UpdateItem` would otherwise
+/// create a partial item when the key doesn't exist. Versioned entities get their version bumped
+/// and checked against the expected version of the update, which they require.
+pub(crate) fn prepare_update<E: Entity>(
+    update: Update,
+    condition: Option<Expression>,
+) -> Result<(Update, Expression, Option<VersionCheck>), DynamoRepositoryError> {
+    let update = match E::get_updated_at_attribute() {
+        Some(updated_at_attribute) => update.set(updated_at_attribute, Utc::now()),
+        None => update,
+    };
+    let condition = match condition {
+        Some(condition) => attribute("pk").exists().and(condition),
+        None => attribute("pk").exists(),
+    };
+
+    let Some(version_attribute) = E::get_version_attribute() else {
+        return Ok((update, condition, None));
+    };
+    let version_check = VersionCheck {
+        attribute: version_attribute,
+        expected: update
+            .get_expected_version()
+            .ok_or(DynamoRepositoryError::MissingVersion)?,
+    };
+
+    Ok((
+        update.increment(version_attribute, 1),
+        version_check.and(Some(condition)),
+        Some(version_check),
+    ))
+}
+
+pub(crate) async fn put_item<E>(
+    client: &aws_sdk_dynamodb::Client,
+    table_name: &str,
+    retry_policy: &RetryPolicy,
+    mut item: E,
+    condition: Option<Expression>,
+    mode: PutMode,
+) -> Result<E, DynamoRepositoryError>
+where
+    E: Entity,
+    E::PrimaryKey: Serialize,
+    E::IndexFields: Serialize,
+{
+    let (condition, version_check) = prepare_put(&mut item, condition, mode);
+
+    let mut attributes = ExpressionAttributes::new();
+    let condition_expression = condition
+        .map(|condition| condition.build(&mut attributes))
+        .transpose()?;
+
+    let request = client
+        .put_item()
+        .table_name(table_name)
+        .set_item(Some(item.serialize_with_indexes()?))
+        .set_condition_expression(condition_expression)
+        .set_expression_attribute_names(attributes.names())
+        .set_expression_attribute_values(attributes.values())
+        .return_values_on_condition_check_failure(ReturnValuesOnConditionCheckFailure::AllOld)
+        .return_consumed_capacity(ReturnConsumedCapacity::Total);
+
+    Operation::new("PutItem", table_name)
+        .with_items(1)
+        .run(&retry_policy.for_writes(), || {
+            request
+                .clone()
+                .send()
+                .map_err(|err| match err.as_service_error() {
+                    Some(PutItemError::ConditionalCheckFailedException(exception)) => {
+                        mode.condition_failed(exception.item.as_ref(), version_check.as_ref())
+                    }
+                    _ => err.into(),
+                })
+        })
+        .await?;
+
+    Ok(item)
+}
+
+pub(crate) async fn update_item<E, Index>(
+    client: &aws_sdk_dynamodb::Client,
+    table_name: &str,
+    retry_policy: &RetryPolicy,
+    index: Index,
+    update: Update,
+    condition: Option<Expression>,
+) -> Result<E, DynamoRepositoryError>
+where
+    E: Entity,
+    Index: RepositoryIndex,
+{
+    let (update, condition, version_check) = prepare_update::<E>(update, condition)?;
+
+    let mut attributes = ExpressionAttributes::new();
+    let update_expression = update.build(&mut attributes)?;
+    let condition_expression = condition.build(&mut attributes)?;
+
+    let request = client
+        .update_item()
+        .table_name(table_name)
+        .set_key(Some(index.to_key()?))
+        .update_expression(update_expression)
+        .condition_expression(condition_expression)
+        .set_expression_attribute_names(attributes.names())
+        .set_expression_attribute_values(attributes.values())
+        .return_values(ReturnValue::AllNew)
+        .return_values_on_condition_check_failure(ReturnValuesOnConditionCheckFailure::AllOld)
+        .return_consumed_capacity(ReturnConsumedCapacity::Total);
+
+    let output = Operation::new("UpdateItem", table_name)
+        .with_items(1)
+        .run(&retry_policy.for_writes(), || {
+            request
+                .clone()
+                .send()
+                .map_err(|err| match err.as_service_error() {
+                    Some(UpdateItemError::ConditionalCheckFailedException(exception)) => {
+                        update_condition_failed(exception.item.as_ref(), version_check.as_ref())
+                    }
+                    _ => err.into(),
+                })
+        })
+        .await?;
+
+    E::from_attribute_values(
+        output
+            .attributes
+            .ok_or(DynamoRepositoryError::ItemNotFoundError)?,
+    )
+    .map_err(DynamoRepositoryError::from)
+}
+
+pub(crate) async fn delete_item<E>(
+    client: &aws_sdk_dynamodb::Client,
+    table_name: &str,
+    retry_policy: &RetryPolicy,
+    item: E,
+    condition: Option<Expression>,
+) -> Result<DeleteItemOutput, DynamoRepositoryError>
+where
+    E: Entity,
+    E::PrimaryKey: Serialize,
+    E::IndexFields: Serialize,
+{
+    let (condition, version_check) = prepare_delete(&item, condition);
+
+    let mut attributes = ExpressionAttributes::new();
+    let condition_expression = condition
+        .map(|condition| condition.build(&mut attributes))
+        .transpose()?;
+
+    let request = client
+        .delete_item()
+        .table_name(table_name)
+        .set_key(Some(item.serialize_primary_key()?))
+        .set_condition_expression(condition_expression)
+        .set_expression_attribute_names(attributes.names())
+        .set_expression_attribute_values(attributes.values())
+        .return_values_on_condition_check_failure(ReturnValuesOnConditionCheckFailure::AllOld)
+        .return_consumed_capacity(ReturnConsumedCapacity::Total);
+
+    Operation::new("DeleteItem", table_name)
+        .with_items(1)
+        .run(&retry_policy.for_writes(), || {
+            request
+                .clone()
+                .send()
+                .map_err(|err| match err.as_service_error() {
+                    Some(DeleteItemError::ConditionalCheckFailedException(exception)) => {
+                        condition_failed(exception.item.as_ref(), version_check.as_ref())
+                    }
+                    _ => err.into(),
+                })
+        })
+        .await
+}
@@ -0,0 +1,37 @@
+use std::sync::Arc;
+
+use actix_web::http::StatusCode;
+use actix_web::web::{self, Data, Json};
+use actix_web::{HttpRequest, HttpResponse, Resource};
+use juniper::http::graphiql::graphiql_source;
+use juniper::http::GraphQLRequest;
+
+use crate::graphql::CrudSchema;
+
+/// Serves `schema` at `path`: POST executes a GraphQL request, GET opens the GraphiQL playground
+/// on it
+pub fn graphql_resource(path: &str, schema: Arc<CrudSchema>) -> Resource {
+    web::resource(path)
+        .app_data(Data::from(schema))
+        .route(web::post().to(execute))
+        .route(web::get().to(graphiql))
+}
+
+async fn execute(schema: Data<CrudSchema>, request: Json<GraphQLRequest>) -> HttpResponse {
+    let response = request.execute(&schema, &()).await;
+
+    // Failed resolvers still answer 200 with their errors, only invalid requests are rejected
+    let status = if response.is_ok() {
+        StatusCode::OK
+    } else {
+        StatusCode::BAD_REQUEST
+    };
+
+    HttpResponse::build(status).json(response)
+}
+
+async fn graphiql(request: HttpRequest) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(graphiql_source(request.path(), None))
+}
@@ -1,10 +1,99 @@
-use std::fmt::{Display, Formatter};
 use actix_web::http::StatusCode;
 use actix_web::{HttpResponse, ResponseError};
+use serde::Serialize;
 
-use crate::prelude::DynamoRepositoryError;
+use crate::prelude::{CursorError, DynamoRepositoryError, FieldError};
 
-impl ResponseError for DynamoRepositoryError {}
+pub mod graphql;
+#[cfg(feature = "warp")]
+pub mod warp;
+
+/// Problem details body of an error response, as described by RFC 7807:
+///
+/// ```json
+/// {"type": "about:blank", "title": "Not Found", "status": 404, "detail": "Item wasn't found"}
+/// ```
+///
+/// Failed validations list the message of every field in an `errors` extension member.
+#[derive(Debug, Clone, Serialize)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errors: Option<Vec<FieldError>>,
+}
+
+impl Problem {
+    pub fn new(status: StatusCode, detail: impl Into<String>) -> Self {
+        Self {
+            problem_type: "about:blank".to_string(),
+            title: status.canonical_reason().unwrap_or_default().to_string(),
+            status: status.as_u16(),
+            detail: detail.into(),
+            errors: None,
+        }
+    }
+
+    pub fn from_error(err: &DynamoRepositoryError) -> Self {
+        let problem = Self::new(err.status_code(), err.to_string());
+
+        match err {
+            DynamoRepositoryError::ValidationFailed(errors) => Self {
+                errors: Some(errors.get_fields().to_vec()),
+                ..problem
+            },
+            _ => problem,
+        }
+    }
+
+    pub fn to_response(&self) -> HttpResponse {
+        let status =
+            StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+
+        HttpResponse::build(status)
+            .content_type("application/problem+json")
+            .json(self)
+    }
+}
+
+impl ResponseError for DynamoRepositoryError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::ItemNotFoundError => StatusCode::NOT_FOUND,
+            Self::ItemAlreadyExists | Self::ConditionFailed { .. } | Self::VersionConflict { .. } => {
+                StatusCode::CONFLICT
+            }
+            Self::TransactionCanceled(failures)
+                if failures.iter().any(|failure| failure.code == "ThrottlingError") =>
+            {
+                StatusCode::TOO_MANY_REQUESTS
+            }
+            Self::TransactionCanceled(_) => StatusCode::CONFLICT,
+            // Keys the cursor can't carry come from the table, not from the request
+            Self::InvalidCursor(CursorError::UnsupportedKey(_) | CursorError::UnsupportedIndex) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            Self::InvalidCursor(_) => StatusCode::BAD_REQUEST,
+            // The entity or key built from the request holds a value DynamoDB can't store
+            Self::SerializationError(_) => StatusCode::BAD_REQUEST,
+            // Batches give up on unprocessed items once retrying didn't get them through the
+            // throughput limits
+            Self::UnprocessedItemsError(_) => StatusCode::TOO_MANY_REQUESTS,
+            Self::TableNotActive(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::ValidationFailed(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            err if err.is_throttled() => StatusCode::TOO_MANY_REQUESTS,
+            err if err.is_unavailable() => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        Problem::from_error(self).to_response()
+    }
+}
 
 
 #[derive(thiserror::Error, Debug)]
@@ -16,14 +105,74 @@ pub enum ActixAnyhowError {
 impl ResponseError for ActixAnyhowError {
     fn status_code(&self) -> StatusCode {
         match &self {
-            Self::InternalError(_) => StatusCode::INTERNAL_SERVER_ERROR
+            // Repository errors keep their own status, e.g. a version conflict stays a 409
+            Self::InternalError(err) => match err.downcast_ref::<DynamoRepositoryError>() {
+                Some(err) => err.status_code(),
+                None => StatusCode::INTERNAL_SERVER_ERROR,
+            },
         }
     }
 
     fn error_response(&self) -> HttpResponse {
-        HttpResponse::build(self.status_code()).body(self.to_string())
+        let problem = match &self {
+            Self::InternalError(err) => match err.downcast_ref::<DynamoRepositoryError>() {
+                Some(err) => Problem::from_error(err),
+                None => Problem::new(self.status_code(), self.to_string()),
+            },
+        };
+
+        problem.to_response()
     }
 }
 
 // Short hand alias, which allows you to use just Result<T>
-pub type ActixAnyhow<T> = std::result::Result<T, ActixAnyhowError>;
\ No newline at end of file
+pub type ActixAnyhow<T> = std::result::Result<T, ActixAnyhowError>;
+
+#[cfg(test)]
+mod test {
+    use actix_web::body::to_bytes;
+    use actix_web::http::StatusCode;
+    use actix_web::ResponseError;
+
+    use crate::prelude::{CursorError, DynamoRepositoryError, ValidationErrors};
+
+    #[test]
+    fn it_maps_errors_to_problems() {
+        let response = DynamoRepositoryError::ItemNotFoundError.error_response();
+        let content_type = response.headers().get("content-type").cloned();
+        let body = futures::executor::block_on(to_bytes(response.into_body())).unwrap();
+        let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(content_type.unwrap(), "application/problem+json");
+        assert_eq!(problem["status"], 404);
+        assert_eq!(problem["title"], "Not Found");
+        assert_eq!(
+            DynamoRepositoryError::ItemAlreadyExists.status_code(),
+            StatusCode::CONFLICT
+        );
+        assert_eq!(
+            DynamoRepositoryError::InvalidCursor(CursorError::Expired).status_code(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            DynamoRepositoryError::InvalidCursor(CursorError::UnsupportedKey("pk".to_string()))
+                .status_code(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[test]
+    fn it_lists_field_errors() {
+        let mut errors = ValidationErrors::new();
+        errors.add("title", "can't be empty");
+
+        let response = DynamoRepositoryError::ValidationFailed(errors).error_response();
+        let status = response.status();
+        let body = futures::executor::block_on(to_bytes(response.into_body())).unwrap();
+        let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(problem["errors"][0]["field"], "title");
+        assert_eq!(problem["errors"][0]["message"], "can't be empty");
+    }
+}
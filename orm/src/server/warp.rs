@@ -0,0 +1,183 @@
+use std::convert::Infallible;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use ::warp::body::BodyDeserializeError;
+use ::warp::http::header::CONTENT_TYPE;
+use ::warp::http::StatusCode;
+use ::warp::reject::{
+    InvalidHeader, InvalidQuery, LengthRequired, MethodNotAllowed, MissingHeader, PayloadTooLarge,
+    Reject, UnsupportedMediaType,
+};
+use ::warp::reply::{self, Response};
+use ::warp::{Filter, Rejection, Reply};
+use serde::Serialize;
+
+use crate::prelude::{
+    CrudService, DynamoRepository, DynamoRepositoryError, Entity, RepositoryIndex,
+};
+use crate::server::Problem;
+
+/// Lets warp handlers return repository errors with `?`, [`handle_rejection`] turns them into
+/// the same problem as actix does
+impl Reject for DynamoRepositoryError {}
+
+impl Reply for Problem {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let json = reply::with_header(reply::json(&self), CONTENT_TYPE, "application/problem+json");
+
+        reply::with_status(json, status).into_response()
+    }
+}
+
+/// Answers every rejection with a problem, to be passed to `Filter::recover`:
+///
+/// ```ignore
+/// let routes = notes_routes(notes_service).recover(handle_rejection);
+/// ```
+pub async fn handle_rejection(rejection: Rejection) -> Result<Problem, Infallible> {
+    Ok(to_problem(&rejection))
+}
+
+fn to_problem(rejection: &Rejection) -> Problem {
+    if let Some(err) = rejection.find::<DynamoRepositoryError>() {
+        return Problem::from_error(err);
+    }
+
+    known::<MethodNotAllowed>(rejection, StatusCode::METHOD_NOT_ALLOWED)
+        .or_else(|| known::<InvalidQuery>(rejection, StatusCode::BAD_REQUEST))
+        .or_else(|| known::<InvalidHeader>(rejection, StatusCode::BAD_REQUEST))
+        .or_else(|| known::<MissingHeader>(rejection, StatusCode::BAD_REQUEST))
+        .or_else(|| known::<BodyDeserializeError>(rejection, StatusCode::BAD_REQUEST))
+        .or_else(|| known::<LengthRequired>(rejection, StatusCode::LENGTH_REQUIRED))
+        .or_else(|| known::<PayloadTooLarge>(rejection, StatusCode::PAYLOAD_TOO_LARGE))
+        .or_else(|| known::<UnsupportedMediaType>(rejection, StatusCode::UNSUPPORTED_MEDIA_TYPE))
+        .unwrap_or_else(|| {
+            if rejection.is_not_found() {
+                return Problem::new(StatusCode::NOT_FOUND, "No route matches the request");
+            }
+
+            tracing::error!("unhandled rejection: {:?}", rejection);
+
+            Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "an unspecified internal error occurred",
+            )
+        })
+}
+
+fn known<T: Display + 'static>(rejection: &Rejection, status: StatusCode) -> Option<Problem> {
+    rejection
+        .find::<T>()
+        .map(|err| Problem::new(status, err.to_string()))
+}
+
+/// Hands a clone of `service` to the handler, the way `web::Data` does for actix
+pub fn with_service<E, R, S>(service: S) -> impl Filter<Extract = (S,), Error = Infallible> + Clone
+where
+    E: Entity,
+    E::PrimaryKey: Serialize,
+    E::IndexFields: Serialize,
+    R: DynamoRepository<E>,
+    S: CrudService<E, R> + Clone + Send + Sync + 'static,
+{
+    ::warp::any().map(move || service.clone())
+}
+
+/// Loads the entity whose primary key `key` builds from the next path segment, rejecting with a
+/// 404 problem when it doesn't exist:
+///
+/// ```ignore
+/// let note = warp::path("notes")
+///     .and(entity_param(notes_service, NotePrimaryKey::find_by_id))
+///     .map(|note: NoteEntity| warp::reply::json(&note));
+/// ```
+pub fn entity_param<E, R, S, T, K>(
+    service: S,
+    key: K,
+) -> impl Filter<Extract = (E,), Error = Rejection> + Clone
+where
+    E: Entity,
+    E::PrimaryKey: RepositoryIndex,
+    E::IndexFields: Serialize,
+    R: DynamoRepository<E>,
+    S: CrudService<E, R> + Clone + Send + Sync + 'static,
+    T: FromStr + Send + 'static,
+    K: Fn(T) -> E::PrimaryKey + Clone + Send + Sync + 'static,
+{
+    ::warp::path::param::<T>()
+        .and(with_service(service))
+        .and_then(move |param: T, service: S| {
+            let key = key(param);
+
+            async move {
+                service
+                    .find(key)
+                    .await?
+                    .ok_or_else(|| ::warp::reject::custom(DynamoRepositoryError::ItemNotFoundError))
+            }
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use ::warp::http::StatusCode;
+    use ::warp::Filter;
+    use futures::executor::block_on;
+    use serde::{Deserialize, Serialize};
+
+    use crate::prelude::{CrudService, Entity, InMemoryRepository};
+    use crate::server::warp::{entity_param, handle_rejection};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Entity)]
+    #[entity(pk = "BOOK", sk = "BOOK#{isbn}")]
+    struct BookEntity {
+        isbn: String,
+        title: String,
+    }
+
+    #[derive(Clone)]
+    struct BookService {
+        repository: InMemoryRepository<BookEntity>,
+    }
+
+    impl CrudService<BookEntity, InMemoryRepository<BookEntity>> for BookService {
+        fn get_repository(&self) -> &InMemoryRepository<BookEntity> {
+            &self.repository
+        }
+    }
+
+    #[test]
+    fn it_answers_with_problems() {
+        let service = BookService {
+            repository: InMemoryRepository::new("books"),
+        };
+        let book = BookEntity {
+            isbn: "1".to_string(),
+            title: "Dune".to_string(),
+        };
+
+        block_on(service.create(book.clone())).unwrap();
+
+        let routes = ::warp::path("books")
+            .and(entity_param(service, BookPrimaryKey::find_by_isbn))
+            .map(|book: BookEntity| ::warp::reply::json(&book))
+            .recover(handle_rejection);
+
+        let request = |path: &str| block_on(::warp::test::request().path(path).reply(&routes));
+        let found = request("/books/1");
+        let missing = request("/books/2");
+        let problem: serde_json::Value = serde_json::from_slice(missing.body()).unwrap();
+
+        assert_eq!(found.status(), StatusCode::OK);
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            missing.headers()["content-type"],
+            "application/problem+json"
+        );
+        assert_eq!(problem["status"], 404);
+        assert_eq!(problem["title"], "Not Found");
+        assert_eq!(request("/authors/1").status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,339 @@
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use crate::repository::entity::{to_attribute_map, Entity};
+use crate::repository::repository::DynamoRepositoryError;
+
+/// Message of a single field that failed validation
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// Field errors collected by the `validate` hooks of an entity
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct ValidationErrors(Vec<FieldError>);
+
+impl ValidationErrors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.0.push(FieldError {
+            field: field.into(),
+            message: message.into(),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn get_fields(&self) -> &[FieldError] {
+        &self.0
+    }
+}
+
+impl Display for ValidationErrors {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let fields = self
+            .0
+            .iter()
+            .map(|error| format!("{} {}", error.field, error.message))
+            .collect::<Vec<_>>();
+
+        write!(f, "{}", fields.join(", "))
+    }
+}
+
+/// Runs around the persistence of an entity by a [`CrudService`](crate::service::CrudService).
+/// Every hook defaults to doing nothing, so an implementation only overrides the ones it needs:
+///
+/// ```ignore
+/// struct NoteValidation;
+///
+/// #[async_trait::async_trait]
+/// impl EntityHook<NoteEntity> for NoteValidation {
+///     async fn validate(&self, note: &NoteEntity, errors: &mut ValidationErrors) {
+///         if note.title.trim().is_empty() {
+///             errors.add("title", "can't be empty");
+///         }
+///     }
+///
+///     async fn before_save(&self, mut note: NoteEntity) -> Result<NoteEntity, DynamoRepositoryError> {
+///         note.title = note.title.trim().to_string();
+///         Ok(note)
+///     }
+/// }
+/// ```
+#[async_trait::async_trait]
+pub trait EntityHook<E: Entity>: Send + Sync {
+    /// Adds the problems of `entity` to `errors`, which rejects the write when any hook added one
+    async fn validate(&self, _entity: &E, _errors: &mut ValidationErrors) {}
+
+    /// Runs before the first write of an entity, after validation
+    async fn before_create(&self, entity: E) -> Result<E, DynamoRepositoryError> {
+        Ok(entity)
+    }
+
+    /// Runs before every put of an entity, after validation
+    async fn before_save(&self, entity: E) -> Result<E, DynamoRepositoryError> {
+        Ok(entity)
+    }
+
+    /// Runs after an entity was written, with the entity as stored
+    async fn after_save(&self, _entity: &E) -> Result<(), DynamoRepositoryError> {
+        Ok(())
+    }
+
+    async fn after_delete(&self, _entity: &E) -> Result<(), DynamoRepositoryError> {
+        Ok(())
+    }
+
+    /// Runs on every entity read, before it's returned
+    async fn after_load(&self, entity: E) -> Result<E, DynamoRepositoryError> {
+        Ok(entity)
+    }
+}
+
+/// The hooks registered for an entity, run in the order they were registered. Clones share the
+/// hooks.
+pub struct Hooks<E: Entity> {
+    hooks: Vec<Arc<dyn EntityHook<E>>>,
+}
+
+impl<E: Entity> Clone for Hooks<E> {
+    fn clone(&self) -> Self {
+        Self {
+            hooks: self.hooks.clone(),
+        }
+    }
+}
+
+impl<E: Entity> Default for Hooks<E> {
+    fn default() -> Self {
+        Self { hooks: Vec::new() }
+    }
+}
+
+impl<E: Entity> Hooks<E> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(mut self, hook: impl EntityHook<E> + 'static) -> Self {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// Fails with `ValidationFailed`, listing the errors of every hook, when any hook rejected
+    /// the entity
+    pub async fn validate(&self, entity: &E) -> Result<(), DynamoRepositoryError> {
+        let mut errors = ValidationErrors::new();
+
+        for hook in &self.hooks {
+            hook.validate(entity, &mut errors).await;
+        }
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(DynamoRepositoryError::ValidationFailed(errors)),
+        }
+    }
+
+    pub async fn before_create(&self, mut entity: E) -> Result<E, DynamoRepositoryError> {
+        for hook in &self.hooks {
+            entity = hook.before_create(entity).await?;
+        }
+
+        Ok(entity)
+    }
+
+    pub async fn before_save(&self, mut entity: E) -> Result<E, DynamoRepositoryError> {
+        for hook in &self.hooks {
+            entity = hook.before_save(entity).await?;
+        }
+
+        Ok(entity)
+    }
+
+    pub async fn after_save(&self, entity: &E) -> Result<(), DynamoRepositoryError> {
+        for hook in &self.hooks {
+            hook.after_save(entity).await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn after_delete(&self, entity: &E) -> Result<(), DynamoRepositoryError> {
+        for hook in &self.hooks {
+            hook.after_delete(entity).await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn after_load(&self, mut entity: E) -> Result<E, DynamoRepositoryError> {
+        for hook in &self.hooks {
+            entity = hook.after_load(entity).await?;
+        }
+
+        Ok(entity)
+    }
+
+    pub async fn after_load_all(&self, entities: Vec<E>) -> Result<Vec<E>, DynamoRepositoryError> {
+        if self.is_empty() {
+            return Ok(entities);
+        }
+
+        let mut loaded = Vec::with_capacity(entities.len());
+
+        for entity in entities {
+            loaded.push(self.after_load(entity).await?);
+        }
+
+        Ok(loaded)
+    }
+
+    /// Copy of an entity the repository takes ownership of, for the hooks that run after the
+    /// write. Entities don't have to be `Clone`, so it round trips through its attributes.
+    pub(crate) fn copy(&self, entity: &E) -> Result<Option<E>, DynamoRepositoryError> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(E::from_attribute_values(to_attribute_map(entity)?)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use futures::executor::block_on;
+    use serde::{Deserialize, Serialize};
+
+    use crate::prelude::{
+        CrudService, DynamoRepositoryError, Entity, EntityHook, Hooks, InMemoryRepository,
+        ValidationErrors,
+    };
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Entity)]
+    #[entity(pk = "TAG#{name}", sk = "TAG")]
+    struct TagEntity {
+        name: String,
+        label: String,
+    }
+
+    struct TagService {
+        repository: InMemoryRepository<TagEntity>,
+        hooks: Hooks<TagEntity>,
+    }
+
+    impl CrudService<TagEntity, InMemoryRepository<TagEntity>> for TagService {
+        fn get_repository(&self) -> &InMemoryRepository<TagEntity> {
+            &self.repository
+        }
+
+        fn get_hooks(&self) -> Hooks<TagEntity> {
+            self.hooks.clone()
+        }
+    }
+
+    struct Normalize;
+
+    #[async_trait::async_trait]
+    impl EntityHook<TagEntity> for Normalize {
+        async fn validate(&self, tag: &TagEntity, errors: &mut ValidationErrors) {
+            if tag.label.trim().is_empty() {
+                errors.add("label", "can't be empty");
+            }
+        }
+
+        async fn before_save(&self, tag: TagEntity) -> Result<TagEntity, DynamoRepositoryError> {
+            Ok(TagEntity {
+                label: tag.label.trim().to_string(),
+                ..tag
+            })
+        }
+    }
+
+    #[derive(Default)]
+    struct Count {
+        saved: AtomicUsize,
+        deleted: AtomicUsize,
+        loaded: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl EntityHook<TagEntity> for Arc<Count> {
+        async fn after_save(&self, _tag: &TagEntity) -> Result<(), DynamoRepositoryError> {
+            self.saved.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        async fn after_delete(&self, _tag: &TagEntity) -> Result<(), DynamoRepositoryError> {
+            self.deleted.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        async fn after_load(&self, tag: TagEntity) -> Result<TagEntity, DynamoRepositoryError> {
+            self.loaded.fetch_add(1, Ordering::SeqCst);
+            Ok(tag)
+        }
+    }
+
+    fn tag(name: &str, label: &str) -> TagEntity {
+        TagEntity {
+            name: name.to_string(),
+            label: label.to_string(),
+        }
+    }
+
+    #[test]
+    fn it_runs_hooks_around_persistence() {
+        let count = Arc::new(Count::default());
+        let service = TagService {
+            repository: InMemoryRepository::new("tags"),
+            hooks: Hooks::new().register(Normalize).register(count.clone()),
+        };
+
+        let created = block_on(service.create(tag("rust", "  Rust "))).unwrap();
+        let found = block_on(service.get(TagPrimaryKey::find_by_name("rust".to_string()))).unwrap();
+        block_on(service.delete(found)).unwrap();
+
+        assert_eq!(created.label, "Rust");
+        assert_eq!(count.saved.load(Ordering::SeqCst), 1);
+        assert_eq!(count.loaded.load(Ordering::SeqCst), 1);
+        assert_eq!(count.deleted.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn it_rejects_invalid_entities() {
+        let service = TagService {
+            repository: InMemoryRepository::new("tags"),
+            hooks: Hooks::new().register(Normalize),
+        };
+
+        let Err(DynamoRepositoryError::ValidationFailed(errors)) =
+            block_on(service.upsert(tag("empty", " ")))
+        else {
+            panic!("expected the validation to fail");
+        };
+
+        assert_eq!(errors.get_fields()[0].field, "label");
+        assert!(block_on(service.find(TagPrimaryKey::find_by_name("empty".to_string())))
+            .unwrap()
+            .is_none());
+    }
+}
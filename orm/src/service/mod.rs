@@ -1,11 +1,30 @@
 use aws_sdk_dynamodb::operation::delete_item::DeleteItemOutput;
-use aws_sdk_dynamodb::operation::put_item::PutItemOutput;
 use serde::Serialize;
 
-use crate::prelude::{DynamoRepositoryError, QueryData, QueryResult};
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+
+use crate::prelude::{
+    BatchWriteOperation, CursorCodec, DynamoRepositoryError, EntityEnum, Expression, Page,
+    QueryData, QueryResult, QueryStream, ScanData, Update, View,
+};
 use crate::repository::entity::Entity;
 use crate::repository::repository::{DynamoRepository, RepositoryIndex};
+use crate::service::hooks::Hooks;
+
+pub mod hooks;
 
+/// Persists an entity through its repository, running the [`Hooks`] of the entity around every
+/// write and read:
+///
+/// - `create` validates, then runs `before_create`, `before_save`, the write and `after_save`
+/// - `upsert` validates, then runs `before_save`, the write and `after_save`
+/// - `update` only runs `after_save`, as there's no entity to validate before the write
+/// - `delete` runs `after_delete` once the item is gone
+/// - every read of entities runs `after_load`, views and collections aren't entities
+///
+/// Batch writes validate and run `before_save` on their puts, but don't run the hooks after
+/// the write.
 #[async_trait::async_trait]
 pub trait CrudService<E, R>
     where
@@ -15,54 +34,288 @@ pub trait CrudService<E, R>
         R: DynamoRepository<E>,
 {
     fn get_repository(&self) -> &R;
-    async fn create(&self, entity: E) -> Result<PutItemOutput, DynamoRepositoryError> {
-        self.get_repository().create(entity).await
+
+    /// Hooks run around persisting the entity, override it to register them
+    fn get_hooks(&self) -> Hooks<E> {
+        Hooks::default()
     }
-    async fn upsert(&self, entity: E) -> Result<PutItemOutput, DynamoRepositoryError> {
-        self.get_repository().upsert(entity).await
+
+    async fn create(&self, entity: E) -> Result<E, DynamoRepositoryError> {
+        let hooks = self.get_hooks();
+
+        hooks.validate(&entity).await?;
+        let entity = hooks.before_create(entity).await?;
+        let entity = hooks.before_save(entity).await?;
+        let entity = self.get_repository().create(entity).await?;
+        hooks.after_save(&entity).await?;
+
+        Ok(entity)
+    }
+    async fn upsert(&self, entity: E) -> Result<E, DynamoRepositoryError> {
+        let hooks = self.get_hooks();
+
+        hooks.validate(&entity).await?;
+        let entity = hooks.before_save(entity).await?;
+        let entity = self.get_repository().upsert(entity).await?;
+        hooks.after_save(&entity).await?;
+
+        Ok(entity)
+    }
+    async fn upsert_with_condition(
+        &self,
+        entity: E,
+        condition: Expression,
+    ) -> Result<E, DynamoRepositoryError> {
+        let hooks = self.get_hooks();
+
+        hooks.validate(&entity).await?;
+        let entity = hooks.before_save(entity).await?;
+        let entity = self
+            .get_repository()
+            .upsert_with_condition(entity, condition)
+            .await?;
+        hooks.after_save(&entity).await?;
+
+        Ok(entity)
     }
     async fn delete(&self, entity: E) -> Result<DeleteItemOutput, DynamoRepositoryError> {
-        self.get_repository().delete(entity).await
+        let hooks = self.get_hooks();
+        let deleted = hooks.copy(&entity)?;
+        let output = self.get_repository().delete(entity).await?;
+
+        if let Some(deleted) = deleted {
+            hooks.after_delete(&deleted).await?;
+        }
+
+        Ok(output)
+    }
+    async fn delete_with_condition(
+        &self,
+        entity: E,
+        condition: Expression,
+    ) -> Result<DeleteItemOutput, DynamoRepositoryError> {
+        let hooks = self.get_hooks();
+        let deleted = hooks.copy(&entity)?;
+        let output = self
+            .get_repository()
+            .delete_with_condition(entity, condition)
+            .await?;
+
+        if let Some(deleted) = deleted {
+            hooks.after_delete(&deleted).await?;
+        }
+
+        Ok(output)
+    }
+    async fn update<Index: RepositoryIndex>(
+        &self,
+        index: Index,
+        update: Update,
+    ) -> Result<E, DynamoRepositoryError> {
+        let entity = self.get_repository().update(index, update).await?;
+        self.get_hooks().after_save(&entity).await?;
+
+        Ok(entity)
+    }
+    async fn update_with_condition<Index: RepositoryIndex>(
+        &self,
+        index: Index,
+        update: Update,
+        condition: Expression,
+    ) -> Result<E, DynamoRepositoryError> {
+        let entity = self
+            .get_repository()
+            .update_with_condition(index, update, condition)
+            .await?;
+        self.get_hooks().after_save(&entity).await?;
+
+        Ok(entity)
     }
     async fn find<Index: RepositoryIndex>(
         &self,
         index: Index,
     ) -> Result<Option<E>, DynamoRepositoryError> {
-        self.get_repository().find(index).await
+        match self.get_repository().find(index).await? {
+            Some(entity) => Ok(Some(self.get_hooks().after_load(entity).await?)),
+            None => Ok(None),
+        }
+    }
+    async fn find_view<V: View<Entity = E>, Index: RepositoryIndex>(
+        &self,
+        index: Index,
+    ) -> Result<Option<V>, DynamoRepositoryError> {
+        self.get_repository().find_view(index).await
+    }
+    async fn batch_get<Index: RepositoryIndex>(
+        &self,
+        indexes: Vec<Index>,
+    ) -> Result<Vec<E>, DynamoRepositoryError> {
+        let entities = self.get_repository().batch_get(indexes).await?;
+
+        self.get_hooks().after_load_all(entities).await
+    }
+    async fn batch_write(
+        &self,
+        operations: Vec<BatchWriteOperation<E>>,
+    ) -> Result<(), DynamoRepositoryError> {
+        let hooks = self.get_hooks();
+        let mut prepared = Vec::with_capacity(operations.len());
+
+        for operation in operations {
+            prepared.push(match operation {
+                BatchWriteOperation::Put(entity) => {
+                    hooks.validate(&entity).await?;
+                    BatchWriteOperation::Put(hooks.before_save(entity).await?)
+                }
+                delete => delete,
+            });
+        }
+
+        self.get_repository().batch_write(prepared).await
     }
     async fn get<Index: RepositoryIndex>(&self, index: Index) -> Result<E, DynamoRepositoryError> {
-        println!("Hoi hij komt hier");
-        self.get_repository().get(index).await
+        self.find(index)
+            .await?
+            .ok_or(DynamoRepositoryError::ItemNotFoundError)
     }
 
     async fn query<Index: RepositoryIndex>(
         &self,
         query_data: QueryData<Index>,
     ) -> Result<QueryResult<E>, DynamoRepositoryError> {
-        self.get_repository().query(query_data).await
+        let result = self.get_repository().query(query_data).await?;
+
+        Ok(QueryResult {
+            items: self.get_hooks().after_load_all(result.items).await?,
+            last_evaluated_key: result.last_evaluated_key,
+        })
     }
 
+    async fn query_view<V: View<Entity = E>, Index: RepositoryIndex>(
+        &self,
+        query_data: QueryData<Index>,
+    ) -> Result<QueryResult<V>, DynamoRepositoryError> {
+        self.get_repository().query_view(query_data).await
+    }
+
+    async fn query_collection<T: EntityEnum, Index: RepositoryIndex>(
+        &self,
+        query_data: QueryData<Index>,
+    ) -> Result<QueryResult<T>, DynamoRepositoryError> {
+        self.get_repository().query_collection(query_data).await
+    }
+
+    /// Reads a single page, continuing from a cursor of a previous page of the same index
+    async fn query_page<Index: RepositoryIndex>(
+        &self,
+        codec: &CursorCodec,
+        query_data: QueryData<Index>,
+        cursor: Option<&str>,
+    ) -> Result<Page<E>, DynamoRepositoryError> {
+        let index = query_data.get_index().clone();
+        let last_evaluated_key = cursor
+            .map(|cursor| codec.decode(&index, cursor))
+            .transpose()?;
+
+        let result = self
+            .query(query_data.with_last_evaluated_key(last_evaluated_key))
+            .await?;
+
+        Ok(codec.page(&index, result)?)
+    }
+
+    /// Reads a single page of views, see [`CrudService::query_page`]
+    async fn query_view_page<V: View<Entity = E>, Index: RepositoryIndex>(
+        &self,
+        codec: &CursorCodec,
+        query_data: QueryData<Index>,
+        cursor: Option<&str>,
+    ) -> Result<Page<V>, DynamoRepositoryError> {
+        let index = query_data.get_index().clone();
+        let last_evaluated_key = cursor
+            .map(|cursor| codec.decode(&index, cursor))
+            .transpose()?;
+
+        let result = self
+            .query_view(query_data.with_last_evaluated_key(last_evaluated_key))
+            .await?;
+
+        Ok(codec.page(&index, result)?)
+    }
+
+    fn query_stream<'a, Index: RepositoryIndex + 'a>(
+        &'a self,
+        query_data: QueryData<Index>,
+    ) -> QueryStream<'a, E>
+    where
+        Self: Sync,
+    {
+        let start_key = query_data.last_evaluated_key.clone();
+        let page_size = query_data.limit;
+
+        QueryStream::new(start_key, page_size, move |last_evaluated_key, limit| {
+            let mut query_data = query_data.clone().with_last_evaluated_key(last_evaluated_key);
+            query_data.limit = limit;
+
+            self.query(query_data)
+        })
+    }
+
+    async fn scan(&self, scan_data: ScanData) -> Result<QueryResult<E>, DynamoRepositoryError> {
+        let result = self.get_repository().scan(scan_data).await?;
+
+        Ok(QueryResult {
+            items: self.get_hooks().after_load_all(result.items).await?,
+            last_evaluated_key: result.last_evaluated_key,
+        })
+    }
+
+    fn scan_stream(&self, scan_data: ScanData) -> QueryStream<'_, E>
+    where
+        Self: Sync,
+    {
+        let start_key = scan_data.last_evaluated_key.clone();
+        let page_size = scan_data.limit;
+
+        QueryStream::new(start_key, page_size, move |last_evaluated_key, limit| {
+            let mut scan_data = scan_data.clone().with_last_evaluated_key(last_evaluated_key);
+            scan_data.limit = limit;
+
+            self.scan(scan_data)
+        })
+    }
+
+    fn parallel_scan(
+        &self,
+        scan_data: ScanData,
+        segments: i32,
+    ) -> BoxStream<'_, Result<E, DynamoRepositoryError>>
+    where
+        Self: Sync,
+    {
+        let hooks = self.get_hooks();
+
+        self.get_repository()
+            .parallel_scan(scan_data, segments)
+            .and_then(move |entity| {
+                let hooks = hooks.clone();
+                async move { hooks.after_load(entity).await }
+            })
+            .boxed()
+    }
+
+    /// Buffers every item of the partition, prefer [`CrudService::query_stream`] for partitions
+    /// that can grow large
     async fn query_all<Index: RepositoryIndex>(
         &self,
         index: Index,
-    ) -> Result<Vec<E>, DynamoRepositoryError> {
-        // Keep querying until result of QueryResult.last_evaluated_key is None
-        let mut items = Vec::new();
-        let mut last_evaluated_key = None;
-        loop {
-            let result = self
-                .get_repository()
-                .query(QueryData::new(index.clone(), last_evaluated_key))
-                .await?;
-
-            items.extend(result.items);
-            last_evaluated_key = result.last_evaluated_key;
-            if last_evaluated_key.is_none() {
-                break;
-            }
-        }
-
-        Ok(items)
+    ) -> Result<Vec<E>, DynamoRepositoryError>
+    where
+        Self: Sync,
+    {
+        self.query_stream(QueryData::new(index, None))
+            .try_collect()
+            .await
     }
 }
 //
@@ -98,10 +98,8 @@ impl BertServiceRunner {
                     let embeddings = self.encode_string(&formatted_note);
 
                     let encoded_note = NoteEntity {
-                        id: note.id,
-                        title: note.title,
-                        body: note.body,
                         encoded: Some(embeddings.get(0).unwrap().to_owned()),
+                        ..note
                     };
 
                     response
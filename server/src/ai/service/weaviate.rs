@@ -66,17 +66,19 @@ pub struct ExploreQueryResult {
 
 impl ExploreQueryResult {
     pub async fn get_notes(&self, notes_service: &NotesService) -> anyhow::Result<Vec<NoteEntity>> {
-        let mut result = Vec::new();
+        let mut ids = Vec::new();
 
         for item in &self.data.Explore {
             let splitted = &item.beacon.split("/").collect::<Vec<&str>>();
             let input = splitted.get(splitted.len() - 1).expect("No uuid found");
-            let id = Uuid::parse_str(input).unwrap();
-
-            let note_entity = notes_service.find_by_id(id).await.unwrap().expect("Weaviate returned non existing note!");
-            result.push(note_entity);
+            ids.push(Uuid::parse_str(input)?);
         }
 
+        let mut result = notes_service.find_by_ids(ids.clone()).await?;
+
+        // BatchGetItem doesn't keep the order, so restore the ranking Weaviate returned
+        result.sort_by_key(|note| ids.iter().position(|id| *id == note.id));
+
         Ok(result)
     }
 }
@@ -178,14 +180,14 @@ impl WeaviateService {
         Ok(())
     }
 
-    pub async fn update_note(
+    /// Replaces the copy of the note, or inserts it when there is none yet. Objects can't be
+    /// replaced together with their vector, so the old copy is deleted first.
+    pub async fn upsert_note(
         &self,
         note: &NoteEntity,
     ) -> Result<(), WeaviateServiceError> {
-        match self.delete_note(note).await {
-            Ok(_) => println!("Successfully deleted item for updating {:?}", note.id),
-            Err(_) => println!("Coudln't delete item for updating {:?}", note.id)
-        };
+        // Fails when the note was never indexed, which is fine as it gets inserted below
+        let _ = self.delete_note(note).await;
 
         self.insert_note(note).await?;
 
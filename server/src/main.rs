@@ -8,13 +8,18 @@ use dotenvy::dotenv;
 use env_logger::Env;
 use std::env;
 use std::path::PathBuf;
+use std::sync::Arc;
+use uuid::Uuid;
 
 use orm::prelude::*;
+use orm::server::graphql::graphql_resource;
 use crate::ai::service::chatgpt::ChatGptService;
 use crate::ai::service::encoder::SentenceEncoderService;
 use crate::ai::service::weaviate::WeaviateService;
 
-use crate::notes::repository::DynamoNotesRepository;
+use crate::notes::entities::NotePartitionKey;
+use crate::notes::repository::{backfill_timestamps, DynamoNotesRepository};
+use crate::notes::service::hooks::{NoteIndexing, NoteValidation};
 use crate::notes::service::NotesService;
 
 mod ai;
@@ -36,11 +41,42 @@ async fn main() -> Result<(), DynamoRepositoryError> {
     let config = load_from_env().await;
     let client = Client::new(&config);
 
-    let repository = DynamoNotesRepository::new(client);
-    let notes_service = NotesService::new(repository);
+    let repository = DynamoNotesRepository::new(client).cached();
+
+    println!("Ensuring the notes table exists...");
+
+    repository.ensure_table().await?;
+
+    let backfilled = backfill_timestamps(&repository).await?;
+
+    if backfilled > 0 {
+        log::info!("Dated {} notes stored before their timestamps were recorded", backfilled);
+    }
+
+    let cursor_secret = env::var("CURSOR_SECRET").unwrap_or_else(|_| {
+        log::warn!("No CURSOR_SECRET set, pagination cursors won't survive a restart");
+
+        Uuid::new_v4().to_string()
+    });
     let weaviate_service = WeaviateService::new().await.unwrap();
     let ai_service = SentenceEncoderService::new();
+    let notes_service = NotesService::new(repository, CursorCodec::new(cursor_secret.clone())).with_hooks(
+        Hooks::new()
+            .register(NoteValidation)
+            .register(NoteIndexing::new(ai_service.clone(), weaviate_service.clone())),
+    );
     let chatgpt_service = ChatGptService::new();
+    let graphql_schema = Arc::new(
+        CrudSchemaBuilder::new()
+            .register(
+                notes_service.clone(),
+                "notes",
+                NotePartitionKey::find_all(),
+                CursorCodec::new(cursor_secret),
+            )
+            .build()
+            .unwrap(),
+    );
 
     let path: PathBuf = env::var("FRONTEND_LOCATION")
         .unwrap_or_else(|_| "static".to_string())
@@ -82,7 +118,12 @@ async fn main() -> Result<(), DynamoRepositoryError> {
             .app_data(actix_web::web::Data::new(chatgpt_service.clone()))
             .wrap(Logger::default())
             .wrap(Cors::permissive())
-            .service(scope("/api").service(notes::routes::get_routes()).service(ai::routes::get_routes()));
+            .service(
+                scope("/api")
+                    .service(graphql_resource("/graphql", graphql_schema.clone()))
+                    .service(notes::routes::get_routes())
+                    .service(ai::routes::get_routes()),
+            );
 
         if let Some(path) = &frontend_path {
             app = app.service(Files::new("/", path).index_file("index.html"));
@@ -112,8 +153,13 @@ mod test {
     use crate::ai::service::encoder::SentenceEncoderService;
     use crate::ai::service::weaviate::WeaviateService;
     use crate::notes::entities::NoteEntity;
+    use crate::notes::models::{NewNoteDTO, NoteSort, SortOrder};
     use crate::notes::repository::DynamoNotesRepository;
+    use crate::notes::service::hooks::NoteValidation;
     use crate::notes::service::NotesService;
+    use orm::prelude::{
+        CrudService, CursorCodec, DynamoRepositoryError, Hooks, InMemoryRepository,
+    };
 
     // Create test for updating weaviate object
     #[tokio::test]
@@ -124,6 +170,9 @@ mod test {
             title: "title".to_string(),
             body: "content".to_string(),
             encoded: None,
+            version: 0,
+            created_at: Default::default(),
+            updated_at: Default::default(),
         };
 
         // Create a new weaviate service
@@ -154,8 +203,8 @@ mod test {
         let encoding_service = SentenceEncoderService::new();
         let weaviate_service = WeaviateService::new().await.unwrap();
         let chatgpt_service = ChatGptService::new();
-        let dynamo_repository = DynamoNotesRepository::new(client);
-        let notes_service = NotesService::new(dynamo_repository);
+        let dynamo_repository = DynamoNotesRepository::new(client).cached();
+        let notes_service = NotesService::new(dynamo_repository, CursorCodec::new("test"));
 
         let question = encoding_service.encode_string(question.to_string()).await;
 
@@ -169,4 +218,76 @@ mod test {
 
         println!("Result: {:?}", response_message);
     }
+
+    #[tokio::test]
+    async fn test_notes_offline() {
+        let notes_service =
+            NotesService::new(InMemoryRepository::new("notes"), CursorCodec::new("test"));
+
+        for title in ["first", "second", "third"] {
+            notes_service
+                .create(NoteEntity {
+                    id: Uuid::new_v4(),
+                    title: title.to_string(),
+                    body: "content".to_string(),
+                    encoded: None,
+                    version: 0,
+                    created_at: Default::default(),
+                    updated_at: Default::default(),
+                })
+                .await
+                .unwrap();
+        }
+
+        let page = notes_service.find_page(None, 2, None, SortOrder::Asc).await.unwrap();
+        let next_page = notes_service
+            .find_page(page.cursor.as_deref(), 2, None, SortOrder::Asc)
+            .await
+            .unwrap();
+        let newest = notes_service
+            .find_page(None, 3, Some(NoteSort::CreatedAt), SortOrder::Desc)
+            .await
+            .unwrap();
+
+        assert_eq!(page.items.len(), 2);
+        assert_eq!(next_page.items.len(), 1);
+        assert!(next_page.cursor.is_none());
+        assert_eq!(newest.items[0].title, "third");
+
+        // Saving a note loaded before someone else saved it conflicts
+        let note = notes_service.find_by_id(page.items[0].id).await.unwrap().unwrap();
+
+        notes_service.upsert(note.clone()).await.unwrap();
+
+        assert!(matches!(
+            notes_service.upsert(note).await,
+            Err(DynamoRepositoryError::VersionConflict { expected: 1, found: Some(2) })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_note_validation() {
+        let notes_service =
+            NotesService::new(InMemoryRepository::new("notes"), CursorCodec::new("test"))
+                .with_hooks(Hooks::new().register(NoteValidation));
+
+        let note = notes_service
+            .create_note(&NewNoteDTO {
+                title: "  title ".to_string(),
+                body: "content".to_string(),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(note.title, "title");
+        assert!(matches!(
+            notes_service
+                .create_note(&NewNoteDTO {
+                    title: " ".to_string(),
+                    body: "content".to_string(),
+                })
+                .await,
+            Err(DynamoRepositoryError::ValidationFailed(_))
+        ));
+    }
 }
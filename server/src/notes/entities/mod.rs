@@ -1,4 +1,5 @@
 use apistos::ApiComponent;
+use chrono::{DateTime, Utc};
 use rust_bert::pipelines::sentence_embeddings::Embedding;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -6,36 +7,24 @@ use uuid::Uuid;
 
 use orm::prelude::*;
 
-#[derive(Debug, Clone, Serialize, Deserialize, ApiComponent, JsonSchema)]
+#[derive(Debug, Clone, Serialize, Deserialize, ApiComponent, JsonSchema, Entity)]
+#[entity(pk = "NOTE", sk = "NOTE_ID#{id}")]
+// Lists notes by date. Notes have no tag or owner yet, so the indexes listing them by those
+// are deferred until the fields exist.
+#[gsi(name = "gsi1", pk = "NOTE", sk = "CREATED_AT#{created_at}")]
+#[gsi(name = "gsi2", pk = "NOTE", sk = "UPDATED_AT#{updated_at}")]
 pub struct NoteEntity {
     pub id: Uuid,
     pub title: String,
     pub body: String,
     pub encoded: Option<Embedding>,
-    // created_at: NaiveDateTime,
-}
-
-#[derive(Debug, Clone, Serialize)]
-pub struct NoteIndex {}
-
-#[derive(Debug, Clone, Serialize)]
-pub struct NotePrimaryKey {
-    pub pk: String,
-    pub sk: String,
-}
-
-impl Entity for NoteEntity {
-    type PrimaryKey = NotePrimaryKey;
-    type IndexFields = NoteIndex;
-
-    fn get_primary_key(&self) -> Self::PrimaryKey {
-        NotePrimaryKey {
-            pk: "NOTE".to_string(),
-            sk: format!("NOTE_ID#{}", self.id),
-        }
-    }
-
-    fn get_index_fields(&self) -> Self::IndexFields {
-        NoteIndex {}
-    }
+    #[entity(version)]
+    #[serde(default)]
+    pub version: u64,
+    #[entity(created_at)]
+    #[serde(default)]
+    pub created_at: DateTime<Utc>,
+    #[entity(updated_at)]
+    #[serde(default)]
+    pub updated_at: DateTime<Utc>,
 }
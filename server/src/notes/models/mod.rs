@@ -1,7 +1,9 @@
 use apistos::ApiComponent;
+use chrono::{DateTime, Utc};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
+use orm::prelude::View;
 use crate::notes::entities::NoteEntity;
 
 #[derive(Debug, Clone, Deserialize, ApiComponent, JsonSchema)]
@@ -17,15 +19,57 @@ impl From<NewNoteDTO> for NoteEntity {
             title: new_note.title,
             body: new_note.body,
             encoded: None,
+            version: 0,
+            created_at: DateTime::default(),
+            updated_at: DateTime::default(),
         }
     }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize, ApiComponent, JsonSchema)]
+/// Timestamp to list notes by. Sorted lists read the notes from an index, notes stored before
+/// their timestamps were recorded are dated when the server starts.
+#[derive(Debug, Clone, Copy, Deserialize, ApiComponent, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteSort {
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, ApiComponent, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Clone, Deserialize, ApiComponent, JsonSchema)]
+pub struct NotesPageQuery {
+    /// Cursor of the previous page, as returned with it
+    pub cursor: Option<String>,
+    pub limit: Option<i32>,
+    /// Lists the notes in no particular order when left out
+    pub sort: Option<NoteSort>,
+    #[serde(default)]
+    pub order: SortOrder,
+}
+
+/// Also read as a view of `NoteEntity`, so listing notes doesn't read their embeddings.
+///
+/// Clients updating a note send its `version` to detect concurrent writes, a missing one only
+/// matches notes stored before they were versioned. The timestamps are set by the server.
+#[derive(Debug, Clone, Deserialize, Serialize, ApiComponent, JsonSchema, View)]
+#[view(entity = NoteEntity)]
 pub struct NoteDTO {
     pub id: Uuid,
     pub title: String,
     pub body: String,
+    #[serde(default)]
+    pub version: u64,
+    #[serde(default)]
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub updated_at: DateTime<Utc>,
 }
 
 impl From<NoteEntity> for NoteDTO {
@@ -34,6 +78,9 @@ impl From<NoteEntity> for NoteDTO {
             id: note.id,
             title: note.title,
             body: note.body,
+            version: note.version,
+            created_at: note.created_at,
+            updated_at: note.updated_at,
         }
     }
 }
@@ -45,6 +92,9 @@ impl From<NoteDTO> for NoteEntity {
             title: note.title,
             body: note.body,
             encoded: None,
+            version: note.version,
+            created_at: note.created_at,
+            updated_at: note.updated_at,
         }
     }
 }
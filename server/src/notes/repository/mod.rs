@@ -1,39 +1,35 @@
+use std::time::Duration;
+
 use aws_sdk_dynamodb::Client;
-use serde::Serialize;
-use uuid::Uuid;
+use chrono::Utc;
 
-use orm::prelude::{DynamoRepository, RepositoryIndex};
+use orm::prelude::{
+    attribute, CachedRepository, DynamoRepository, DynamoRepositoryError, LruCache, ScanData,
+};
 
 use crate::notes::entities::NoteEntity;
 
 const TABLE_NAME: &str = "notes";
+const CACHE_CAPACITY: usize = 1_000;
+const CACHE_TTL: Duration = Duration::from_secs(60);
+
+pub type CachedNotesRepository = CachedRepository<NoteEntity, DynamoNotesRepository>;
 
 #[derive(Clone)]
 pub struct DynamoNotesRepository {
     client: Client,
 }
 
-#[derive(Debug, Clone, Serialize)]
-pub struct NotePrimaryIndex {
-    pk: String,
-    sk: String,
-}
-
-impl NotePrimaryIndex {
-    pub fn find_by_id(uuid: Uuid) -> Self {
-        Self {
-            pk: "NOTE".to_string(),
-            sk: format!("NOTE_ID#{}", uuid),
-        }
-    }
-}
-
-impl RepositoryIndex for NotePrimaryIndex {}
-
 impl DynamoNotesRepository {
     pub fn new(client: Client) -> Self {
         Self { client }
     }
+
+    /// Serves note lookups from an in-process cache, notes written by other instances show up
+    /// within a minute
+    pub fn cached(self) -> CachedNotesRepository {
+        CachedRepository::new(self, LruCache::new(CACHE_CAPACITY).with_ttl(CACHE_TTL))
+    }
 }
 
 impl DynamoRepository<NoteEntity> for DynamoNotesRepository {
@@ -45,3 +41,42 @@ impl DynamoRepository<NoteEntity> for DynamoNotesRepository {
         &self.client
     }
 }
+
+/// Dates the notes stored before their timestamps were recorded, so the indexes listing notes by
+/// date hold them too, and returns how many were dated. Their creation time is unknown, they're
+/// dated at the backfill.
+pub async fn backfill_timestamps<R>(repository: &R) -> Result<usize, DynamoRepositoryError>
+where
+    R: DynamoRepository<NoteEntity>,
+{
+    let mut scan_data = ScanData::new().with_filter(attribute("created_at").not_exists());
+    let mut backfilled = 0;
+
+    loop {
+        let page = repository.scan(scan_data.clone()).await?;
+
+        for mut note in page.items {
+            note.created_at = Utc::now();
+
+            // Notes written in the meantime, or dated by another instance, are left as they are
+            match repository
+                .upsert_with_condition(note, attribute("created_at").not_exists())
+                .await
+            {
+                Ok(_) => backfilled += 1,
+                Err(
+                    DynamoRepositoryError::ConditionFailed { .. }
+                    | DynamoRepositoryError::VersionConflict { .. },
+                ) => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        match page.last_evaluated_key {
+            Some(last_evaluated_key) => {
+                scan_data = scan_data.with_last_evaluated_key(Some(last_evaluated_key))
+            }
+            None => return Ok(backfilled),
+        }
+    }
+}
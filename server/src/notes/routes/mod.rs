@@ -1,16 +1,13 @@
-use actix_web::web::{Data, Json, Path};
+use actix_web::web::{Data, Json, Path, Query};
 use actix_web::{delete, get, post, put};
 use apistos::api_operation;
 use uuid::Uuid;
 use anyhow::{anyhow, Result};
 
-use orm::prelude::{CrudService, DynamoRepositoryError};
-use orm::server::ActixAnyhow;
-use crate::ai::service::encoder::SentenceEncoderService;
-use crate::ai::service::weaviate::WeaviateService;
+use orm::prelude::{CrudService, DynamoRepositoryError, Page};
 use crate::helpers::{Truncatable};
 
-use crate::notes::models::{NewNoteDTO, NoteDTO};
+use crate::notes::models::{NewNoteDTO, NoteDTO, NotesPageQuery};
 
 use crate::notes::service::NotesService;
 
@@ -23,53 +20,61 @@ pub fn get_routes() -> actix_web::Scope {
         .service(delete_note_by_id)
 }
 
-// Actix route for retrieving all notes
-// #[api_operation(summary = "List all notes")]
+const DEFAULT_PAGE_SIZE: i32 = 50;
+const MAX_PAGE_SIZE: i32 = 100;
+
+// Actix route for retrieving a page of notes, pass the returned cursor to get the next one
+// #[api_operation(summary = "List notes")]
 #[get("")]
 async fn get_notes(
+    query: Query<NotesPageQuery>,
     notes_service: Data<NotesService>,
-) -> Result<Json<Vec<NoteDTO>>, DynamoRepositoryError> {
-    Ok(Json(notes_service.find_all().await?.into_iter().map(|note| {
-        let mut note_dto: NoteDTO = note.into();
-
-        note_dto.body = note_dto.body.truncate_with_dots(100);
+) -> Result<Json<Page<NoteDTO>>, DynamoRepositoryError> {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let page = notes_service
+        .find_page(query.cursor.as_deref(), limit, query.sort, query.order)
+        .await?;
 
-        note_dto
-    }).collect()))
+    Ok(Json(page.map(|note| NoteDTO {
+        body: note.body.truncate_with_dots(100),
+        ..note
+    })))
 }
 
 #[get("/{id}")]
 async fn get_note_by_id(
     path: Path<Uuid>,
     notes_service: Data<NotesService>,
-) -> Json<Option<NoteDTO>> {
-    Json(notes_service.find_by_id(path.into_inner()).await.unwrap().map(|note| note.into()))
+) -> Result<Json<NoteDTO>, DynamoRepositoryError> {
+    let note = notes_service
+        .find_by_id(path.into_inner())
+        .await?
+        .ok_or(DynamoRepositoryError::ItemNotFoundError)?;
+
+    Ok(Json(note.into()))
 }
 
 #[delete("/{id}")]
 async fn delete_note_by_id(
     path: Path<Uuid>,
     notes_service: Data<NotesService>,
-    weaviate_service: Data<WeaviateService>,
-) -> ActixAnyhow<Json<NoteDTO>> {
-    let note = notes_service.find_by_id(path.into_inner()).await.map_err(anyhow::Error::from)?;
+) -> Result<Json<NoteDTO>, DynamoRepositoryError> {
+    let note = notes_service
+        .find_by_id(path.into_inner())
+        .await?
+        .ok_or(DynamoRepositoryError::ItemNotFoundError)?;
 
-    if let Some(note) = &note {
-        notes_service.delete(note.clone()).await.map_err(anyhow::Error::from)?;
-        weaviate_service.delete_note(note).await.map_err(anyhow::Error::from)?;
-    }
+    notes_service.delete(note.clone()).await?;
 
-    Ok(Json(note.map(|note| note.into()).unwrap()))
+    Ok(Json(note.into()))
 }
 
 #[post("")]
 async fn create_note(
     note: Json<NewNoteDTO>,
     notes_service: Data<NotesService>,
-    ai_service: Data<SentenceEncoderService>,
-    weaviate_service: Data<WeaviateService>,
-) -> ActixAnyhow<Json<NoteDTO>> {
-    Ok(Json(notes_service.create_note(&note, ai_service, weaviate_service).await?.into()))
+) -> Result<Json<NoteDTO>, DynamoRepositoryError> {
+    Ok(Json(notes_service.create_note(&note).await?.into()))
 }
 
 #[put("/{id}")]
@@ -77,12 +82,11 @@ async fn update_note(
     path: Path<Uuid>,
     note: Json<NoteDTO>,
     notes_service: Data<NotesService>,
-    ai_service: Data<SentenceEncoderService>,
-    weaviate_service: Data<WeaviateService>,
-) -> ActixAnyhow<Json<NoteDTO>> {
+) -> Result<Json<NoteDTO>, DynamoRepositoryError> {
     Ok(Json(
         notes_service
-            .update_note(path.into_inner(), &note.into_inner().into(), ai_service, weaviate_service)
-            .await?.into(),
+            .update_note(path.into_inner(), &note.into_inner().into())
+            .await?
+            .into(),
     ))
 }
@@ -0,0 +1,73 @@
+use orm::prelude::{DynamoRepositoryError, EntityHook, ValidationErrors};
+
+use crate::ai::service::encoder::SentenceEncoderService;
+use crate::ai::service::weaviate::WeaviateService;
+use crate::notes::entities::NoteEntity;
+
+const MAX_TITLE_LENGTH: usize = 200;
+
+/// Rejects notes without a title, and trims the title before saving it
+pub struct NoteValidation;
+
+#[async_trait::async_trait]
+impl EntityHook<NoteEntity> for NoteValidation {
+    async fn validate(&self, note: &NoteEntity, errors: &mut ValidationErrors) {
+        let title = note.title.trim();
+
+        if title.is_empty() {
+            errors.add("title", "can't be empty");
+        } else if title.chars().count() > MAX_TITLE_LENGTH {
+            errors.add("title", format!("can't be longer than {} characters", MAX_TITLE_LENGTH));
+        }
+    }
+
+    async fn before_save(&self, note: NoteEntity) -> Result<NoteEntity, DynamoRepositoryError> {
+        Ok(NoteEntity {
+            title: note.title.trim().to_string(),
+            ..note
+        })
+    }
+}
+
+/// Encodes every saved note and keeps its copy in Weaviate in sync, so questions can be answered
+/// from it.
+///
+/// The note is already written to DynamoDB when Weaviate is updated, so failing the request then
+/// would only make the client retry a write that succeeded. Indexing failures are logged instead,
+/// and the copy catches up the next time the note is saved.
+pub struct NoteIndexing {
+    ai_service: SentenceEncoderService,
+    weaviate_service: WeaviateService,
+}
+
+impl NoteIndexing {
+    pub fn new(ai_service: SentenceEncoderService, weaviate_service: WeaviateService) -> Self {
+        Self {
+            ai_service,
+            weaviate_service,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EntityHook<NoteEntity> for NoteIndexing {
+    async fn before_save(&self, note: NoteEntity) -> Result<NoteEntity, DynamoRepositoryError> {
+        Ok(self.ai_service.encode_note(note).await)
+    }
+
+    async fn after_save(&self, note: &NoteEntity) -> Result<(), DynamoRepositoryError> {
+        if let Err(err) = self.weaviate_service.upsert_note(note).await {
+            log::warn!("Couldn't index note {}: {}", note.id, err);
+        }
+
+        Ok(())
+    }
+
+    async fn after_delete(&self, note: &NoteEntity) -> Result<(), DynamoRepositoryError> {
+        if let Err(err) = self.weaviate_service.delete_note(note).await {
+            log::warn!("Couldn't remove note {} from the index: {}", note.id, err);
+        }
+
+        Ok(())
+    }
+}
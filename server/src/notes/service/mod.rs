@@ -1,111 +1,115 @@
-use actix_web::web::Data;
 use orm::prelude::{
-    CrudService, DynamoRepositoryError, LastEvaluatedKey, QueryData, QueryResult, RepositoryIndex,
+    CrudService, CursorCodec, DynamoRepository, DynamoRepositoryError, Hooks, Page, QueryData,
 };
 use uuid::Uuid;
-use crate::ai::service::encoder::SentenceEncoderService;
-use crate::ai::service::weaviate::WeaviateService;
 
-use crate::notes::entities::{NoteEntity};
-use crate::notes::models::NewNoteDTO;
-use crate::notes::repository::{DynamoNotesRepository, NotePrimaryIndex};
+use crate::notes::entities::{
+    NoteEntity, NoteGsi1Index, NoteGsi2Index, NotePartitionKey, NotePrimaryKey,
+};
+use crate::notes::models::{NewNoteDTO, NoteDTO, NoteSort, SortOrder};
+use crate::notes::repository::CachedNotesRepository;
+
+pub mod hooks;
 
+/// Generic over the repository so tests can run on an `InMemoryRepository`
 #[derive(Clone)]
-pub struct NotesService {
-    repository: DynamoNotesRepository,
+pub struct NotesService<R = CachedNotesRepository> {
+    repository: R,
+    cursors: CursorCodec,
+    hooks: Hooks<NoteEntity>,
 }
 
-impl CrudService<NoteEntity, DynamoNotesRepository> for NotesService {
-    fn get_repository(&self) -> &DynamoNotesRepository {
+impl<R: DynamoRepository<NoteEntity>> CrudService<NoteEntity, R> for NotesService<R> {
+    fn get_repository(&self) -> &R {
         &self.repository
     }
-}
 
-#[derive(Debug, Clone, serde::Serialize)]
-pub struct QueryNoteIndex {
-    pk: String,
+    fn get_hooks(&self) -> Hooks<NoteEntity> {
+        self.hooks.clone()
+    }
 }
 
-impl QueryNoteIndex {
-    pub fn find_all() -> Self {
+impl<R: DynamoRepository<NoteEntity>> NotesService<R> {
+    pub fn new(repository: R, cursors: CursorCodec) -> Self {
         Self {
-            pk: "NOTE".to_string(),
+            repository,
+            cursors,
+            hooks: Hooks::new(),
         }
     }
-}
 
-impl RepositoryIndex for QueryNoteIndex {}
-
-impl NotesService {
-    pub fn new(repository: DynamoNotesRepository) -> Self {
-        Self { repository }
+    /// Runs `hooks` around every write and read of a note, see [`hooks`]
+    pub fn with_hooks(mut self, hooks: Hooks<NoteEntity>) -> Self {
+        self.hooks = hooks;
+        self
     }
 
     pub async fn find_by_id(
         &self,
         uuid: Uuid,
     ) -> Result<Option<NoteEntity>, DynamoRepositoryError> {
-        self.find(NotePrimaryIndex::find_by_id(uuid)).await
+        self.find(NotePrimaryKey::find_by_id(uuid)).await
     }
 
-    pub async fn find_all_paged(
+    pub async fn find_by_ids(
         &self,
-        last_evaluated_key: Option<LastEvaluatedKey>,
-    ) -> Result<QueryResult<NoteEntity>, DynamoRepositoryError> {
-        self.query(QueryData::new(
-            QueryNoteIndex::find_all(),
-            last_evaluated_key,
-        ))
+        uuids: Vec<Uuid>,
+    ) -> Result<Vec<NoteEntity>, DynamoRepositoryError> {
+        self.batch_get(uuids.into_iter().map(NotePrimaryKey::find_by_id).collect())
             .await
     }
 
-    pub async fn find_all(&self) -> Result<Vec<NoteEntity>, DynamoRepositoryError> {
-        self.query_all(QueryNoteIndex::find_all()).await
-    }
-
-    pub async fn create_note(
+    pub async fn find_page(
         &self,
-        note: &NewNoteDTO,
-        ai_service: Data<SentenceEncoderService>,
-        weaviate_service: Data<WeaviateService>,
-    ) -> Result<NoteEntity, anyhow::Error> {
-        let note: NoteEntity = note.to_owned().into();
-        self.create(note.clone()).await?;
-
-        let note = ai_service.encode_note(note.clone()).await;
-
-        weaviate_service.insert_note(&note).await?;
-
-        self.upsert(note.clone()).await?;
+        cursor: Option<&str>,
+        limit: i32,
+        sort: Option<NoteSort>,
+        order: SortOrder,
+    ) -> Result<Page<NoteDTO>, DynamoRepositoryError> {
+        let ascending = matches!(order, SortOrder::Asc);
+
+        match sort {
+            None => {
+                let query = QueryData::new(NotePartitionKey::find_all(), None);
+
+                self.query_view_page(&self.cursors, query.with_limit(limit), cursor).await
+            }
+            Some(NoteSort::CreatedAt) => {
+                let query = QueryData::new(NoteGsi1Index::find_all(), None)
+                    .with_scan_index_forward(ascending);
+
+                self.query_view_page(&self.cursors, query.with_limit(limit), cursor).await
+            }
+            Some(NoteSort::UpdatedAt) => {
+                let query = QueryData::new(NoteGsi2Index::find_all(), None)
+                    .with_scan_index_forward(ascending);
+
+                self.query_view_page(&self.cursors, query.with_limit(limit), cursor).await
+            }
+        }
+    }
 
-        Ok(note)
+    pub async fn create_note(&self, note: &NewNoteDTO) -> Result<NoteEntity, DynamoRepositoryError> {
+        self.create(note.to_owned().into()).await
     }
 
     pub async fn update_note(
         &self,
         note_id: Uuid,
         note: &NoteEntity,
-        ai_service: Data<SentenceEncoderService>,
-        weaviate_service: Data<WeaviateService>,
-    ) -> Result<NoteEntity, anyhow::Error> {
-        // Check if note exists
-        self.find(NotePrimaryIndex::find_by_id(note_id))
+    ) -> Result<NoteEntity, DynamoRepositoryError> {
+        let stored = self
+            .find(NotePrimaryKey::find_by_id(note_id))
             .await?
             .ok_or(DynamoRepositoryError::ItemNotFoundError)?;
 
-        let entity = NoteEntity {
+        let note = NoteEntity {
             id: note_id,
+            created_at: stored.created_at,
             ..note.clone()
         };
 
-        self.upsert(entity.clone()).await?;
-
-        let note = ai_service.encode_note(entity.clone()).await;
-
-        weaviate_service.update_note(&note).await?;
-
-        self.upsert(note).await?;
-
-        Ok(entity)
+        // Fails with a version conflict when the note was edited since the client loaded it
+        self.upsert(note).await
     }
 }